@@ -0,0 +1,100 @@
+#!/usr/bin/env python3
+"""Builds receipt-12x24.bin, the bitmap font used for receipt previews.
+
+Renders DejaVu Sans Mono through cairo (libcairo and fontconfig must be
+installed) into 12x24 dot cells, the size of ESC/POS font A, for ASCII and
+every character of the code pages in src/printer/codepage.rs.
+
+Each record is the code point (u32, little endian) followed by 24 rows of
+two bytes, leftmost dot in the most significant bit. Records are sorted by
+code point.
+
+    python3 fonts/build_receipt_font.py
+"""
+
+import ctypes
+import re
+import struct
+from pathlib import Path
+
+WIDTH, HEIGHT = 12, 24
+FONT = b"DejaVu Sans Mono"
+SIZE = 20.0
+BASELINE = 19
+
+ROOT = Path(__file__).resolve().parent.parent
+CODEPAGE_RS = ROOT / "src" / "printer" / "codepage.rs"
+OUT = ROOT / "fonts" / "receipt-12x24.bin"
+
+cairo = ctypes.CDLL("libcairo.so.2")
+cairo.cairo_image_surface_create.restype = ctypes.c_void_p
+cairo.cairo_create.restype = ctypes.c_void_p
+cairo.cairo_create.argtypes = [ctypes.c_void_p]
+cairo.cairo_image_surface_get_data.restype = ctypes.POINTER(ctypes.c_ubyte)
+cairo.cairo_image_surface_get_data.argtypes = [ctypes.c_void_p]
+cairo.cairo_image_surface_get_stride.argtypes = [ctypes.c_void_p]
+cairo.cairo_font_options_create.restype = ctypes.c_void_p
+for name in ["cairo_select_font_face", "cairo_set_font_size", "cairo_set_operator",
+             "cairo_paint", "cairo_move_to", "cairo_show_text", "cairo_set_font_options",
+             "cairo_surface_flush", "cairo_font_options_set_antialias",
+             "cairo_font_options_set_hint_style"]:
+    getattr(cairo, name).restype = None
+
+CAIRO_FORMAT_A8 = 2
+CAIRO_OPERATOR_CLEAR = 0
+CAIRO_OPERATOR_OVER = 2
+CAIRO_ANTIALIAS_NONE = 1
+CAIRO_HINT_STYLE_FULL = 4
+
+
+def characters():
+    source = CODEPAGE_RS.read_text(encoding="utf-8")
+    chars = {chr(c) for c in range(0x20, 0x7F)}
+    for table in re.findall(r'const CP\d+: &str = concat!\((.*?)\);', source, re.S):
+        for literal in re.findall(r'"([^"]*)"', table):
+            # Rust \u{...} escapes
+            chars.update(re.sub(r"\\u\{([0-9a-fA-F]+)\}", lambda m: chr(int(m[1], 16)), literal))
+    chars.discard("�")
+    return sorted(chars)
+
+
+def render(surface, cr, ch):
+    cairo.cairo_set_operator(cr, CAIRO_OPERATOR_CLEAR)
+    cairo.cairo_paint(cr)
+    cairo.cairo_set_operator(cr, CAIRO_OPERATOR_OVER)
+    cairo.cairo_move_to(cr, ctypes.c_double(0), ctypes.c_double(BASELINE))
+    cairo.cairo_show_text(cr, ch.encode("utf-8"))
+    cairo.cairo_surface_flush(ctypes.c_void_p(surface))
+    data = cairo.cairo_image_surface_get_data(surface)
+    stride = cairo.cairo_image_surface_get_stride(surface)
+    rows = []
+    for y in range(HEIGHT):
+        bits = 0
+        for x in range(WIDTH):
+            if data[y * stride + x] >= 128:
+                bits |= 0x8000 >> x
+        rows.append(bits)
+    return rows
+
+
+def main():
+    surface = cairo.cairo_image_surface_create(CAIRO_FORMAT_A8, WIDTH, HEIGHT)
+    cr = cairo.cairo_create(surface)
+    cairo.cairo_select_font_face(ctypes.c_void_p(cr), FONT, 0, 0)
+    cairo.cairo_set_font_size(ctypes.c_void_p(cr), ctypes.c_double(SIZE))
+    options = cairo.cairo_font_options_create()
+    cairo.cairo_font_options_set_antialias(ctypes.c_void_p(options), CAIRO_ANTIALIAS_NONE)
+    cairo.cairo_font_options_set_hint_style(ctypes.c_void_p(options), CAIRO_HINT_STYLE_FULL)
+    cairo.cairo_set_font_options(ctypes.c_void_p(cr), ctypes.c_void_p(options))
+
+    out = bytearray()
+    for ch in characters():
+        out += struct.pack("<I", ord(ch))
+        for row in render(surface, ctypes.c_void_p(cr), ch):
+            out += struct.pack(">H", row)
+    OUT.write_bytes(out)
+    print(f"{len(out) // (4 + 2 * HEIGHT)} glyphs -> {OUT.relative_to(ROOT)}")
+
+
+if __name__ == "__main__":
+    main()
@@ -1,92 +1,896 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::env;
-use std::fs;
+mod printer;
+
 use std::io::Write;
-use std::net::TcpStream;
-use std::path::PathBuf;
+use std::sync::Arc;
 use base64::{Engine as _, engine::general_purpose};
-use serde_json::Value;
+use printer::config::{
+    self, ConfigError, PrinterConfig, PrinterDefinition, PrinterLanguage, PrinterRole, Transport,
+};
+use printer::decode::{self, Inspection, Issue, Preflight};
+use printer::discovery::{self, DiscoveredPrinter, Ports, Subnet};
+use printer::document::Document;
+use printer::drawer::{self, DrawerEvent, DrawerLog, DrawerSettings};
+use printer::error::{PrintError, PrintErrorKind};
+use printer::escpos::EscPosBuilder;
+use printer::failover::{self, FailoverEvent, FailoverLog};
+use printer::history::{self, HistoryEntry, PrintHistory};
+use printer::ipp::{self, IppClient, IppJob, IppJobState};
+use printer::label::{self, Label};
+use printer::preview::{self, PreviewSource, ReceiptImage};
+use printer::reload::{self, ConfigStore};
+use printer::routing::{KitchenOrder, RoutingStore, RoutingTable, StationPrint, StationTicket};
+use printer::simulate::{SimulatedPrint, SimulatedPrinter};
+use printer::spool::{self, CancelToken, JobKind, PrintJob, Spool};
+use printer::status::{self, PrinterStatus, StatusBoard, StatusMode};
+use printer::templates::{self, KitchenTicketData, ReceiptData, ReportData};
+use printer::transport;
+use tauri::{AppHandle, Manager, State};
 
-#[derive(serde::Serialize, serde::Deserialize, Clone)]
-struct PrinterConfig {
-    host: String,
-    port: u16,
-    simulate: bool,
+#[tauri::command]
+fn list_printers(app: AppHandle) -> Vec<PrinterDefinition> {
+    current_config(&app).printers.clone()
 }
 
-impl Default for PrinterConfig {
-    fn default() -> Self {
-        PrinterConfig {
-            host: "127.0.0.1".to_string(),
-            port: 9100,
-            simulate: true,
-        }
+/// Returns the saved config from `~/.chefcloud/printer.json`, without the
+/// `PRINTER_*` environment overrides, or the defaults when there is none.
+#[tauri::command]
+fn get_printer_config() -> Result<PrinterConfig, ConfigError> {
+    let path = config::config_path().ok_or("Cannot locate the home directory")?;
+    Ok(config::read_config_file(&path)?.unwrap_or_default())
+}
+
+/// Validates and atomically writes `~/.chefcloud/printer.json`, then
+/// reloads it right away rather than waiting for the watcher.
+#[tauri::command]
+fn save_printer_config(
+    config: PrinterConfig,
+    app: AppHandle,
+) -> Result<PrinterConfig, ConfigError> {
+    let path = config::config_path().ok_or("Cannot locate the home directory")?;
+    config::write_config_file(&path, &config)?;
+    let reloaded = app.state::<Arc<ConfigStore>>().reload_if_changed();
+    report_reload(&app, reloaded.transpose());
+    Ok(config)
+}
+
+/// Connects to a printer entry, saved or not, and reads its status.
+#[tauri::command(async)]
+fn test_printer_connection(printer: PrinterDefinition) -> Result<String, ConfigError> {
+    let errors = printer.validate();
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+    if let Transport::Ipp { .. } = printer.transport {
+        let attributes = ipp_client(&printer)?
+            .get_printer_attributes()
+            .map_err(String::from)?;
+        return Ok(format!(
+            "Connected to {} ({})",
+            printer.transport,
+            attributes.make_and_model.as_deref().unwrap_or("IPP printer")
+        ));
     }
+
+    let mut stream = transport::open(&printer.transport, &printer.timeouts)
+        .map_err(String::from)?;
+    if let Transport::Lpd { .. } = printer.transport {
+        return Ok(format!("Connected to {} (LPD reports no status)", printer.transport));
+    }
+    Ok(match status::query(&mut stream) {
+        Ok(reported) if reported.is_ready() => format!("Connected to {}: ready", printer.transport),
+        Ok(_) => format!("Connected to {}: printer reports an error", printer.transport),
+        Err(_) => format!("Connected to {} (no status reply)", printer.transport),
+    })
 }
 
-fn load_printer_config() -> PrinterConfig {
-    // Priority 1: Environment variables
-    if let Ok(simulate) = env::var("PRINTER_SIMULATE") {
-        return PrinterConfig {
-            host: env::var("PRINTER_HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
-            port: env::var("PRINTER_PORT")
-                .ok()
-                .and_then(|p| p.parse().ok())
-                .unwrap_or(9100),
-            simulate: simulate == "true",
-        };
+/// Scans the terminal's subnet (or `subnet`, e.g. `192.168.1.0/24`) and mDNS
+/// for printers. Takes a few seconds, so it runs off the main thread.
+#[tauri::command(async)]
+fn discover_printers(subnet: Option<String>) -> Result<Vec<DiscoveredPrinter>, String> {
+    let subnet = match subnet {
+        Some(cidr) => Subnet::parse(&cidr)?,
+        None => discovery::local_subnet()?,
+    };
+    Ok(discovery::discover(subnet, Ports::STANDARD))
+}
+
+/// Prints raw ESC/POS and keeps it in the print history under `order_id`.
+#[tauri::command(async)]
+fn print_receipt(
+    base64_data: String,
+    printer_id: Option<String>,
+    order_id: Option<String>,
+    app: AppHandle,
+    spool: State<'_, Arc<Spool>>,
+) -> Result<String, PrintError> {
+    let config = current_config(&app);
+    let printer = config.printer(printer_id.as_deref())?;
+    if printer.language == PrinterLanguage::Star {
+        return Err(PrintError::new(
+            PrintErrorKind::Invalid,
+            format!(
+                "Printer '{}' is in Star Line Mode; send receipt data or a document instead",
+                printer.id
+            ),
+        ));
     }
+    
+    // Decode base64
+    let bytes = general_purpose::STANDARD
+        .decode(&base64_data)
+        .map_err(|e| format!("Failed to decode base64: {}", e))?;
 
-    // Priority 2: Config file ~/.chefcloud/printer.json
-    if let Some(home) = dirs::home_dir() {
-        let config_path: PathBuf = home.join(".chefcloud").join("printer.json");
-        if config_path.exists() {
-            if let Ok(content) = fs::read_to_string(&config_path) {
-                if let Ok(json) = serde_json::from_str::<Value>(&content) {
-                    return PrinterConfig {
-                        host: json["host"].as_str().unwrap_or("127.0.0.1").to_string(),
-                        port: json["port"].as_u64().unwrap_or(9100) as u16,
-                        simulate: json["simulate"].as_bool().unwrap_or(true),
-                    };
-                }
-            }
+    if config.preflight != Preflight::Off {
+        let issues = decode::lint(&decode::decode(&bytes), printer);
+        check_preflight(&app, config.preflight, printer, &issues)?;
+    }
+    dispatch_receipt(&app, &spool, &config, printer, JobKind::Raw, bytes, order_id)
+}
+
+/// Decodes raw ESC/POS and reports what it would do on a printer, including
+/// the issues the `print_receipt` pre-flight check would raise.
+#[tauri::command]
+fn inspect_print_bytes(
+    base64_data: String,
+    printer_id: Option<String>,
+    app: AppHandle,
+) -> Result<Inspection, String> {
+    let config = current_config(&app);
+    let printer = config.printer(printer_id.as_deref())?;
+    let bytes = general_purpose::STANDARD
+        .decode(&base64_data)
+        .map_err(|e| format!("Failed to decode base64: {}", e))?;
+    Ok(decode::inspect(&bytes, printer))
+}
+
+/// Renders a document, receipt or raw ESC/POS as a PNG at the printer's dot
+/// width. Without a printer id, the first receipt printer is used.
+#[tauri::command(async)]
+fn preview_receipt(
+    source: PreviewSource,
+    printer_id: Option<String>,
+    app: AppHandle,
+) -> Result<ReceiptImage, String> {
+    let config = current_config(&app);
+    let printer = config.printer_for(printer_id.as_deref(), PrinterRole::Receipt)?;
+    preview::render(&source, printer)
+}
+
+/// Rejects the job in `Reject` mode; in `Warn` mode logs the issues and
+/// emits `print-preflight-warning`.
+fn check_preflight(
+    app: &AppHandle,
+    mode: Preflight,
+    printer: &PrinterDefinition,
+    issues: &[Issue],
+) -> Result<(), PrintError> {
+    if issues.is_empty() {
+        return Ok(());
+    }
+    let summary = issues
+        .iter()
+        .map(|issue| format!("byte {}: {}", issue.offset, issue.message))
+        .collect::<Vec<_>>()
+        .join("; ");
+    match mode {
+        Preflight::Off => Ok(()),
+        Preflight::Warn => {
+            eprintln!("Pre-flight issues for printer '{}': {}", printer.id, summary);
+            let _ = app.emit_all(
+                "print-preflight-warning",
+                serde_json::json!({ "printerId": printer.id, "issues": issues }),
+            );
+            Ok(())
         }
+        Preflight::Reject => Err(PrintError::new(
+            PrintErrorKind::Invalid,
+            format!("Print job failed pre-flight check: {}", summary),
+        )),
     }
+}
+
+#[tauri::command(async)]
+fn print_document(
+    document: Document,
+    printer_id: Option<String>,
+    app: AppHandle,
+    spool: State<'_, Arc<Spool>>,
+) -> Result<String, PrintError> {
+    render_and_send(&app, &spool, printer_id, PrinterRole::Receipt, JobKind::Document, |_| document)
+}
+
+#[tauri::command(async)]
+fn print_receipt_data(
+    data: ReceiptData,
+    printer_id: Option<String>,
+    app: AppHandle,
+    spool: State<'_, Arc<Spool>>,
+) -> Result<String, PrintError> {
+    let config = current_config(&app);
+    let (printer, bytes) = render(&config, printer_id, PrinterRole::Receipt, |columns| {
+        templates::receipt(&data, columns)
+    })?;
+    let order_id = Some(data.order_number);
+    dispatch_receipt(&app, &spool, &config, printer, JobKind::Receipt, bytes, order_id)
+}
+
+#[tauri::command(async)]
+fn print_kitchen_ticket(
+    data: KitchenTicketData,
+    printer_id: Option<String>,
+    app: AppHandle,
+    spool: State<'_, Arc<Spool>>,
+) -> Result<String, PrintError> {
+    render_and_send(&app, &spool, printer_id, PrinterRole::Kitchen, JobKind::KitchenTicket, |columns| {
+        templates::kitchen_ticket(&data, columns)
+    })
+}
+
+/// Prints a label on a ZPL or TSPL label printer, by default the first
+/// printer with the `label` role.
+#[tauri::command(async)]
+fn print_label(
+    label: Label,
+    printer_id: Option<String>,
+    app: AppHandle,
+    spool: State<'_, Arc<Spool>>,
+) -> Result<String, PrintError> {
+    let config = current_config(&app);
+    let printer = config.printer_for(printer_id.as_deref(), PrinterRole::Label)?;
+    let bytes = label::render(&label, printer)?;
+    dispatch(&app, &spool, &config, printer, JobKind::Label, bytes)
+}
 
-    // Priority 3: Defaults
-    PrinterConfig::default()
+/// Splits an order into one kitchen ticket per station using the cached
+/// routing table, and prints each on its station's printer. A station whose
+/// printer fails does not hold up the others; its job stays queued.
+#[tauri::command(async)]
+fn print_kitchen_order(order: KitchenOrder, app: AppHandle) -> Vec<StationPrint> {
+    let spool = app.state::<Arc<Spool>>();
+    let tickets = app
+        .state::<RoutingStore>()
+        .get()
+        .split(&order, &current_config(&app));
+    tickets
+        .into_iter()
+        .map(|StationTicket { printer_id, missing_printer, ticket }| {
+            let result = render_and_send(
+                &app,
+                &spool,
+                printer_id.clone(),
+                PrinterRole::Kitchen,
+                JobKind::KitchenTicket,
+                |columns| templates::kitchen_ticket(&ticket, columns),
+            );
+            StationPrint {
+                station: ticket.station,
+                printer_id,
+                missing_printer,
+                message: result.as_ref().ok().cloned(),
+                error: result.err().map(String::from),
+            }
+        })
+        .collect()
 }
 
+/// Caches the station routing table sent by the server.
 #[tauri::command]
-fn print_receipt(base64_data: String) -> Result<String, String> {
-    let config = load_printer_config();
-    
-    // Decode base64
+fn sync_station_routes(
+    table: RoutingTable,
+    routes: State<'_, RoutingStore>,
+) -> Result<RoutingTable, String> {
+    routes.replace(table)
+}
+
+#[tauri::command]
+fn get_station_routes(routes: State<'_, RoutingStore>) -> RoutingTable {
+    routes.get()
+}
+
+#[tauri::command(async)]
+fn print_shift_report(
+    data: ReportData,
+    printer_id: Option<String>,
+    app: AppHandle,
+    spool: State<'_, Arc<Spool>>,
+) -> Result<String, PrintError> {
+    render_and_send(&app, &spool, printer_id, PrinterRole::Receipt, JobKind::ShiftReport, |columns| {
+        templates::shift_report(&data, columns)
+    })
+}
+
+#[tauri::command]
+fn list_print_jobs(spool: State<'_, Arc<Spool>>) -> Vec<PrintJob> {
+    spool.list()
+}
+
+#[tauri::command]
+fn retry_print_job(job_id: String, spool: State<'_, Arc<Spool>>) -> Result<PrintJob, String> {
+    spool.retry(&job_id)
+}
+
+#[tauri::command]
+fn cancel_print_job(
+    job_id: String,
+    spool: State<'_, Arc<Spool>>,
+    history: State<'_, PrintHistory>,
+) -> Result<PrintJob, String> {
+    let job = spool.cancel(&job_id)?;
+    history.finish(&job_id, &Err(PrintError::cancelled()));
+    Ok(job)
+}
+
+/// Sends a spooled job's bytes again as they are, e.g. a ticket that jammed.
+/// Customer copies of receipts go through `reprint_job` instead.
+#[tauri::command]
+fn reprint_print_job(job_id: String, spool: State<'_, Arc<Spool>>) -> Result<PrintJob, String> {
+    spool.reprint(&job_id)
+}
+
+/// Receipts in the print history, newest first.
+#[tauri::command]
+fn list_print_history(
+    order_id: Option<String>,
+    limit: Option<usize>,
+    history: State<'_, PrintHistory>,
+) -> Vec<HistoryEntry> {
+    history.list(order_id.as_deref(), limit.unwrap_or(50))
+}
+
+/// Prints a receipt from the print history again under a "COPY - REPRINT"
+/// banner, on the printer it first went to. The reprint is kept in the
+/// history with its operator and reason and counted on the original.
+#[tauri::command(async)]
+fn reprint_job(
+    job_id: String,
+    operator: String,
+    reason: Option<String>,
+    app: AppHandle,
+) -> Result<String, PrintError> {
+    if operator.trim().is_empty() {
+        return Err("An operator is required to reprint a receipt".into());
+    }
+    let history = app.state::<PrintHistory>();
+    let entry = history
+        .get(&job_id)
+        .ok_or_else(|| format!("No receipt in the print history for job '{}'", job_id))?;
+    let config = current_config(&app);
+    let printer = config.printer(Some(&entry.printer_id))?;
+    let original_id = entry.reprint_of.clone().unwrap_or_else(|| job_id.clone());
+    let payload = history.payload(&original_id)?;
+    let original = history.count_reprint(&job_id)?;
+    let bytes = history::mark_copy(printer, original.reprints, &payload);
+
+    let spool = app.state::<Arc<Spool>>();
+    let job = spool.submit(&printer.id, original.kind, bytes);
+    history.record(HistoryEntry {
+        reprint_of: Some(original.job_id.clone()),
+        operator: Some(operator),
+        reason,
+        ..HistoryEntry::new(&job, original.order_id)
+    });
+    send_spooled(&app, &spool, &config, printer, &job)
+}
+
+/// Lays out a document for the target printer's paper width and prints it.
+/// Without a printer id, the first printer with `role` is used.
+fn render_and_send(
+    app: &AppHandle,
+    spool: &Spool,
+    printer_id: Option<String>,
+    role: PrinterRole,
+    kind: JobKind,
+    build: impl FnOnce(usize) -> Document,
+) -> Result<String, PrintError> {
+    let config = current_config(app);
+    let (printer, bytes) = render(&config, printer_id, role, build)?;
+    dispatch(app, spool, &config, printer, kind, bytes)
+}
+
+/// Lays out a document for the target printer's paper width. Without a
+/// printer id, the first printer with `role` is used.
+fn render(
+    config: &PrinterConfig,
+    printer_id: Option<String>,
+    role: PrinterRole,
+    build: impl FnOnce(usize) -> Document,
+) -> Result<(&PrinterDefinition, Vec<u8>), PrintError> {
+    let printer = config.printer_for(printer_id.as_deref(), role)?;
+    if printer.language.is_label() {
+        return Err(PrintError::new(
+            PrintErrorKind::Invalid,
+            format!("Printer '{}' is a label printer; use print_label", printer.id),
+        ));
+    }
+    let document = build(printer.columns());
+    let bytes = match printer.transport {
+        Transport::Ipp { .. } => document.render_text(printer.columns()).into_bytes(),
+        _ => document.render(printer)?,
+    };
+    Ok((printer, bytes))
+}
+
+/// Spools the job and makes the first attempt right away.
+fn dispatch(
+    app: &AppHandle,
+    spool: &Spool,
+    config: &PrinterConfig,
+    printer: &PrinterDefinition,
+    kind: JobKind,
+    bytes: Vec<u8>,
+) -> Result<String, PrintError> {
+    let job = spool.submit(&printer.id, kind, bytes);
+    send_spooled(app, spool, config, printer, &job)
+}
+
+/// Like `dispatch`, and also keeps the receipt in the print history.
+fn dispatch_receipt(
+    app: &AppHandle,
+    spool: &Spool,
+    config: &PrinterConfig,
+    printer: &PrinterDefinition,
+    kind: JobKind,
+    bytes: Vec<u8>,
+    order_id: Option<String>,
+) -> Result<String, PrintError> {
+    let job = spool.submit(&printer.id, kind, bytes);
+    app.state::<PrintHistory>().record(HistoryEntry::new(&job, order_id));
+    send_spooled(app, spool, config, printer, &job)
+}
+
+/// Makes the first attempt at a spooled job. On failure the job stays queued
+/// for the background worker, unless it was cancelled.
+fn send_spooled(
+    app: &AppHandle,
+    spool: &Spool,
+    config: &PrinterConfig,
+    printer: &PrinterDefinition,
+    job: &PrintJob,
+) -> Result<String, PrintError> {
+    let cancel = spool.cancel_token(&job.id);
+    let result = send_with_failover(app, config, printer, &job.id, &job.payload, &cancel);
+    spool.finish(&job.id, &result.clone().map_err(String::from));
+    app.state::<PrintHistory>().finish(&job.id, &result);
+    result.map_err(|e| match e.kind {
+        PrintErrorKind::Cancelled => e,
+        kind => PrintError::new(kind, format!("{} (job {} queued for retry)", e, job.id)),
+    })
+}
+
+#[tauri::command(async)]
+fn get_printer_status(
+    printer_id: Option<String>,
+    app: AppHandle,
+) -> Result<PrinterStatus, PrintError> {
+    let config = current_config(&app);
+    let printer = config.printer(printer_id.as_deref())?;
+
+    let reported = if config.simulate {
+        PrinterStatus::simulated()
+    } else if printer.language != PrinterLanguage::EscPos {
+        return Err(PrintError::new(
+            PrintErrorKind::Invalid,
+            format!("Printer '{}' does not answer ESC/POS status queries", printer.id),
+        ));
+    } else if let Transport::Lpd { .. } = printer.transport {
+        return Err(PrintError::new(
+            PrintErrorKind::Invalid,
+            format!("Printer '{}' is reached over LPD, which reports no status", printer.id),
+        ));
+    } else if let Transport::Ipp { .. } = printer.transport {
+        ipp_client(printer)?.get_printer_attributes()?.status()
+    } else {
+        let mut stream = transport::open(&printer.transport, &printer.timeouts)?;
+        let _ = stream.set_read_timeout(printer.timeouts.read());
+        let result = if printer.status == StatusMode::Asb {
+            stream
+                .write_all(&status::ENABLE_ASB)
+                .and_then(|_| status::read_asb(&mut stream))
+        } else {
+            status::query(&mut stream)
+        };
+        result.map_err(|e| {
+            let context = format!("Failed to read printer status from {}", printer.transport);
+            PrintError::io(&context, e)
+        })?
+    };
+
+    report_status(&app, &printer.id, reported);
+    Ok(reported)
+}
+
+/// Kicks the cash drawer wired to a receipt printer. Every attempt is written
+/// to the drawer audit log, including failed ones.
+#[tauri::command(async)]
+#[allow(clippy::too_many_arguments)]
+fn open_cash_drawer(
+    printer_id: Option<String>,
+    operator: String,
+    reason: String,
+    pin: Option<u8>,
+    on_ms: Option<u16>,
+    off_ms: Option<u16>,
+    app: AppHandle,
+    drawer_log: State<'_, DrawerLog>,
+) -> Result<String, PrintError> {
+    if operator.trim().is_empty() {
+        return Err("An operator is required to open the cash drawer".into());
+    }
+    let config = current_config(&app);
+    let printer = config.printer_for(printer_id.as_deref(), PrinterRole::Receipt)?;
+    drawer::check_printer(printer)?;
+    let settings = DrawerSettings {
+        pin: pin.unwrap_or(printer.drawer.pin),
+        on_ms: on_ms.unwrap_or(printer.drawer.on_ms),
+        off_ms: off_ms.unwrap_or(printer.drawer.off_ms),
+    };
+    settings.validate()?;
+
+    // Sent directly rather than spooled: a retried kick would open the
+    // drawer long after the cashier asked for it.
+    let kick = settings.kick_bytes(printer.language);
+    let result = send_to_printer(&app, &config, printer, &kick, false, &CancelToken::default());
+    drawer_log
+        .record(&DrawerEvent {
+            at: spool::now_millis(),
+            printer_id: printer.id.clone(),
+            operator,
+            reason,
+            pin: settings.pin,
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.message.clone()),
+        })
+        .map_err(|e| PrintError::new(PrintErrorKind::Io, e))?;
+    result.map(|_| format!("Cash drawer opened on {}", printer.id))
+}
+
+/// Uploads a printer's configured logo to its NV graphics memory, for
+/// printers whose logo `mode` is `nv`.
+#[tauri::command(async)]
+fn store_printer_logo(printer_id: Option<String>, app: AppHandle) -> Result<String, PrintError> {
+    let config = current_config(&app);
+    let printer = config.printer(printer_id.as_deref())?;
+    let logo = printer
+        .logo
+        .as_ref()
+        .ok_or_else(|| format!("Printer '{}' has no logo configured", printer.id))?;
+    if printer.language != PrinterLanguage::EscPos {
+        return Err(PrintError::new(
+            PrintErrorKind::Invalid,
+            format!("Printer '{}' has no NV graphics memory", printer.id),
+        ));
+    }
+    let bitmap = logo.bitmap(printer.dots())?;
+    let bytes = EscPosBuilder::new()
+        .init()
+        .nv_graphic_store(&logo.nv_key, &bitmap)?
+        .build();
+    send_to_printer(&app, &config, printer, &bytes, false, &CancelToken::default())
+}
+
+/// Prints a PDF or plain-text document, such as an invoice or end-of-day
+/// report, on an office printer reached over IPP. Returns the IPP job so the
+/// UI can follow it with `get_office_job`.
+#[tauri::command(async)]
+fn print_office_document(
+    base64_data: String,
+    printer_id: Option<String>,
+    job_name: Option<String>,
+    app: AppHandle,
+) -> Result<IppJob, PrintError> {
+    let config = current_config(&app);
+    let printer = config.printer_for(printer_id.as_deref(), PrinterRole::Office)?;
+    let client = ipp_client(printer)?;
     let bytes = general_purpose::STANDARD
         .decode(&base64_data)
         .map_err(|e| format!("Failed to decode base64: {}", e))?;
+    let format = ipp::document_format(&bytes);
+    if !format.starts_with("application/pdf") && !format.starts_with("text/plain") {
+        return Err("Office documents must be PDF or plain text".into());
+    }
+
+    if config.simulate {
+        app.state::<SimulatedPrinter>()
+            .capture(printer, &bytes)
+            .map_err(|e| PrintError::new(PrintErrorKind::Io, e))?;
+        return Ok(IppJob {
+            id: 0,
+            state: IppJobState::Completed,
+            state_reasons: Vec::new(),
+        });
+    }
+    client
+        .print_job(
+            job_name.as_deref().unwrap_or("ChefCloud document"),
+            "chefcloud",
+            format,
+            &bytes,
+        )
+}
+
+#[tauri::command]
+fn get_office_job(
+    printer_id: Option<String>,
+    job_id: i32,
+    app: AppHandle,
+) -> Result<IppJob, String> {
+    let config = current_config(&app);
+    let printer = config.printer_for(printer_id.as_deref(), PrinterRole::Office)?;
+    ipp_client(printer)?
+        .get_job_attributes(job_id)
+        .map_err(String::from)
+}
+
+fn ipp_client(printer: &PrinterDefinition) -> Result<IppClient, String> {
+    match &printer.transport {
+        Transport::Ipp { uri } => Ok(IppClient::new(uri)?
+            .with_connect_timeout(printer.timeouts.connect())
+            .with_timeout(printer.timeouts.write())),
+        _ => Err(format!("Printer '{}' is not an IPP printer", printer.id)),
+    }
+}
+
+/// Jobs captured in simulate mode, newest first.
+#[tauri::command]
+fn list_simulated_prints(
+    limit: Option<usize>,
+    simulator: State<'_, SimulatedPrinter>,
+) -> Vec<SimulatedPrint> {
+    simulator.list(limit.unwrap_or(50))
+}
+
+#[tauri::command]
+fn list_failover_events(
+    since: Option<u64>,
+    failover_log: State<'_, FailoverLog>,
+) -> Vec<FailoverEvent> {
+    failover_log.list(since.unwrap_or(0))
+}
+
+#[tauri::command]
+fn list_drawer_events(since: Option<u64>, drawer_log: State<'_, DrawerLog>) -> Vec<DrawerEvent> {
+    drawer_log.list(since.unwrap_or(0))
+}
+
+/// Records a printer's status and emits `printer-status-changed` when it
+/// differs from the last one seen.
+fn report_status(app: &AppHandle, printer_id: &str, reported: PrinterStatus) {
+    if app.state::<StatusBoard>().record(printer_id, reported) {
+        let _ = app.emit_all(
+            "printer-status-changed",
+            serde_json::json!({
+                "printerId": printer_id,
+                "status": reported,
+                "ready": reported.is_ready(),
+            }),
+        );
+    }
+}
 
+/// The active printer config. Commands take one snapshot and use it
+/// throughout, so a reload mid-job cannot mix two configs.
+fn current_config(app: &AppHandle) -> Arc<PrinterConfig> {
+    app.state::<Arc<ConfigStore>>().get()
+}
+
+/// Emits `printer-config-changed` with the new config after a reload, or
+/// `printer-config-invalid` when a change was rejected and the previous
+/// config kept.
+fn report_reload(app: &AppHandle, reloaded: Option<Result<Arc<PrinterConfig>, String>>) {
+    match reloaded {
+        Some(Ok(config)) => {
+            let _ = app.emit_all("printer-config-changed", &*config);
+        }
+        Some(Err(e)) => {
+            eprintln!("Keeping previous printer config: {}", e);
+            let _ = app.emit_all("printer-config-invalid", e);
+        }
+        None => {}
+    }
+}
+
+fn send_job(app: &AppHandle, job: &PrintJob, cancel: &CancelToken) -> Result<String, String> {
+    let config = current_config(app);
+    let printer = config.printer(Some(&job.printer_id))?;
+    let result = send_with_failover(app, &config, printer, &job.id, &job.payload, cancel);
+    app.state::<PrintHistory>().finish(&job.id, &result);
+    result.map_err(String::from)
+}
+
+/// Sends to `printer`, moving on to the next member of its groups when it
+/// cannot be reached or reports that it is not ready. Jobs that move are
+/// printed under a "REROUTED FROM" header, and each move is logged and
+/// emitted as `printer-failover`. A job that fails once it is being sent,
+/// or is cancelled, stops where it is, so the spool retries it on the same
+/// printer. The error's kind is that of the last printer tried.
+fn send_with_failover(
+    app: &AppHandle,
+    config: &PrinterConfig,
+    printer: &PrinterDefinition,
+    job_id: &str,
+    bytes: &[u8],
+    cancel: &CancelToken,
+) -> Result<String, PrintError> {
+    let chain = config.failover_chain(printer);
+    let mut messages = Vec::new();
+    let mut kind = PrintErrorKind::Io;
+    for (i, member) in chain.iter().enumerate() {
+        let next = chain.get(i + 1);
+        let result = if i == 0 {
+            send_to_printer(app, config, member, bytes, next.is_some(), cancel)
+        } else {
+            let rerouted = failover::reroute(member, &printer.id, bytes);
+            send_to_printer(app, config, member, &rerouted, next.is_some(), cancel)
+        };
+        match (result, next) {
+            (Ok(message), _) => return Ok(message),
+            (Err(e), Some(next)) if failover::can_reroute(&e) => {
+                report_failover(app, job_id, member, next, &e.message);
+                messages.push(e.message);
+            }
+            (Err(e), _) => {
+                kind = e.kind;
+                messages.push(e.message);
+                break;
+            }
+        }
+    }
+    Err(PrintError::new(kind, messages.join("; ")))
+}
+
+fn report_failover(
+    app: &AppHandle,
+    job_id: &str,
+    from: &PrinterDefinition,
+    to: &PrinterDefinition,
+    reason: &str,
+) {
+    let event = FailoverEvent {
+        at: spool::now_millis(),
+        job_id: job_id.to_string(),
+        from: from.id.clone(),
+        to: to.id.clone(),
+        reason: reason.to_string(),
+    };
+    eprintln!("Rerouting job from {} to {}: {}", event.from, event.to, event.reason);
+    if let Err(e) = app.state::<FailoverLog>().record(&event) {
+        eprintln!("{}", e);
+    }
+    let _ = app.emit_all("printer-failover", &event);
+}
+
+/// Sends `bytes` to one printer. With `require_ready`, a printer that reads
+/// back its status is asked for it first and the job is refused if it is not
+/// ready, so that it can go to another printer instead.
+fn send_to_printer(
+    app: &AppHandle,
+    config: &PrinterConfig,
+    printer: &PrinterDefinition,
+    bytes: &[u8],
+    require_ready: bool,
+    cancel: &CancelToken,
+) -> Result<String, PrintError> {
+    if cancel.is_cancelled() {
+        return Err(PrintError::cancelled());
+    }
     if config.simulate {
-        println!("PRINT BYTES {}", bytes.len());
-        Ok(format!("Simulated print: {} bytes", bytes.len()))
+        let capture = app.state::<SimulatedPrinter>()
+            .capture(printer, bytes)
+            .map_err(|e| PrintError::new(PrintErrorKind::Io, e))?;
+        Ok(format!("Simulated print: {} bytes ({})", bytes.len(), capture.txt_path))
+    } else if let Transport::Ipp { uri } = &printer.transport {
+        let job = ipp_client(printer)?
+            .print_job("ChefCloud print job", "chefcloud", ipp::document_format(bytes), bytes)?;
+        Ok(format!("Submitted {} bytes to {} as IPP job {}", bytes.len(), uri, job.id))
     } else {
-        // Connect to printer via TCP
-        let addr = format!("{}:{}", config.host, config.port);
-        let mut stream = TcpStream::connect(&addr)
-            .map_err(|e| format!("Failed to connect to printer at {}: {}", addr, e))?;
+        // Connect to printer over its configured transport
+        let mut stream = transport::open(&printer.transport, &printer.timeouts)?;
+
+        let _ = stream.set_read_timeout(printer.timeouts.read());
+        if printer.status == StatusMode::Asb {
+            transport::write_all(&mut *stream, &status::ENABLE_ASB, cancel)?;
+        }
+
+        // Check the printer can take the job before sending it
+        let before = match printer.status {
+            _ if !require_ready => None,
+            StatusMode::None => None,
+            StatusMode::Query => status::query(&mut stream).ok(),
+            StatusMode::Asb => status::read_asb(&mut stream).ok(),
+        };
+        if let Some(before) = before {
+            report_status(app, &printer.id, before);
+            if !before.is_ready() {
+                let problems = before.problems().join(", ");
+                return Err(PrintError::new(
+                    PrintErrorKind::NotReady,
+                    format!("Printer '{}' is not ready: {}", printer.id, problems),
+                ));
+            }
+        }
 
-        stream.write_all(&bytes)
-            .map_err(|e| format!("Failed to send data to printer: {}", e))?;
+        // Serial ports share one timeout between reads and writes.
+        let _ = stream.set_write_timeout(printer.timeouts.write());
+        transport::write_all(&mut *stream, bytes, cancel)?;
+        let _ = stream.set_read_timeout(printer.timeouts.read());
 
-        Ok(format!("Printed {} bytes to {}", bytes.len(), addr))
+        // Read back the status on the same connection, if configured. ASB
+        // sends one packet when enabled, which the check above already read.
+        let reported = match printer.status {
+            StatusMode::None => None,
+            StatusMode::Query => status::query(&mut stream).ok(),
+            StatusMode::Asb if before.is_some() => None,
+            StatusMode::Asb => status::read_asb(&mut stream).ok(),
+        };
+        if let Some(reported) = reported {
+            report_status(app, &printer.id, reported);
+        }
+
+        Ok(format!("Printed {} bytes to {}", bytes.len(), printer.transport))
     }
 }
 
 fn main() {
   tauri::Builder::default()
-    .invoke_handler(tauri::generate_handler![print_receipt])
+    .setup(|app| {
+      let data_dir = app
+        .path_resolver()
+        .app_data_dir()
+        .ok_or("Failed to resolve app data directory")?;
+      let config_store = Arc::new(ConfigStore::open(config::config_path()));
+      let handle = app.handle();
+      reload::spawn_watcher(config_store.clone(), move |reloaded| {
+        report_reload(&handle, Some(reloaded))
+      });
+      app.manage(config_store);
+
+      let spool = Arc::new(Spool::open(data_dir.join("print-spool.json")));
+      let handle = app.handle();
+      spool::spawn_worker(spool.clone(), move |job, cancel| send_job(&handle, job, cancel));
+      app.manage(spool);
+      app.manage(DrawerLog::new(data_dir.join("drawer-audit.jsonl")));
+      app.manage(FailoverLog::new(data_dir.join("failover.jsonl")));
+      app.manage(PrintHistory::open(data_dir.join("print-history.json")));
+      app.manage(RoutingStore::open(data_dir.join("station-routes.json")));
+      app.manage(StatusBoard::default());
+      app.manage(SimulatedPrinter::new(data_dir.join("print-sim")));
+      Ok(())
+    })
+    .invoke_handler(tauri::generate_handler![
+      list_printers,
+      get_printer_config,
+      save_printer_config,
+      test_printer_connection,
+      discover_printers,
+      print_receipt,
+      inspect_print_bytes,
+      preview_receipt,
+      print_document,
+      print_receipt_data,
+      print_kitchen_ticket,
+      print_kitchen_order,
+      sync_station_routes,
+      get_station_routes,
+      print_shift_report,
+      print_label,
+      list_print_jobs,
+      retry_print_job,
+      cancel_print_job,
+      reprint_print_job,
+      list_print_history,
+      reprint_job,
+      get_printer_status,
+      open_cash_drawer,
+      list_drawer_events,
+      list_failover_events,
+      list_simulated_prints,
+      store_printer_logo,
+      print_office_document,
+      get_office_job
+    ])
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
 }
@@ -0,0 +1,316 @@
+//! Bar patterns for the 1D symbologies the printers draw from `GS k`, so
+//! previews can show a barcode the way the printer lays it out.
+
+use super::escpos::Symbology;
+
+/// Code 128 bar and space widths in modules for values 0-105, then the stop
+/// pattern with its final bar.
+const CODE128: [&str; 107] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212",
+    "221213", "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221",
+    "223211", "221132", "221231", "213212", "223112", "312131", "311222", "321122", "321221",
+    "312212", "322112", "322211", "212123", "212321", "232121", "111323", "131123", "131321",
+    "112313", "132113", "132311", "211313", "231113", "231311", "112133", "112331", "132131",
+    "113123", "113321", "133121", "313121", "211331", "231131", "213113", "213311", "213131",
+    "311123", "311321", "331121", "312113", "312311", "332111", "314111", "221411", "431111",
+    "111224", "111422", "121124", "121421", "141122", "141221", "112214", "112412", "122114",
+    "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111", "111242",
+    "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311",
+    "113141", "114131", "311141", "411131", "211412", "211214", "211232", "2331112",
+];
+const CODE128_CODE_C: u32 = 99;
+const CODE128_CODE_B: u32 = 100;
+const CODE128_CODE_A: u32 = 101;
+const CODE128_START_A: u32 = 103;
+const CODE128_STOP: usize = 106;
+
+/// Code 39 characters, with `*` as the start and stop character.
+const CODE39_CHARS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ-. *$/+%";
+/// Bar, space, bar... for each of `CODE39_CHARS`, `1` marking a wide element.
+const CODE39: [&str; 44] = [
+    "000110100",
+    "100100001",
+    "001100001",
+    "101100000",
+    "000110001",
+    "100110000",
+    "001110000",
+    "000100101",
+    "100100100",
+    "001100100",
+    "100001001",
+    "001001001",
+    "101001000",
+    "000011001",
+    "100011000",
+    "001011000",
+    "000001101",
+    "100001100",
+    "001001100",
+    "000011100",
+    "100000011",
+    "001000011",
+    "101000010",
+    "000010011",
+    "100010010",
+    "001010010",
+    "000000111",
+    "100000110",
+    "001000110",
+    "000010110",
+    "110000001",
+    "011000001",
+    "111000000",
+    "010010001",
+    "110010000",
+    "011010000",
+    "010000101",
+    "110000100",
+    "011000100",
+    "010010100",
+    "010101000",
+    "010100010",
+    "010001010",
+    "000101010",
+];
+/// Width of a wide Code 39 element in modules.
+const CODE39_WIDE: usize = 3;
+
+/// EAN-13 left-hand odd parity ("L") patterns for digits 0-9. The right-hand
+/// patterns are their complement and the even parity ("G") patterns the
+/// right-hand ones reversed.
+const EAN_L: [u8; 10] = [0x0d, 0x19, 0x13, 0x3d, 0x23, 0x31, 0x2f, 0x3b, 0x37, 0x0b];
+/// Which of the left-hand digits use G patterns, by the leading digit.
+const EAN_PARITY: [u8; 10] = [
+    0b000000, 0b001011, 0b001101, 0b001110, 0b010011, 0b011001, 0b011100, 0b010101, 0b010110,
+    0b011010,
+];
+
+/// A barcode as the printer draws it: one entry per module, `true` for a bar.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bars {
+    pub modules: Vec<bool>,
+    /// The human-readable interpretation printed with `GS H`.
+    pub text: String,
+}
+
+/// Encodes `data` as sent in `GS k`: Code 128 data starts with a `{A`, `{B`
+/// or `{C` code set selector and EAN-13 takes 12 digits plus an optional
+/// check digit.
+pub fn encode(symbology: Symbology, data: &str) -> Result<Bars, String> {
+    match symbology {
+        Symbology::Code128 => code128(data),
+        Symbology::Ean13 => ean13(data),
+        Symbology::Code39 => code39(data),
+    }
+}
+
+fn push_widths(modules: &mut Vec<bool>, widths: impl Iterator<Item = usize>) {
+    for (i, width) in widths.enumerate() {
+        modules.extend(std::iter::repeat_n(i % 2 == 0, width));
+    }
+}
+
+fn code128(data: &str) -> Result<Bars, String> {
+    let invalid = |reason: &str| format!("Invalid Code128 barcode data '{}': {}", data, reason);
+    let bytes = data.as_bytes();
+    let mut values: Vec<u32> = Vec::new();
+    let mut text = String::new();
+    let mut set = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'{' {
+            let selector = *bytes.get(i + 1).ok_or_else(|| invalid("ends after '{'"))?;
+            i += 2;
+            let (code, next) = match selector {
+                b'A' => (CODE128_CODE_A, b'A'),
+                b'B' => (CODE128_CODE_B, b'B'),
+                b'C' => (CODE128_CODE_C, b'C'),
+                // "{{" is a literal brace; FNC and shift codes are not used
+                // by our documents.
+                b'{' => {
+                    text.push('{');
+                    values.push(code128_char(set, b'{').ok_or_else(|| invalid("'{' in set C"))?);
+                    continue;
+                }
+                other => return Err(invalid(&format!("unsupported code '{{{}'", other as char))),
+            };
+            values.push(if values.is_empty() {
+                CODE128_START_A + (next - b'A') as u32
+            } else {
+                code
+            });
+            set = Some(next);
+            continue;
+        }
+        let value = if set == Some(b'C') {
+            let pair = bytes
+                .get(i..i + 2)
+                .filter(|p| p.iter().all(u8::is_ascii_digit));
+            let pair = pair.ok_or_else(|| invalid("set C takes pairs of digits"))?;
+            text.push_str(&data[i..i + 2]);
+            i += 1;
+            ((pair[0] - b'0') * 10 + (pair[1] - b'0')) as u32
+        } else {
+            text.push(bytes[i] as char);
+            code128_char(set, bytes[i]).ok_or_else(|| invalid("character not in code set"))?
+        };
+        values.push(value);
+        i += 1;
+    }
+    if values.len() < 2 {
+        return Err(invalid("no data"));
+    }
+    let checksum = values
+        .iter()
+        .enumerate()
+        .map(|(pos, &value)| pos.max(1) as u32 * value)
+        .sum::<u32>()
+        % 103;
+    values.push(checksum);
+    values.push(CODE128_STOP as u32);
+
+    let mut modules = Vec::new();
+    for value in values {
+        let widths = CODE128[value as usize].bytes().map(|w| (w - b'0') as usize);
+        push_widths(&mut modules, widths);
+    }
+    Ok(Bars { modules, text })
+}
+
+/// The value of `byte` in code set A or B, or `None` before a code set is
+/// selected or when the set cannot encode it.
+fn code128_char(set: Option<u8>, byte: u8) -> Option<u32> {
+    match (set?, byte) {
+        (b'A', 0x00..=0x1f) => Some(byte as u32 + 64),
+        (b'A', 0x20..=0x5f) | (b'B', 0x20..=0x7f) => Some(byte as u32 - 32),
+        _ => None,
+    }
+}
+
+fn ean13(data: &str) -> Result<Bars, String> {
+    Symbology::Ean13.validate(data)?;
+    let mut digits: Vec<usize> = data.bytes().map(|b| (b - b'0') as usize).collect();
+    if digits.len() == 12 {
+        let sum: usize = digits
+            .iter()
+            .enumerate()
+            .map(|(i, d)| if i % 2 == 0 { *d } else { d * 3 })
+            .sum();
+        digits.push((10 - sum % 10) % 10);
+    }
+
+    let mut modules = Vec::new();
+    let mut push_bits = |bits: u8, count: usize| {
+        modules.extend((0..count).rev().map(|bit| (bits >> bit) & 1 == 1));
+    };
+    push_bits(0b101, 3);
+    let parity = EAN_PARITY[digits[0]];
+    for (i, &digit) in digits[1..7].iter().enumerate() {
+        let l = EAN_L[digit];
+        if (parity >> (5 - i)) & 1 == 1 {
+            // G: the R pattern (!L) read backwards.
+            push_bits((!l & 0x7f).reverse_bits() >> 1, 7);
+        } else {
+            push_bits(l, 7);
+        }
+    }
+    push_bits(0b01010, 5);
+    for &digit in &digits[7..] {
+        push_bits(!EAN_L[digit] & 0x7f, 7);
+    }
+    push_bits(0b101, 3);
+
+    let text = digits.iter().map(|d| char::from(b'0' + *d as u8)).collect();
+    Ok(Bars { modules, text })
+}
+
+fn code39(data: &str) -> Result<Bars, String> {
+    Symbology::Code39.validate(data)?;
+    let mut modules = Vec::new();
+    for (i, byte) in std::iter::once(b'*')
+        .chain(data.bytes())
+        .chain(std::iter::once(b'*'))
+        .enumerate()
+    {
+        if i > 0 {
+            // Narrow gap between characters.
+            modules.push(false);
+        }
+        let index = CODE39_CHARS.iter().position(|&c| c == byte).unwrap_or(0);
+        let widths = CODE39[index]
+            .bytes()
+            .map(|w| if w == b'1' { CODE39_WIDE } else { 1 });
+        push_widths(&mut modules, widths);
+    }
+    Ok(Bars {
+        modules,
+        text: format!("*{}*", data),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code128_patterns_are_eleven_modules_with_a_checksum() {
+        assert!(CODE128[..CODE128_STOP].iter().all(|p| p
+            .bytes()
+            .map(|w| (w - b'0') as usize)
+            .sum::<usize>()
+            == 11));
+
+        // Start B, "CC", checksum (104 + 35 + 2 * 35) % 103 = 3, stop.
+        let bars = encode(Symbology::Code128, "{BCC").unwrap();
+        assert_eq!(bars.text, "CC");
+        assert_eq!(bars.modules.len(), 11 * 4 + 13);
+        let mut expected = Vec::new();
+        for value in [104, 35, 35, 3, 106] {
+            push_widths(
+                &mut expected,
+                CODE128[value].bytes().map(|w| (w - b'0') as usize),
+            );
+        }
+        assert_eq!(bars.modules, expected);
+
+        // Set C packs digit pairs; a later "{B" switches with code B.
+        let bars = encode(Symbology::Code128, "{C1234{BA").unwrap();
+        assert_eq!(bars.text, "1234A");
+        assert_eq!(bars.modules.len(), 11 * 6 + 13);
+        assert!(encode(Symbology::Code128, "{C123").is_err());
+        assert!(encode(Symbology::Code128, "no selector").is_err());
+    }
+
+    #[test]
+    fn ean13_adds_the_check_digit_and_guards() {
+        let bars = encode(Symbology::Ean13, "400638133393").unwrap();
+        assert_eq!(bars.text, "4006381333931");
+        assert_eq!(bars.modules.len(), 95);
+        assert_eq!(bars.modules[..3], [true, false, true]);
+        assert_eq!(bars.modules[45..50], [false, true, false, true, false]);
+        // First left digit 0 uses L parity (leading 4 is LGLLGG).
+        let l0 = [false, false, false, true, true, false, true];
+        assert_eq!(bars.modules[3..10], l0);
+        // Second left digit 0 uses G parity.
+        let g0 = [false, true, false, false, true, true, true];
+        assert_eq!(bars.modules[10..17], g0);
+        assert_eq!(
+            encode(Symbology::Ean13, "4006381333931").unwrap().modules,
+            bars.modules
+        );
+    }
+
+    #[test]
+    fn code39_has_three_wide_elements_per_character() {
+        assert!(CODE39
+            .iter()
+            .all(|p| p.len() == 9 && p.bytes().filter(|&b| b == b'1').count() == 3));
+        let bars = encode(Symbology::Code39, "A1").unwrap();
+        assert_eq!(bars.text, "*A1*");
+        // Four characters of 6 narrow + 3 wide elements, 3 gaps.
+        assert_eq!(bars.modules.len(), 4 * (6 + 3 * CODE39_WIDE) + 3);
+        assert!(encode(Symbology::Code39, "lower").is_err());
+    }
+}
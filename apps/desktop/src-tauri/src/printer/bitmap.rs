@@ -0,0 +1,110 @@
+use qrcode::{Color, EcLevel, QrCode};
+
+use super::escpos::QrErrorCorrection;
+
+/// 1-bit image, rows packed MSB-first with each row padded to a whole byte,
+/// as expected by `GS v 0`. A set bit prints a black dot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl Bitmap {
+    pub fn new(width: usize, height: usize) -> Self {
+        Bitmap {
+            width,
+            height,
+            data: vec![0; width.div_ceil(8) * height],
+        }
+    }
+
+    pub fn bytes_per_row(&self) -> usize {
+        self.width.div_ceil(8)
+    }
+
+    pub fn set(&mut self, x: usize, y: usize) {
+        if x < self.width && y < self.height {
+            let row = self.bytes_per_row();
+            self.data[y * row + x / 8] |= 0x80 >> (x % 8);
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < self.width
+            && y < self.height
+            && self.data[y * self.bytes_per_row() + x / 8] & (0x80 >> (x % 8)) != 0
+    }
+
+    /// Renders a QR code in software for printers without native QR
+    /// support. Each module is `scale` dots square, with a 4-module quiet
+    /// zone.
+    pub fn qr(data: &str, scale: u8, ec: QrErrorCorrection) -> Result<Self, String> {
+        let code = encode_qr(data, ec)?;
+        let modules = code.width();
+        let colors = code.to_colors();
+        let scale = scale.max(1) as usize;
+        let quiet = 4;
+        let size = (modules + 2 * quiet) * scale;
+
+        let mut bitmap = Bitmap::new(size, size);
+        for (i, color) in colors.iter().enumerate() {
+            if *color != Color::Dark {
+                continue;
+            }
+            let (mx, my) = (i % modules + quiet, i / modules + quiet);
+            for y in my * scale..(my + 1) * scale {
+                for x in mx * scale..(mx + 1) * scale {
+                    bitmap.set(x, y);
+                }
+            }
+        }
+        Ok(bitmap)
+    }
+}
+
+/// Encodes `data` as a QR code, failing when it does not fit the largest
+/// symbol at this error correction level.
+pub fn encode_qr(data: &str, ec: QrErrorCorrection) -> Result<QrCode, String> {
+    let level = match ec {
+        QrErrorCorrection::L => EcLevel::L,
+        QrErrorCorrection::M => EcLevel::M,
+        QrErrorCorrection::Q => EcLevel::Q,
+        QrErrorCorrection::H => EcLevel::H,
+    };
+    QrCode::with_error_correction_level(data.as_bytes(), level)
+        .map_err(|e| format!("Failed to encode QR code: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_pixels_msb_first() {
+        let mut bitmap = Bitmap::new(10, 2);
+        bitmap.set(0, 0);
+        bitmap.set(9, 1);
+        assert_eq!(bitmap.bytes_per_row(), 2);
+        assert_eq!(bitmap.data, [0x80, 0x00, 0x00, 0x40]);
+        assert!(bitmap.get(9, 1));
+        assert!(!bitmap.get(8, 1));
+    }
+
+    #[test]
+    fn software_qr_has_quiet_zone_and_finder_pattern() {
+        let bitmap = Bitmap::qr(
+            "https://efris.ura.go.ug/verify/123",
+            3,
+            QrErrorCorrection::M,
+        )
+        .unwrap();
+        assert_eq!(bitmap.width, bitmap.height);
+        // Quiet zone is blank, top-left finder pattern starts after it.
+        assert!(!bitmap.get(0, 0));
+        assert!(!bitmap.get(11, 11));
+        assert!(bitmap.get(12, 12));
+        assert!(bitmap.get(12 + 6 * 3, 12));
+    }
+}
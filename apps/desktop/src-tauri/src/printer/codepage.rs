@@ -0,0 +1,234 @@
+//! UTF-8 to printer code page transcoding. Thermal printers interpret text
+//! bytes in the code page selected with `ESC t n`, so menu item names like
+//! "Crème brûlée" must be translated before they are sent.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum CodePage {
+    /// US, box drawing. No euro sign.
+    #[default]
+    Cp437,
+    /// Western European.
+    Cp850,
+    /// Central European.
+    Cp852,
+    /// CP850 with the euro sign.
+    Cp858,
+    /// Cyrillic.
+    Cp866,
+    /// Windows Western European.
+    Cp1252,
+}
+
+impl CodePage {
+    pub const ALL: [CodePage; 6] = [
+        CodePage::Cp437,
+        CodePage::Cp850,
+        CodePage::Cp852,
+        CodePage::Cp858,
+        CodePage::Cp866,
+        CodePage::Cp1252,
+    ];
+
+    /// The code page selected by `ESC t n`, if it is one we support.
+    pub fn from_escpos_table(table: u8) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|page| page.escpos_table() == table)
+    }
+
+    /// Table number for Epson `ESC t n`.
+    pub fn escpos_table(&self) -> u8 {
+        match self {
+            CodePage::Cp437 => 0,
+            CodePage::Cp850 => 2,
+            CodePage::Cp852 => 18,
+            CodePage::Cp858 => 19,
+            CodePage::Cp866 => 17,
+            CodePage::Cp1252 => 16,
+        }
+    }
+
+    /// Code page number for Star `ESC GS t n`. Star has no CP850 table, so
+    /// it prints with CP858, which differs only in the euro sign.
+    pub fn star_table(&self) -> u8 {
+        match self {
+            CodePage::Cp437 => 1,
+            CodePage::Cp850 | CodePage::Cp858 => 4,
+            CodePage::Cp852 => 5,
+            CodePage::Cp866 => 10,
+            CodePage::Cp1252 => 32,
+        }
+    }
+
+    /// Characters for bytes 0x80 to 0xFF. U+FFFD marks unassigned bytes.
+    fn upper_half(&self) -> &'static str {
+        match self {
+            CodePage::Cp437 => CP437,
+            CodePage::Cp850 => CP850,
+            CodePage::Cp852 => CP852,
+            CodePage::Cp858 => CP858,
+            CodePage::Cp866 => CP866,
+            CodePage::Cp1252 => CP1252,
+        }
+    }
+
+    pub fn encode_char(&self, c: char) -> Option<u8> {
+        if c.is_ascii() {
+            return Some(c as u8);
+        }
+        if c == '\u{fffd}' {
+            return None;
+        }
+        self.upper_half()
+            .chars()
+            .position(|mapped| mapped == c)
+            .map(|i| 0x80 + i as u8)
+    }
+
+    /// Decodes bytes printed in this code page back to text.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        bytes
+            .iter()
+            .map(|&b| {
+                if b.is_ascii() {
+                    b as char
+                } else {
+                    self.upper_half()
+                        .chars()
+                        .nth(b as usize - 0x80)
+                        .unwrap_or('\u{fffd}')
+                }
+            })
+            .collect()
+    }
+
+    /// Encodes `text`, replacing characters the code page cannot represent
+    /// with an ASCII approximation where one exists, or with `fallback`.
+    pub fn encode(&self, text: &str, fallback: &str) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(text.len());
+        for c in text.chars() {
+            if let Some(byte) = self.encode_char(c) {
+                bytes.push(byte);
+            } else if let Some(approx) = transliterate(c) {
+                bytes.extend(approx.chars().filter_map(|a| self.encode_char(a)));
+            } else {
+                bytes.extend(fallback.chars().filter_map(|f| self.encode_char(f)));
+            }
+        }
+        bytes
+    }
+}
+
+/// ASCII stand-ins for common characters missing from some code pages.
+fn transliterate(c: char) -> Option<&'static str> {
+    Some(match c {
+        '€' => "EUR",
+        '‘' | '’' | '‚' | '′' => "'",
+        '“' | '”' | '„' | '″' => "\"",
+        '–' | '—' | '−' => "-",
+        '…' => "...",
+        '•' => "*",
+        '™' => "TM",
+        '©' => "(C)",
+        '®' => "(R)",
+        '\u{a0}' => " ",
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => "a",
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' => "A",
+        'è' | 'é' | 'ê' | 'ë' => "e",
+        'È' | 'É' | 'Ê' | 'Ë' => "E",
+        'ì' | 'í' | 'î' | 'ï' => "i",
+        'Ì' | 'Í' | 'Î' | 'Ï' => "I",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' => "o",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' => "O",
+        'ù' | 'ú' | 'û' | 'ü' => "u",
+        'Ù' | 'Ú' | 'Û' | 'Ü' => "U",
+        'ç' => "c",
+        'Ç' => "C",
+        'ñ' => "n",
+        'Ñ' => "N",
+        'ß' => "ss",
+        _ => return None,
+    })
+}
+
+const CP437: &str = concat!(
+    "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒ",
+    "áíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐",
+    "└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀",
+    "αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}",
+);
+
+const CP850: &str = concat!(
+    "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜø£Ø×ƒ",
+    "áíóúñÑªº¿®¬½¼¡«»░▒▓│┤ÁÂÀ©╣║╗╝¢¥┐",
+    "└┴┬├─┼ãÃ╚╔╩╦╠═╬¤ðÐÊËÈıÍÎÏ┘┌█▄¦Ì▀",
+    "ÓßÔÒõÕµþÞÚÛÙýÝ¯´\u{ad}±‗¾¶§÷¸°¨·¹³²■\u{a0}",
+);
+
+const CP852: &str = concat!(
+    "ÇüéâäůćçłëŐőîŹÄĆÉĹĺôöĽľŚśÖÜŤťŁ×č",
+    "áíóúĄąŽžĘę¬źČş«»░▒▓│┤ÁÂĚŞ╣║╗╝Żż┐",
+    "└┴┬├─┼Ăă╚╔╩╦╠═╬¤đĐĎËďŇÍÎě┘┌█▄ŢŮ▀",
+    "ÓßÔŃńňŠšŔÚŕŰýÝţ´\u{ad}˝˛ˇ˘§÷¸°¨˙űŘř■\u{a0}",
+);
+
+const CP858: &str = concat!(
+    "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜø£Ø×ƒ",
+    "áíóúñÑªº¿®¬½¼¡«»░▒▓│┤ÁÂÀ©╣║╗╝¢¥┐",
+    "└┴┬├─┼ãÃ╚╔╩╦╠═╬¤ðÐÊËÈ€ÍÎÏ┘┌█▄¦Ì▀",
+    "ÓßÔÒõÕµþÞÚÛÙýÝ¯´\u{ad}±‗¾¶§÷¸°¨·¹³²■\u{a0}",
+);
+
+const CP866: &str = concat!(
+    "АБВГДЕЖЗИЙКЛМНОПРСТУФХЦЧШЩЪЫЬЭЮЯ",
+    "абвгдежзийклмноп░▒▓│┤╡╢╖╕╣║╗╝╜╛┐",
+    "└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀",
+    "рстуфхцчшщъыьэюяЁёЄєЇїЎў°∙·√№¤■\u{a0}",
+);
+
+const CP1252: &str = concat!(
+    "€\u{fffd}‚ƒ„…†‡ˆ‰Š‹Œ\u{fffd}Ž\u{fffd}\u{fffd}‘’“”•–—˜™š›œ\u{fffd}žŸ",
+    "\u{a0}¡¢£¤¥¦§¨©ª«¬\u{ad}®¯°±²³´µ¶·¸¹º»¼½¾¿",
+    "ÀÁÂÃÄÅÆÇÈÉÊËÌÍÎÏÐÑÒÓÔÕÖ×ØÙÚÛÜÝÞß",
+    "àáâãäåæçèéêëìíîïðñòóôõö÷øùúûüýþÿ",
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_cover_the_upper_half() {
+        for page in CodePage::ALL {
+            assert_eq!(page.upper_half().chars().count(), 128, "{:?}", page);
+            assert_eq!(CodePage::from_escpos_table(page.escpos_table()), Some(page));
+        }
+    }
+
+    #[test]
+    fn encodes_accented_menu_items() {
+        let text = "Crème brûlée";
+        assert_eq!(CodePage::Cp437.encode(text, "?"), b"Cr\x8ame br\x96l\x82e");
+        assert_eq!(CodePage::Cp1252.encode(text, "?"), b"Cr\xe8me br\xfbl\xe9e");
+        assert_eq!(CodePage::Cp437.decode(b"Cr\x8ame br\x96l\x82e"), text);
+    }
+
+    #[test]
+    fn euro_sign_depends_on_code_page() {
+        assert_eq!(CodePage::Cp858.encode("€5", "?"), b"\xd55");
+        assert_eq!(CodePage::Cp1252.encode("€5", "?"), b"\x805");
+        assert_eq!(CodePage::Cp850.encode("€5", "?"), b"EUR5");
+    }
+
+    #[test]
+    fn unmappable_characters_use_fallback() {
+        assert_eq!(CodePage::Cp437.encode("Pho 🍜", "?"), b"Pho ?");
+        assert_eq!(CodePage::Cp437.encode("Борщ", "*"), b"****");
+        assert_eq!(CodePage::Cp866.encode("Борщ", "*"), b"\x81\xae\xe0\xe9");
+        // Unassigned CP1252 slots never match.
+        assert_eq!(CodePage::Cp1252.encode("\u{fffd}", ""), b"");
+    }
+}
@@ -0,0 +1,1081 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::codepage::CodePage;
+use super::decode::Preflight;
+use super::drawer::DrawerSettings;
+use super::ipp::IppClient;
+use super::logo::{LogoMode, LogoSettings};
+use super::status::StatusMode;
+use super::transport::{SerialSettings, Timeouts};
+
+/// Id given to the printer described by a legacy single-object config or the
+/// `PRINTER_*` environment variables.
+pub const DEFAULT_PRINTER_ID: &str = "default";
+
+/// Width in dots of a character in ESC/POS font A.
+const FONT_A_WIDTH: usize = 12;
+/// Narrowest line a receipt layout is expected to fit.
+const MIN_COLUMNS: usize = 16;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PrinterRole {
+    #[default]
+    Receipt,
+    Kitchen,
+    Bar,
+    Label,
+    Office,
+}
+
+/// The command language a printer takes.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PrinterLanguage {
+    #[default]
+    #[serde(rename = "escpos")]
+    EscPos,
+    /// Zebra ZPL II label printers.
+    Zpl,
+    /// TSC TSPL label printers.
+    Tspl,
+    /// Star Line Mode receipt printers (TSP100, TSP650, mC-Print in Star
+    /// emulation). StarPRNT printers take the same commands.
+    Star,
+}
+
+impl PrinterLanguage {
+    /// Label languages print labels only, never receipts or documents.
+    pub fn is_label(&self) -> bool {
+        matches!(self, PrinterLanguage::Zpl | PrinterLanguage::Tspl)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Transport {
+    Tcp {
+        host: String,
+        #[serde(default = "default_port")]
+        port: u16,
+    },
+    /// LPD queue on a print server, for printers that do not take raw jobs
+    /// on 9100.
+    Lpd {
+        host: String,
+        #[serde(default = "default_lpd_port")]
+        port: u16,
+        #[serde(default = "default_lpd_queue")]
+        queue: String,
+    },
+    /// USB printer class device, e.g. `/dev/usb/lp0`.
+    Usb { path: String },
+    /// Serial port, e.g. `/dev/ttyUSB0` or `COM3`.
+    Serial {
+        path: String,
+        #[serde(flatten)]
+        settings: SerialSettings,
+    },
+    /// IPP printer or CUPS queue, e.g. `ipp://192.168.1.50/ipp/print`.
+    /// Takes PDF or plain text rather than ESC/POS.
+    Ipp { uri: String },
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::Tcp { host, port } => write!(f, "{}:{}", host, port),
+            Transport::Lpd { host, port, queue } => write!(f, "lpd://{}:{}/{}", host, port, queue),
+            Transport::Usb { path } | Transport::Serial { path, .. } => f.write_str(path),
+            Transport::Ipp { uri } => f.write_str(uri),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PrinterDefinition {
+    pub id: String,
+    #[serde(default)]
+    pub role: PrinterRole,
+    pub transport: Transport,
+    #[serde(default)]
+    pub language: PrinterLanguage,
+    /// Print head resolution, used to lay out labels.
+    #[serde(default = "default_dpi")]
+    pub dpi: u16,
+    /// Paper width in millimetres (58 or 80).
+    #[serde(default = "default_paper_width")]
+    pub paper_width: u16,
+    /// Characters per line. Defaults to what the paper width fits (32 at
+    /// 58mm, 48 at 80mm); set it for printers with a narrower print area,
+    /// such as 42 on some 80mm models.
+    #[serde(default)]
+    pub columns: Option<u16>,
+    #[serde(default)]
+    pub code_page: CodePage,
+    /// Printed in place of characters the code page cannot represent.
+    #[serde(default = "default_code_page_fallback")]
+    pub code_page_fallback: String,
+    #[serde(default)]
+    pub status: StatusMode,
+    #[serde(default)]
+    pub drawer: DrawerSettings,
+    /// Whether the printer supports `GS ( k` QR codes. When false, QR codes
+    /// are rendered in software and sent as raster images.
+    #[serde(default = "default_native_qr")]
+    pub native_qr: bool,
+    /// Logo printed at the top of receipts.
+    #[serde(default)]
+    pub logo: Option<LogoSettings>,
+    #[serde(default)]
+    pub timeouts: Timeouts,
+}
+
+/// Printers that stand in for each other. A job for the group, or for one of
+/// its members, moves down `members` in order when a printer cannot take it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PrinterGroup {
+    pub id: String,
+    /// Printer ids, primary first.
+    pub members: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PrinterConfig {
+    #[serde(default = "default_simulate")]
+    pub simulate: bool,
+    /// Printer used when a command does not name one. Falls back to the
+    /// first entry in `printers`.
+    #[serde(default)]
+    pub default_printer: Option<String>,
+    /// Checks run on raw ESC/POS passed to `print_receipt`.
+    #[serde(default)]
+    pub preflight: Preflight,
+    #[serde(default)]
+    pub printers: Vec<PrinterDefinition>,
+    #[serde(default)]
+    pub groups: Vec<PrinterGroup>,
+}
+
+/// A problem with one config field, named by its JSON path, e.g.
+/// `printers[1].transport.host`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Error returned by the config commands, with per-field details when the
+/// config failed validation.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ConfigError {
+    pub message: String,
+    pub fields: Vec<FieldError>,
+}
+
+impl From<String> for ConfigError {
+    fn from(message: String) -> Self {
+        ConfigError {
+            message,
+            fields: Vec::new(),
+        }
+    }
+}
+
+impl From<&str> for ConfigError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl From<Vec<FieldError>> for ConfigError {
+    fn from(fields: Vec<FieldError>) -> Self {
+        ConfigError {
+            message: format!("Printer config has {} invalid field(s)", fields.len()),
+            fields,
+        }
+    }
+}
+
+fn default_port() -> u16 {
+    9100
+}
+
+fn default_lpd_port() -> u16 {
+    515
+}
+
+fn default_lpd_queue() -> String {
+    "lp".to_string()
+}
+
+fn default_dpi() -> u16 {
+    203
+}
+
+fn default_paper_width() -> u16 {
+    80
+}
+
+fn default_code_page_fallback() -> String {
+    "?".to_string()
+}
+
+fn default_native_qr() -> bool {
+    true
+}
+
+fn default_simulate() -> bool {
+    true
+}
+
+impl PrinterDefinition {
+    pub fn tcp(id: &str, host: &str, port: u16) -> Self {
+        PrinterDefinition {
+            id: id.to_string(),
+            role: PrinterRole::Receipt,
+            transport: Transport::Tcp {
+                host: host.to_string(),
+                port,
+            },
+            language: PrinterLanguage::EscPos,
+            dpi: default_dpi(),
+            paper_width: default_paper_width(),
+            columns: None,
+            code_page: CodePage::default(),
+            code_page_fallback: default_code_page_fallback(),
+            status: StatusMode::None,
+            drawer: DrawerSettings::default(),
+            native_qr: default_native_qr(),
+            logo: None,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// Characters per line in the printer's standard font.
+    pub fn columns(&self) -> usize {
+        match self.columns {
+            Some(columns) => columns as usize,
+            None => self.paper_dots() / FONT_A_WIDTH,
+        }
+    }
+
+    /// Checks the fields the UI can get wrong. Field names are relative to
+    /// the printer entry.
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if self.id.trim().is_empty() {
+            errors.push(FieldError::new("id", "Printer id is required"));
+        }
+        match &self.transport {
+            Transport::Tcp { host, port } => {
+                if !is_valid_host(host) {
+                    errors.push(FieldError::new(
+                        "transport.host",
+                        format!("'{}' is not a valid IP address or hostname", host),
+                    ));
+                }
+                if *port == 0 {
+                    errors.push(FieldError::new("transport.port", "Port must be 1-65535"));
+                }
+            }
+            Transport::Lpd { host, port, queue } => {
+                if !is_valid_host(host) {
+                    errors.push(FieldError::new(
+                        "transport.host",
+                        format!("'{}' is not a valid IP address or hostname", host),
+                    ));
+                }
+                if *port == 0 {
+                    errors.push(FieldError::new("transport.port", "Port must be 1-65535"));
+                }
+                if queue.is_empty() || queue.chars().any(|c| c.is_whitespace() || c.is_control()) {
+                    errors.push(FieldError::new(
+                        "transport.queue",
+                        "Queue name is required and cannot contain spaces",
+                    ));
+                }
+                if self.status != StatusMode::None {
+                    errors.push(FieldError::new(
+                        "status",
+                        "LPD has no status channel; status must be none",
+                    ));
+                }
+            }
+            Transport::Usb { path } => {
+                if path.trim().is_empty() {
+                    errors.push(FieldError::new("transport.path", "Device path is required"));
+                }
+            }
+            Transport::Serial { path, settings } => {
+                if path.trim().is_empty() {
+                    errors.push(FieldError::new("transport.path", "Device path is required"));
+                }
+                if settings.baud_rate == 0 {
+                    errors.push(FieldError::new(
+                        "transport.baudRate",
+                        "Baud rate must be positive",
+                    ));
+                }
+                if !(5..=8).contains(&settings.data_bits) {
+                    errors.push(FieldError::new(
+                        "transport.dataBits",
+                        "Data bits must be 5 to 8",
+                    ));
+                }
+                if !(1..=2).contains(&settings.stop_bits) {
+                    errors.push(FieldError::new(
+                        "transport.stopBits",
+                        "Stop bits must be 1 or 2",
+                    ));
+                }
+            }
+            Transport::Ipp { uri } => {
+                if let Err(e) = IppClient::new(uri) {
+                    errors.push(FieldError::new("transport.uri", e));
+                }
+            }
+        }
+        let is_ipp = matches!(self.transport, Transport::Ipp { .. });
+        if self.language != PrinterLanguage::EscPos {
+            if is_ipp {
+                errors.push(FieldError::new(
+                    "language",
+                    "IPP printers take PDF or plain text, not label or Star commands",
+                ));
+            }
+            if self.status != StatusMode::None {
+                errors.push(FieldError::new(
+                    "status",
+                    "Status reads use ESC/POS and are not supported on label or Star printers",
+                ));
+            }
+        }
+        if self.language.is_label() {
+            if self.dpi == 0 {
+                errors.push(FieldError::new("dpi", "Resolution must be positive"));
+            }
+        } else if !is_ipp && self.paper_width != 58 && self.paper_width != 80 {
+            errors.push(FieldError::new(
+                "paperWidth",
+                "Paper width must be 58 or 80 mm",
+            ));
+        } else if let Some(columns) = self.columns {
+            let most = self.paper_dots() / FONT_A_WIDTH;
+            if !(MIN_COLUMNS..=most).contains(&(columns as usize)) {
+                errors.push(FieldError::new(
+                    "columns",
+                    format!(
+                        "Columns must be {}-{} on {}mm paper",
+                        MIN_COLUMNS, most, self.paper_width
+                    ),
+                ));
+            }
+        }
+        if let Err(e) = self.drawer.validate() {
+            errors.push(FieldError::new("drawer", e));
+        }
+        if let Some(logo) = &self.logo {
+            if logo.path.trim().is_empty() {
+                errors.push(FieldError::new("logo.path", "Logo path is required"));
+            }
+            if logo.mode == LogoMode::Nv && self.language == PrinterLanguage::Star {
+                errors.push(FieldError::new(
+                    "logo.mode",
+                    "Star printers have no NV graphics; use the raster logo mode",
+                ));
+            }
+            if logo.nv_key.len() != 2 {
+                errors.push(FieldError::new(
+                    "logo.nvKey",
+                    "NV key must be two characters",
+                ));
+            }
+        }
+        for (field, ms) in [
+            ("timeouts.connectMs", self.timeouts.connect_ms),
+            ("timeouts.writeMs", self.timeouts.write_ms),
+            ("timeouts.readMs", self.timeouts.read_ms),
+        ] {
+            if ms == 0 {
+                errors.push(FieldError::new(field, "Timeout must be positive"));
+            }
+        }
+        errors
+    }
+
+    /// Printable width in dots at 203 dpi: the paper's, or the `columns`
+    /// set for a narrower print area.
+    pub fn dots(&self) -> usize {
+        match self.columns {
+            Some(columns) => (columns as usize * FONT_A_WIDTH).min(self.paper_dots()),
+            None => self.paper_dots(),
+        }
+    }
+
+    fn paper_dots(&self) -> usize {
+        if self.paper_width <= 58 {
+            384
+        } else {
+            576
+        }
+    }
+}
+
+impl Default for PrinterConfig {
+    fn default() -> Self {
+        PrinterConfig {
+            simulate: default_simulate(),
+            default_printer: None,
+            preflight: Preflight::default(),
+            printers: vec![PrinterDefinition::tcp(
+                DEFAULT_PRINTER_ID,
+                "127.0.0.1",
+                9100,
+            )],
+            groups: Vec::new(),
+        }
+    }
+}
+
+impl PrinterConfig {
+    /// Resolves a printer by id, or the default printer when `id` is `None`.
+    /// A group id resolves to the group's primary.
+    pub fn printer(&self, id: Option<&str>) -> Result<&PrinterDefinition, String> {
+        let id = id.or(self.default_printer.as_deref());
+        let group = id.and_then(|id| self.groups.iter().find(|g| g.id == id));
+        let id = match group {
+            Some(group) => Some(
+                group
+                    .members
+                    .first()
+                    .ok_or_else(|| format!("Printer group '{}' has no members", group.id))?
+                    .as_str(),
+            ),
+            None => id,
+        };
+        match id {
+            Some(id) => self
+                .printers
+                .iter()
+                .find(|p| p.id == id)
+                .ok_or_else(|| format!("Unknown printer '{}'", id)),
+            None => self
+                .printers
+                .first()
+                .ok_or_else(|| "No printers configured".to_string()),
+        }
+    }
+
+    /// Resolves a printer by id or, when `id` is `None`, the first printer
+    /// with `role`, falling back to the default printer.
+    pub fn printer_for(
+        &self,
+        id: Option<&str>,
+        role: PrinterRole,
+    ) -> Result<&PrinterDefinition, String> {
+        if id.is_none() {
+            if let Some(printer) = self.printers.iter().find(|p| p.role == role) {
+                return Ok(printer);
+            }
+        }
+        self.printer(id)
+    }
+
+    /// Parses either the registry format (`{"printers": [...]}`) or the
+    /// legacy single-printer object (`{"host", "port", "simulate"}`).
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let mut config = if json.get("printers").is_some() {
+            serde_json::from_value::<PrinterConfig>(json.clone())
+                .map_err(|e| format!("Invalid printer registry: {}", e))?
+        } else {
+            PrinterConfig {
+                simulate: json["simulate"].as_bool().unwrap_or(true),
+                default_printer: None,
+                preflight: Preflight::default(),
+                printers: vec![PrinterDefinition::tcp(
+                    DEFAULT_PRINTER_ID,
+                    json["host"].as_str().unwrap_or("127.0.0.1"),
+                    json["port"].as_u64().unwrap_or(9100) as u16,
+                )],
+                groups: Vec::new(),
+            }
+        };
+        if config.printers.is_empty() {
+            config.printers = PrinterConfig::default().printers;
+        }
+        Ok(config)
+    }
+
+    /// `printer` followed by the other members of the groups it belongs to,
+    /// in group order, each once.
+    pub fn failover_chain<'a>(
+        &'a self,
+        printer: &'a PrinterDefinition,
+    ) -> Vec<&'a PrinterDefinition> {
+        let mut chain = vec![printer];
+        for group in self
+            .groups
+            .iter()
+            .filter(|g| g.members.contains(&printer.id))
+        {
+            for member in &group.members {
+                if let Some(next) = self.printers.iter().find(|p| &p.id == member) {
+                    if !chain.iter().any(|p| p.id == next.id) {
+                        chain.push(next);
+                    }
+                }
+            }
+        }
+        chain
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if self.printers.is_empty() {
+            errors.push(FieldError::new(
+                "printers",
+                "At least one printer is required",
+            ));
+        }
+        for (i, printer) in self.printers.iter().enumerate() {
+            if self.printers[..i].iter().any(|p| p.id == printer.id) {
+                errors.push(FieldError::new(
+                    &format!("printers[{}].id", i),
+                    format!("Duplicate printer id '{}'", printer.id),
+                ));
+            }
+            errors.extend(printer.validate().into_iter().map(|e| FieldError {
+                field: format!("printers[{}].{}", i, e.field),
+                message: e.message,
+            }));
+        }
+        for (i, group) in self.groups.iter().enumerate() {
+            errors.extend(self.validate_group(group).into_iter().map(|e| FieldError {
+                field: format!("groups[{}].{}", i, e.field),
+                message: e.message,
+            }));
+            if self.groups[..i].iter().any(|g| g.id == group.id) {
+                errors.push(FieldError::new(
+                    &format!("groups[{}].id", i),
+                    format!("Duplicate group id '{}'", group.id),
+                ));
+            }
+        }
+        if let Some(id) = &self.default_printer {
+            let known = self.printers.iter().any(|p| &p.id == id)
+                || self.groups.iter().any(|g| &g.id == id);
+            if !known {
+                errors.push(FieldError::new(
+                    "defaultPrinter",
+                    format!("No printer or group with id '{}'", id),
+                ));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn validate_group(&self, group: &PrinterGroup) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if group.id.trim().is_empty() {
+            errors.push(FieldError::new("id", "Group id is required"));
+        } else if self.printers.iter().any(|p| p.id == group.id) {
+            errors.push(FieldError::new(
+                "id",
+                format!("Group id '{}' is already a printer id", group.id),
+            ));
+        }
+        if group.members.is_empty() {
+            errors.push(FieldError::new(
+                "members",
+                "At least one member is required",
+            ));
+        }
+        let mut accepts = None;
+        let mut fits = None;
+        for (j, member) in group.members.iter().enumerate() {
+            let field = format!("members[{}]", j);
+            let printer = match self.printers.iter().find(|p| &p.id == member) {
+                Some(printer) => printer,
+                None => {
+                    errors.push(FieldError::new(
+                        &field,
+                        format!("No printer with id '{}'", member),
+                    ));
+                    continue;
+                }
+            };
+            if group.members[..j].contains(member) {
+                errors.push(FieldError::new(
+                    &field,
+                    format!("Duplicate member '{}'", member),
+                ));
+            }
+            // Jobs are rendered for the member they were addressed to, so
+            // members must all take the same kind of print data.
+            let kind = (
+                matches!(printer.transport, Transport::Ipp { .. }),
+                printer.language,
+            );
+            if *accepts.get_or_insert(kind) != kind {
+                errors.push(FieldError::new(
+                    &field,
+                    "Members must all be IPP printers or all use the same command language",
+                ));
+            }
+            // A job laid out for one member must fit on the others.
+            let width = (printer.columns(), printer.dots());
+            if *fits.get_or_insert(width) != width {
+                errors.push(FieldError::new(
+                    &field,
+                    "Members must all have the same paper width and columns",
+                ));
+            }
+        }
+        errors
+    }
+
+    /// Points the default printer at `host:port`, replacing its transport.
+    /// A default group is overridden through its primary.
+    fn override_default(&mut self, host: String, port: u16) {
+        let id = match self.printer(None) {
+            Ok(printer) => printer.id.clone(),
+            Err(_) => self
+                .default_printer
+                .clone()
+                .unwrap_or_else(|| DEFAULT_PRINTER_ID.to_string()),
+        };
+        match self.printers.iter_mut().find(|p| p.id == id) {
+            Some(printer) => printer.transport = Transport::Tcp { host, port },
+            None => self
+                .printers
+                .insert(0, PrinterDefinition::tcp(&id, &host, port)),
+        }
+    }
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".chefcloud").join("printer.json"))
+}
+
+fn is_valid_host(host: &str) -> bool {
+    if host.parse::<IpAddr>().is_ok() {
+        return true;
+    }
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Reads a config file, or `Ok(None)` when it does not exist.
+pub fn read_config_file(path: &Path) -> Result<Option<PrinterConfig>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let json = serde_json::from_str::<Value>(&content)
+        .map_err(|e| format!("Invalid JSON in {}: {}", path.display(), e))?;
+    PrinterConfig::from_json(&json).map(Some)
+}
+
+/// Validates `config` and writes it in the registry format. The file is
+/// written to a temporary sibling and renamed over the original, so a
+/// crash never leaves a half-written config behind.
+pub fn write_config_file(path: &Path, config: &PrinterConfig) -> Result<(), ConfigError> {
+    config.validate()?;
+    let json = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        format!("Failed to replace {}: {}", path.display(), e)
+    })?;
+    Ok(())
+}
+
+/// Reads and validates the config at `path`, or the defaults when there is
+/// no file, then applies the `PRINTER_*` environment overrides.
+pub fn load_config(path: Option<&Path>) -> Result<PrinterConfig, String> {
+    let mut config = match path {
+        Some(path) => read_config_file(path)?.unwrap_or_default(),
+        None => PrinterConfig::default(),
+    };
+    config.validate().map_err(|errors| {
+        let details: Vec<String> = errors
+            .iter()
+            .map(|e| format!("{}: {}", e.field, e.message))
+            .collect();
+        format!("Invalid printer config: {}", details.join("; "))
+    })?;
+
+    // Environment variables take priority for the default printer
+    if let Ok(simulate) = env::var("PRINTER_SIMULATE") {
+        config.simulate = simulate == "true";
+        config.override_default(
+            env::var("PRINTER_HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
+            env::var("PRINTER_PORT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(9100),
+        );
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::transport::{FlowControl, Parity};
+    use serde_json::json;
+
+    #[test]
+    fn legacy_object_becomes_default_printer() {
+        let config = PrinterConfig::from_json(&json!({
+            "host": "10.0.0.50",
+            "port": 9999,
+            "simulate": false
+        }))
+        .unwrap();
+
+        assert!(!config.simulate);
+        let printer = config.printer(None).unwrap();
+        assert_eq!(printer.id, DEFAULT_PRINTER_ID);
+        assert_eq!(
+            printer.transport,
+            Transport::Tcp {
+                host: "10.0.0.50".to_string(),
+                port: 9999
+            }
+        );
+    }
+
+    #[test]
+    fn registry_resolves_named_and_default_printers() {
+        let config = PrinterConfig::from_json(&json!({
+            "simulate": false,
+            "defaultPrinter": "receipt",
+            "printers": [
+                { "id": "kitchen-1", "role": "kitchen", "paperWidth": 58,
+                  "transport": { "type": "tcp", "host": "10.0.0.21" } },
+                { "id": "receipt", "codePage": "cp858",
+                  "transport": { "type": "tcp", "host": "10.0.0.20", "port": 9101 } }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(config.printer(None).unwrap().id, "receipt");
+        let kitchen = config.printer(Some("kitchen-1")).unwrap();
+        assert_eq!(kitchen.role, PrinterRole::Kitchen);
+        assert_eq!(kitchen.columns(), 32);
+        assert!(config.printer(Some("bar")).is_err());
+        assert_eq!(
+            config.printer_for(None, PrinterRole::Kitchen).unwrap().id,
+            "kitchen-1"
+        );
+        assert_eq!(
+            config.printer_for(None, PrinterRole::Bar).unwrap().id,
+            "receipt"
+        );
+    }
+
+    #[test]
+    fn parses_usb_and_serial_transports() {
+        let config = PrinterConfig::from_json(&json!({
+            "printers": [
+                { "id": "counter", "transport": { "type": "usb", "path": "/dev/usb/lp0" } },
+                { "id": "legacy", "transport": { "type": "serial", "path": "/dev/ttyUSB0",
+                  "baudRate": 38400, "parity": "even", "flowControl": "hardware" } }
+            ]
+        }))
+        .unwrap();
+
+        let counter = config.printer(Some("counter")).unwrap();
+        assert_eq!(counter.transport.to_string(), "/dev/usb/lp0");
+        match &config.printer(Some("legacy")).unwrap().transport {
+            Transport::Serial { path, settings } => {
+                assert_eq!(path, "/dev/ttyUSB0");
+                assert_eq!(settings.baud_rate, 38400);
+                assert_eq!(settings.parity, Parity::Even);
+                assert_eq!(settings.flow_control, FlowControl::Hardware);
+                assert_eq!((settings.data_bits, settings.stop_bits), (8, 1));
+            }
+            other => panic!("unexpected transport {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_lpd_transport_with_defaults() {
+        let mut printer: PrinterDefinition = serde_json::from_value(json!({
+            "id": "kitchen", "transport": { "type": "lpd", "host": "10.0.0.60" }
+        }))
+        .unwrap();
+        assert_eq!(printer.transport.to_string(), "lpd://10.0.0.60:515/lp");
+        assert!(printer.validate().is_empty());
+
+        printer.transport = Transport::Lpd {
+            host: "10.0.0.60".to_string(),
+            port: 515,
+            queue: "raw queue".to_string(),
+        };
+        printer.status = StatusMode::Query;
+        let fields: Vec<String> = printer.validate().into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["transport.queue", "status"]);
+    }
+
+    #[test]
+    fn validation_reports_field_errors() {
+        let mut config = PrinterConfig::from_json(&json!({
+            "defaultPrinter": "missing",
+            "printers": [
+                { "id": "receipt", "paperWidth": 72,
+                  "transport": { "type": "tcp", "host": "bad host!", "port": 0 } },
+                { "id": "receipt", "transport": { "type": "serial", "path": "", "stopBits": 3 } },
+                { "id": "office", "transport": { "type": "ipp", "uri": "http//nope" } }
+            ]
+        }))
+        .unwrap();
+
+        let fields: Vec<String> = config
+            .validate()
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                "printers[0].transport.host",
+                "printers[0].transport.port",
+                "printers[0].paperWidth",
+                "printers[1].id",
+                "printers[1].transport.path",
+                "printers[1].transport.stopBits",
+                "printers[2].transport.uri",
+                "defaultPrinter",
+            ]
+        );
+
+        config.printers = vec![PrinterDefinition::tcp("receipt", "pos-printer.local", 9100)];
+        config.default_printer = None;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn label_printers_skip_receipt_checks() {
+        let mut printer: PrinterDefinition = serde_json::from_value(json!({
+            "id": "labels", "role": "label", "language": "zpl", "dpi": 300, "paperWidth": 104,
+            "transport": { "type": "tcp", "host": "10.0.0.40" }
+        }))
+        .unwrap();
+        assert_eq!(printer.language, PrinterLanguage::Zpl);
+        assert!(printer.validate().is_empty());
+
+        printer.status = StatusMode::Query;
+        printer.transport = Transport::Ipp {
+            uri: "ipp://10.0.0.40/ipp/print".to_string(),
+        };
+        let fields: Vec<String> = printer.validate().into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["language", "status"]);
+    }
+
+    #[test]
+    fn star_printers_keep_receipt_checks() {
+        let printer: PrinterDefinition = serde_json::from_value(json!({
+            "id": "front", "language": "star", "paperWidth": 80, "status": "query",
+            "transport": { "type": "tcp", "host": "10.0.0.30" },
+            "logo": { "path": "/logo.png", "mode": "nv" }
+        }))
+        .unwrap();
+        assert_eq!(printer.language, PrinterLanguage::Star);
+        assert!(!printer.language.is_label());
+        let fields: Vec<String> = printer.validate().into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["status", "logo.mode"]);
+    }
+
+    #[test]
+    fn columns_default_to_the_paper_width() {
+        let mut printer: PrinterDefinition = serde_json::from_value(json!({
+            "id": "bar", "paperWidth": 80, "transport": { "type": "tcp", "host": "10.0.0.22" }
+        }))
+        .unwrap();
+        assert_eq!((printer.columns(), printer.dots()), (48, 576));
+
+        printer.columns = Some(42);
+        assert_eq!((printer.columns(), printer.dots()), (42, 504));
+        assert!(printer.validate().is_empty());
+
+        printer.paper_width = 58;
+        let errors = printer.validate();
+        assert_eq!(errors[0].field, "columns");
+        assert_eq!(errors[0].message, "Columns must be 16-32 on 58mm paper");
+    }
+
+    #[test]
+    fn timeouts_default_per_field() {
+        let mut printer: PrinterDefinition = serde_json::from_value(json!({
+            "id": "kitchen", "transport": { "type": "tcp", "host": "10.0.0.21" },
+            "timeouts": { "connectMs": 500 }
+        }))
+        .unwrap();
+        assert_eq!(
+            printer.timeouts,
+            Timeouts {
+                connect_ms: 500,
+                ..Timeouts::default()
+            }
+        );
+        printer.timeouts.read_ms = 0;
+        let fields: Vec<String> = printer.validate().into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["timeouts.readMs"]);
+    }
+
+    #[test]
+    fn groups_resolve_to_primary_and_chain_members() {
+        let mut config = PrinterConfig::from_json(&json!({
+            "defaultPrinter": "kitchen",
+            "printers": [
+                { "id": "hot", "transport": { "type": "tcp", "host": "10.0.0.21" } },
+                { "id": "cold", "transport": { "type": "tcp", "host": "10.0.0.22" } },
+                { "id": "receipt", "transport": { "type": "tcp", "host": "10.0.0.20" } }
+            ],
+            "groups": [{ "id": "kitchen", "members": ["hot", "cold", "receipt"] }]
+        }))
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let primary = config.printer(None).unwrap();
+        assert_eq!(primary.id, "hot");
+        let ids = |chain: Vec<&PrinterDefinition>| -> Vec<String> {
+            chain.into_iter().map(|p| p.id.clone()).collect()
+        };
+        assert_eq!(
+            ids(config.failover_chain(primary)),
+            ["hot", "cold", "receipt"]
+        );
+        let cold = config.printer(Some("cold")).unwrap();
+        assert_eq!(ids(config.failover_chain(cold)), ["cold", "hot", "receipt"]);
+
+        config.printers[1].paper_width = 58;
+        config.printers[2].transport = Transport::Ipp {
+            uri: "ipp://10.0.0.20/ipp/print".to_string(),
+        };
+        config.groups.push(PrinterGroup {
+            id: "hot".to_string(),
+            members: vec!["bar".to_string()],
+        });
+        let fields: Vec<String> = config
+            .validate()
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                "groups[0].members[1]",
+                "groups[0].members[2]",
+                "groups[1].id",
+                "groups[1].members[0]",
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_config_atomically_and_rejects_invalid() {
+        let dir = std::env::temp_dir().join(format!("chefcloud-config-{}", std::process::id()));
+        let path = dir.join("printer.json");
+        let _ = fs::remove_file(&path);
+
+        let mut config = PrinterConfig {
+            simulate: false,
+            ..PrinterConfig::default()
+        };
+        config.printers[0].transport = Transport::Usb {
+            path: "/dev/usb/lp0".to_string(),
+        };
+        write_config_file(&path, &config).unwrap();
+        assert_eq!(read_config_file(&path).unwrap(), Some(config.clone()));
+        assert!(!path.with_extension("json.tmp").exists());
+
+        config.printers[0].id = String::new();
+        let err = write_config_file(&path, &config).unwrap_err();
+        assert_eq!(err.fields[0].field, "printers[0].id");
+        assert_eq!(
+            read_config_file(&path).unwrap().unwrap().printers[0].id,
+            DEFAULT_PRINTER_ID
+        );
+
+        fs::write(&path, "{ not json").unwrap();
+        assert!(read_config_file(&path).is_err());
+        assert_eq!(read_config_file(&dir.join("missing.json")), Ok(None));
+    }
+
+    #[test]
+    fn env_override_replaces_default_printer_transport() {
+        let mut config = PrinterConfig::default();
+        config.override_default("192.168.1.100".to_string(), 9200);
+        assert_eq!(
+            config.printer(None).unwrap().transport,
+            Transport::Tcp {
+                host: "192.168.1.100".to_string(),
+                port: 9200
+            }
+        );
+
+        let mut config = PrinterConfig::from_json(&json!({
+            "defaultPrinter": "kitchen",
+            "printers": [
+                { "id": "hot", "transport": { "type": "tcp", "host": "10.0.0.21" } },
+                { "id": "cold", "transport": { "type": "tcp", "host": "10.0.0.22" } }
+            ],
+            "groups": [{ "id": "kitchen", "members": ["hot", "cold"] }]
+        }))
+        .unwrap();
+        config.override_default("192.168.1.100".to_string(), 9200);
+        assert_eq!(config.printers.len(), 2);
+        assert_eq!(config.printer(None).unwrap().id, "hot");
+        assert_eq!(
+            config.printers[0].transport,
+            Transport::Tcp {
+                host: "192.168.1.100".to_string(),
+                port: 9200
+            }
+        );
+        assert!(config.validate().is_ok());
+    }
+}
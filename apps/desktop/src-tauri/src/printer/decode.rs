@@ -0,0 +1,747 @@
+//! Decodes ESC/POS byte streams back into commands, checks them against the
+//! target printer, and renders them as a plain-text preview of what the
+//! printer would produce.
+
+use serde::{Deserialize, Serialize};
+
+use super::codepage::CodePage;
+use super::config::{PrinterDefinition, PrinterLanguage, Transport};
+use super::escpos::Align;
+
+const LF: u8 = 0x0a;
+const HT: u8 = 0x09;
+const DLE: u8 = 0x10;
+const ESC: u8 = 0x1b;
+const GS: u8 = 0x1d;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum Command {
+    /// Printable bytes, decoded in the code page selected at that point.
+    Text {
+        text: String,
+    },
+    LineFeed,
+    Init,
+    Bold {
+        on: bool,
+    },
+    Underline {
+        mode: u8,
+    },
+    Invert {
+        on: bool,
+    },
+    Align {
+        align: Align,
+    },
+    /// `ESC !` - font, bold, double size and underline in one byte.
+    PrintMode {
+        mode: u8,
+    },
+    Size {
+        width: u8,
+        height: u8,
+    },
+    Feed {
+        lines: u8,
+    },
+    Cut {
+        partial: bool,
+    },
+    CodePage {
+        table: u8,
+    },
+    DrawerKick {
+        pin: u8,
+        on_ms: u16,
+        off_ms: u16,
+    },
+    BarcodeHeight {
+        dots: u8,
+    },
+    BarcodeWidth {
+        module: u8,
+    },
+    BarcodeHri {
+        position: u8,
+    },
+    Barcode {
+        system: u8,
+        data: String,
+    },
+    /// `GS ( k` QR code function; `data` is set for the store function (80).
+    Qr {
+        function: u8,
+        data: Option<String>,
+    },
+    Raster {
+        width: usize,
+        height: usize,
+    },
+    /// `GS ( L` / `GS 8 L` NV graphics function.
+    NvGraphic {
+        function: u8,
+    },
+    StatusQuery {
+        n: u8,
+    },
+    EnableAsb {
+        mask: u8,
+    },
+    /// A recognised command with no effect on the preview, e.g. `ESC M`.
+    Setting {
+        command: String,
+    },
+    Unknown {
+        bytes: Vec<u8>,
+    },
+    /// The stream ended part-way through a command.
+    Truncated {
+        bytes: Vec<u8>,
+    },
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Decoded {
+    /// Byte offset of the command in the stream.
+    pub offset: usize,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+pub fn decode(bytes: &[u8]) -> Vec<Decoded> {
+    let mut commands = Vec::new();
+    let mut code_page = CodePage::default();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let (command, len) = decode_one(&bytes[pos..], code_page).unwrap_or_else(|| {
+            (
+                Command::Truncated {
+                    bytes: bytes[pos..].to_vec(),
+                },
+                bytes.len() - pos,
+            )
+        });
+        match command {
+            Command::CodePage { table } => {
+                code_page = CodePage::from_escpos_table(table).unwrap_or(code_page);
+            }
+            Command::Init => code_page = CodePage::default(),
+            _ => {}
+        }
+        commands.push(Decoded {
+            offset: pos,
+            command,
+        });
+        pos += len;
+    }
+    commands
+}
+
+fn setting(name: &str, len: usize) -> (Command, usize) {
+    (
+        Command::Setting {
+            command: name.to_string(),
+        },
+        len,
+    )
+}
+
+/// Decodes the command at the start of `b` and returns it with its length,
+/// or `None` when `b` ends before the command does.
+fn decode_one(b: &[u8], code_page: CodePage) -> Option<(Command, usize)> {
+    let arg = |i: usize| b.get(i).copied();
+    let unknown = |len: usize| {
+        let len = len.min(b.len());
+        (
+            Command::Unknown {
+                bytes: b[..len].to_vec(),
+            },
+            len,
+        )
+    };
+    Some(match b[0] {
+        LF => (Command::LineFeed, 1),
+        0x0d => setting("CR", 1),
+        ESC => match arg(1)? {
+            b'@' => (Command::Init, 2),
+            b'E' => (
+                Command::Bold {
+                    on: arg(2)? & 1 == 1,
+                },
+                3,
+            ),
+            b'-' => (
+                Command::Underline {
+                    mode: arg(2)? & 0x03,
+                },
+                3,
+            ),
+            b'a' => {
+                let align = match arg(2)? & 0x03 {
+                    1 => Align::Center,
+                    2 => Align::Right,
+                    _ => Align::Left,
+                };
+                (Command::Align { align }, 3)
+            }
+            b'!' => (Command::PrintMode { mode: arg(2)? }, 3),
+            b'd' => (Command::Feed { lines: arg(2)? }, 3),
+            b't' => (Command::CodePage { table: arg(2)? }, 3),
+            b'p' => {
+                let (m, t1, t2) = (arg(2)?, arg(3)?, arg(4)?);
+                let pin = if m & 1 == 0 { 2 } else { 5 };
+                let (on_ms, off_ms) = (t1 as u16 * 2, t2 as u16 * 2);
+                (Command::DrawerKick { pin, on_ms, off_ms }, 5)
+            }
+            b'i' => (Command::Cut { partial: false }, 2),
+            b'm' => (Command::Cut { partial: true }, 2),
+            b'2' => setting("ESC 2", 2),
+            op @ (b'G' | b'M' | b'R' | b'3' | b'J' | b'V' | b'{' | b' ' | b'U') => {
+                arg(2)?;
+                setting(&format!("ESC {}", op as char), 3)
+            }
+            _ => unknown(2),
+        },
+        GS => match arg(1)? {
+            b'!' => {
+                let n = arg(2)?;
+                let (width, height) = ((n >> 4) + 1, (n & 0x0f) + 1);
+                (Command::Size { width, height }, 3)
+            }
+            b'B' => (
+                Command::Invert {
+                    on: arg(2)? & 1 == 1,
+                },
+                3,
+            ),
+            b'V' => match arg(2)? {
+                0 | 48 => (Command::Cut { partial: false }, 3),
+                1 | 49 => (Command::Cut { partial: true }, 3),
+                65 | 66 => {
+                    arg(3)?;
+                    (
+                        Command::Cut {
+                            partial: arg(2)? == 66,
+                        },
+                        4,
+                    )
+                }
+                _ => unknown(3),
+            },
+            b'h' => (Command::BarcodeHeight { dots: arg(2)? }, 3),
+            b'w' => (Command::BarcodeWidth { module: arg(2)? }, 3),
+            b'H' => (
+                Command::BarcodeHri {
+                    position: arg(2)? & 0x03,
+                },
+                3,
+            ),
+            b'k' => {
+                let system = arg(2)?;
+                let (data, len) = if system <= 6 {
+                    let end = 3 + b[3..].iter().position(|&c| c == 0)?;
+                    (&b[3..end], end + 1)
+                } else if (65..=73).contains(&system) {
+                    let n = arg(3)? as usize;
+                    (b.get(4..4 + n)?, 4 + n)
+                } else {
+                    return Some(unknown(3));
+                };
+                let data = String::from_utf8_lossy(data).into_owned();
+                (Command::Barcode { system, data }, len)
+            }
+            b'(' => {
+                let kind = arg(2)?;
+                let len = 5 + arg(3)? as usize + arg(4)? as usize * 256;
+                let body = b.get(5..len)?;
+                match kind {
+                    b'k' if body.len() >= 2 => {
+                        let function = body[1];
+                        let data = (function == 80).then(|| {
+                            String::from_utf8_lossy(body.get(3..).unwrap_or(&[])).into_owned()
+                        });
+                        (Command::Qr { function, data }, len)
+                    }
+                    b'L' if body.len() >= 2 => (Command::NvGraphic { function: body[1] }, len),
+                    other => setting(&format!("GS ( {}", other as char), len),
+                }
+            }
+            b'8' => {
+                if arg(2)? != b'L' {
+                    return Some(unknown(3));
+                }
+                let size = u32::from_le_bytes([arg(3)?, arg(4)?, arg(5)?, arg(6)?]) as usize;
+                let len = 7 + size;
+                b.get(..len)?;
+                (Command::NvGraphic { function: arg(8)? }, len)
+            }
+            b'v' => {
+                if arg(2)? != b'0' {
+                    return Some(unknown(3));
+                }
+                let x = arg(4)? as usize + arg(5)? as usize * 256;
+                let y = arg(6)? as usize + arg(7)? as usize * 256;
+                let len = 8 + x * y;
+                b.get(..len)?;
+                (
+                    Command::Raster {
+                        width: x * 8,
+                        height: y,
+                    },
+                    len,
+                )
+            }
+            b'a' => (Command::EnableAsb { mask: arg(2)? }, 3),
+            op @ (b'f' | b'I' | b'r') => {
+                arg(2)?;
+                setting(&format!("GS {}", op as char), 3)
+            }
+            op @ (b'L' | b'W') => {
+                arg(3)?;
+                setting(&format!("GS {}", op as char), 4)
+            }
+            _ => unknown(2),
+        },
+        DLE => match arg(1)? {
+            0x04 => (Command::StatusQuery { n: arg(2)? }, 3),
+            0x05 => {
+                arg(2)?;
+                setting("DLE ENQ", 3)
+            }
+            0x14 if arg(2)? == 1 => {
+                let (m, t) = (arg(3)?, arg(4)?);
+                let pin = if m == 0 { 2 } else { 5 };
+                let ms = t as u16 * 100;
+                let kick = Command::DrawerKick {
+                    pin,
+                    on_ms: ms,
+                    off_ms: ms,
+                };
+                (kick, 5)
+            }
+            _ => unknown(2),
+        },
+        HT | 0x20.. => {
+            let len = b
+                .iter()
+                .position(|&c| c < 0x20 && c != HT)
+                .unwrap_or(b.len());
+            let text = code_page.decode(&b[..len]);
+            (Command::Text { text }, len)
+        }
+        _ => unknown(1),
+    })
+}
+
+/// What `print_receipt` does when the bytes it is given fail [`lint`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Preflight {
+    Off,
+    /// Print anyway, logging the issues and emitting `print-preflight-warning`.
+    #[default]
+    Warn,
+    Reject,
+}
+
+/// A problem found in a print stream.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Issue {
+    pub offset: usize,
+    pub message: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Inspection {
+    pub commands: Vec<Decoded>,
+    pub issues: Vec<Issue>,
+    pub preview: String,
+}
+
+/// Decodes, checks and previews `bytes` as they would print on `printer`.
+pub fn inspect(bytes: &[u8], printer: &PrinterDefinition) -> Inspection {
+    let commands = decode(bytes);
+    Inspection {
+        issues: lint(&commands, printer),
+        preview: preview(&commands, printer.columns()),
+        commands,
+    }
+}
+
+/// Flags unknown and truncated commands, commands `printer` cannot carry out,
+/// and bold toggles that do not pair up.
+pub fn lint(commands: &[Decoded], printer: &PrinterDefinition) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut issue = |offset: usize, message: String| issues.push(Issue { offset, message });
+    if let Transport::Ipp { .. } = printer.transport {
+        issue(
+            0,
+            format!(
+                "Printer '{}' uses IPP and does not accept ESC/POS",
+                printer.id
+            ),
+        );
+    } else if printer.language == PrinterLanguage::Star {
+        issue(
+            0,
+            format!(
+                "Printer '{}' is in Star Line Mode and does not accept ESC/POS",
+                printer.id
+            ),
+        );
+    } else if printer.language != PrinterLanguage::EscPos {
+        issue(
+            0,
+            format!(
+                "Printer '{}' is a {:?} label printer and does not accept ESC/POS",
+                printer.id, printer.language
+            ),
+        );
+    }
+
+    // Offset of the command that turned bold on, while it is on.
+    let mut bold_from = None;
+    for Decoded { offset, command } in commands {
+        let offset = *offset;
+        let bold = match command {
+            Command::Bold { on } => Some(*on),
+            Command::PrintMode { mode } => Some(mode & 0x08 != 0),
+            Command::Init => {
+                bold_from = None;
+                None
+            }
+            _ => None,
+        };
+        match (bold, bold_from) {
+            (Some(true), None) => bold_from = Some(offset),
+            (Some(false), None) => issue(offset, "Bold turned off but was not on".to_string()),
+            (Some(false), Some(_)) => bold_from = None,
+            _ => {}
+        }
+
+        match command {
+            Command::Unknown { bytes } => {
+                issue(offset, format!("Unknown command {}", hex(bytes)));
+            }
+            Command::Truncated { bytes } => {
+                issue(offset, format!("Stream ends inside command {}", hex(bytes)));
+            }
+            Command::CodePage { table } if CodePage::from_escpos_table(*table).is_none() => {
+                issue(offset, format!("Unsupported code page table {}", table));
+            }
+            Command::Size { width, height } if *width > 8 || *height > 8 => {
+                issue(
+                    offset,
+                    format!("Character size {}x{} exceeds 8x8", width, height),
+                );
+            }
+            Command::Qr { function: 81, .. } if !printer.native_qr => issue(
+                offset,
+                format!("Printer '{}' does not support native QR codes", printer.id),
+            ),
+            Command::Raster { width, .. } if *width > printer.dots() => issue(
+                offset,
+                format!(
+                    "Image is {} dots wide but printer '{}' prints {}",
+                    width,
+                    printer.id,
+                    printer.dots()
+                ),
+            ),
+            _ => {}
+        }
+    }
+    if let Some(offset) = bold_from {
+        issue(offset, "Bold turned on and never turned off".to_string());
+    }
+    issues
+}
+
+/// The first bytes of a command, for messages.
+fn hex(bytes: &[u8]) -> String {
+    let shown: Vec<String> = bytes.iter().take(8).map(|b| format!("{:02x}", b)).collect();
+    let more = if bytes.len() > 8 { " ..." } else { "" };
+    format!("{}{}", shown.join(" "), more)
+}
+
+/// Renders decoded commands as the text a receipt would show. Bold text is
+/// wrapped in `**`, and cuts, barcodes, QR codes, images and drawer kicks
+/// are shown as markers.
+pub fn preview(commands: &[Decoded], columns: usize) -> String {
+    let mut out = Preview {
+        columns,
+        ..Preview::default()
+    };
+    let mut qr_data = String::new();
+    for decoded in commands {
+        match &decoded.command {
+            Command::Text { text } => out.text(text),
+            Command::LineFeed => out.flush(true),
+            Command::Init => {
+                out.flush(false);
+                out.bold = false;
+                out.align = Align::Left;
+                out.width = 1;
+            }
+            Command::Bold { on } => out.set_bold(*on),
+            Command::PrintMode { mode } => {
+                out.set_bold(mode & 0x08 != 0);
+                out.width = if mode & 0x20 != 0 { 2 } else { 1 };
+            }
+            Command::Align { align } => out.align = *align,
+            Command::Size { width, .. } => out.width = *width as usize,
+            Command::Feed { lines } => {
+                out.flush(false);
+                for _ in 0..*lines {
+                    out.flush(true);
+                }
+            }
+            Command::Cut { partial } => {
+                let label = if *partial { " PARTIAL CUT " } else { " CUT " };
+                out.marker(&format!("{:-^width$}", label, width = columns));
+            }
+            Command::Barcode { system, data } => {
+                let (name, data) = match system {
+                    73 => ("CODE128", data.trim_start_matches("{B")),
+                    67 | 2 => ("EAN13", data.as_str()),
+                    69 | 4 => ("CODE39", data.as_str()),
+                    _ => ("BARCODE", data.as_str()),
+                };
+                out.marker(&format!("[{} {}]", name, data));
+            }
+            Command::Qr { function: 80, data } => {
+                qr_data = data.clone().unwrap_or_default();
+            }
+            Command::Qr { function: 81, .. } => out.marker(&format!("[QR {}]", qr_data)),
+            Command::Raster { width, height } => {
+                out.marker(&format!("[IMAGE {}x{}]", width, height))
+            }
+            // GS ( L fn 69 / GS 8 L fn 69 - print NV graphic
+            Command::NvGraphic { function: 69 } => out.marker("[NV LOGO]"),
+            Command::DrawerKick { pin, .. } => out.marker(&format!("[DRAWER KICK pin {}]", pin)),
+            _ => {}
+        }
+    }
+    out.flush(false);
+    out.text
+}
+
+#[derive(Default)]
+struct Preview {
+    columns: usize,
+    text: String,
+    line: String,
+    /// Printed width of `line` in standard characters.
+    line_width: usize,
+    line_align: Option<Align>,
+    align: Align,
+    bold: bool,
+    bold_open: bool,
+    width: usize,
+}
+
+impl Preview {
+    fn text(&mut self, text: &str) {
+        self.line_align.get_or_insert(self.align);
+        if self.bold && !self.bold_open {
+            self.line.push_str("**");
+            self.bold_open = true;
+        }
+        self.line.push_str(text);
+        self.line_width += text.chars().count() * self.width.max(1);
+    }
+
+    fn set_bold(&mut self, on: bool) {
+        if !on && self.bold_open {
+            self.line.push_str("**");
+            self.bold_open = false;
+        }
+        self.bold = on;
+    }
+
+    /// Ends the current line. With `force`, an empty line is still printed.
+    fn flush(&mut self, force: bool) {
+        if self.line.is_empty() && !force {
+            return;
+        }
+        if self.bold_open {
+            self.line.push_str("**");
+            self.bold_open = false;
+        }
+        let pad = self.columns.saturating_sub(self.line_width);
+        let left = match self.line_align.take().unwrap_or(self.align) {
+            Align::Left => 0,
+            Align::Center => pad / 2,
+            Align::Right => pad,
+        };
+        if !self.line.is_empty() {
+            self.text.push_str(&" ".repeat(left));
+        }
+        self.text.push_str(self.line.trim_end());
+        self.text.push('\n');
+        self.line.clear();
+        self.line_width = 0;
+    }
+
+    /// A line of its own, aligned like text.
+    fn marker(&mut self, marker: &str) {
+        self.flush(false);
+        let width = self.width;
+        let bold = self.bold;
+        self.width = 1;
+        self.bold = false;
+        self.text(marker);
+        self.flush(true);
+        self.width = width;
+        self.bold = bold;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::escpos::{Cut, EscPosBuilder, Hri, QrErrorCorrection, Symbology};
+
+    fn ops(bytes: &[u8]) -> Vec<Command> {
+        decode(bytes).into_iter().map(|d| d.command).collect()
+    }
+
+    #[test]
+    fn decodes_builder_output() {
+        let bytes = EscPosBuilder::with_code_page(CodePage::Cp858, "?")
+            .init()
+            .bold(true)
+            .line("Crème €")
+            .bold(false)
+            .barcode(Symbology::Code128, "A-1042", 60, 2, Hri::Below)
+            .cut(Cut::Partial)
+            .build();
+        let decoded = decode(&bytes);
+        assert_eq!(decoded[1].offset, 2);
+        assert_eq!(
+            ops(&bytes),
+            [
+                Command::Init,
+                Command::CodePage { table: 19 },
+                Command::Bold { on: true },
+                Command::Text {
+                    text: "Crème €".to_string()
+                },
+                Command::LineFeed,
+                Command::Bold { on: false },
+                Command::BarcodeHri { position: 2 },
+                Command::BarcodeHeight { dots: 60 },
+                Command::BarcodeWidth { module: 2 },
+                Command::Barcode {
+                    system: 73,
+                    data: "{BA-1042".to_string()
+                },
+                Command::Cut { partial: true },
+            ]
+        );
+    }
+
+    #[test]
+    fn flags_unknown_and_truncated_commands() {
+        assert_eq!(
+            ops(&[0x1d, 0x7e, b'o', b'k', 0x1d, 0x21]),
+            [
+                Command::Unknown {
+                    bytes: vec![0x1d, 0x7e]
+                },
+                Command::Text {
+                    text: "ok".to_string()
+                },
+                Command::Truncated {
+                    bytes: vec![0x1d, 0x21]
+                },
+            ]
+        );
+        // Raster header promising more data than was sent.
+        let truncated = ops(&[0x1d, 0x76, 0x30, 0, 2, 0, 10, 0, 0xff]);
+        assert!(matches!(truncated[..], [Command::Truncated { .. }]));
+    }
+
+    #[test]
+    fn lints_bold_toggles_and_unsupported_commands() {
+        let mut printer = PrinterDefinition::tcp("bar", "127.0.0.1", 9100);
+        printer.paper_width = 58;
+        printer.native_qr = false;
+        let mut bytes = EscPosBuilder::new()
+            .init()
+            .bold(false)
+            .bold(true)
+            .line("TOTAL")
+            .qr("A1", 4, QrErrorCorrection::L)
+            .raw(&[0x1b, 0x74, 99])
+            .build();
+        // 50 bytes (400 dots) wide, one row.
+        bytes.extend([0x1d, 0x76, 0x30, 0, 50, 0, 1, 0]);
+        bytes.extend([0; 50]);
+        bytes.extend([0x1d, 0x28]);
+
+        let messages: Vec<(usize, String)> = lint(&decode(&bytes), &printer)
+            .into_iter()
+            .map(|issue| (issue.offset, issue.message))
+            .collect();
+        let qr_print = bytes.len() - 2 - 58 - 3 - 8;
+        assert_eq!(
+            messages,
+            [
+                (5, "Bold turned off but was not on".to_string()),
+                (
+                    qr_print,
+                    "Printer 'bar' does not support native QR codes".to_string()
+                ),
+                (qr_print + 8, "Unsupported code page table 99".to_string()),
+                (
+                    qr_print + 11,
+                    "Image is 400 dots wide but printer 'bar' prints 384".to_string()
+                ),
+                (
+                    bytes.len() - 2,
+                    "Stream ends inside command 1d 28".to_string()
+                ),
+                (8, "Bold turned on and never turned off".to_string()),
+            ]
+        );
+
+        let clean = EscPosBuilder::new()
+            .init()
+            .bold(true)
+            .line("OK")
+            .bold(false)
+            .build();
+        assert!(inspect(&clean, &printer).issues.is_empty());
+    }
+
+    #[test]
+    fn previews_alignment_bold_barcodes_and_cuts() {
+        let bytes = EscPosBuilder::new()
+            .init()
+            .align(Align::Center)
+            .bold(true)
+            .line("CHEFCLOUD")
+            .bold(false)
+            .align(Align::Left)
+            .line("Latte   4,500")
+            .qr("https://chefcloud.app/r/1", 4, QrErrorCorrection::M)
+            .newline()
+            .feed(1)
+            .cut(Cut::Full)
+            .build();
+        assert_eq!(
+            preview(&decode(&bytes), 20),
+            concat!(
+                "     **CHEFCLOUD**\n",
+                "Latte   4,500\n",
+                "[QR https://chefcloud.app/r/1]\n",
+                "\n",
+                "\n",
+                "------- CUT --------\n",
+            )
+        );
+    }
+}
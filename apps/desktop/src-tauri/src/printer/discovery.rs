@@ -0,0 +1,732 @@
+//! Finds network printers on the terminal's subnet: mDNS browsing for
+//! `_pdl-datastream._tcp` and `_ipp._tcp` adverts, then a TCP scan of the
+//! raw (9100), LPD (515) and IPP (631) ports with IPP and ESC/POS probes of
+//! the responders. Hosts that speak IPP are office printers, which would
+//! print the ESC/POS probe as garbage, so they never get it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::config::{PrinterDefinition, PrinterRole, Transport};
+use super::ipp::IppClient;
+use super::status;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(300);
+const PROBE_TIMEOUT: Duration = Duration::from_millis(800);
+const MDNS_LISTEN: Duration = Duration::from_millis(1500);
+const SCAN_THREADS: usize = 64;
+/// Largest subnet scanned, to keep a mistyped prefix from taking minutes.
+const MAX_HOSTS: usize = 1024;
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+const RAW_SERVICE: &str = "_pdl-datastream._tcp.local";
+const IPP_SERVICE: &str = "_ipp._tcp.local";
+
+const DNS_A: u16 = 1;
+const DNS_PTR: u16 = 12;
+const DNS_TXT: u16 = 16;
+const DNS_SRV: u16 = 33;
+
+/// Ports probed on every host.
+#[derive(Clone, Copy, Debug)]
+pub struct Ports {
+    pub raw: u16,
+    pub lpd: u16,
+    pub ipp: u16,
+}
+
+impl Ports {
+    pub const STANDARD: Ports = Ports {
+        raw: 9100,
+        lpd: 515,
+        ipp: 631,
+    };
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredPrinter {
+    pub host: String,
+    pub open_ports: Vec<u16>,
+    /// Answered an ESC/POS `DLE EOT` status query.
+    pub escpos: bool,
+    /// Answered an IPP Get-Printer-Attributes request or advertised `_ipp._tcp`.
+    pub ipp: bool,
+    /// Model hint from `GS I`, IPP `printer-make-and-model` or mDNS TXT records.
+    pub model: Option<String>,
+    /// mDNS instance name or IPP `printer-name`.
+    pub name: Option<String>,
+    /// Registry entry the UI can save into `printer.json` as is. Missing
+    /// when no supported transport answered.
+    pub printer: Option<PrinterDefinition>,
+}
+
+/// One host address range, e.g. `192.168.1.0/24`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Subnet {
+    pub network: Ipv4Addr,
+    pub prefix: u8,
+}
+
+impl Subnet {
+    pub fn parse(cidr: &str) -> Result<Self, String> {
+        let (address, prefix) = cidr.split_once('/').unwrap_or((cidr, "24"));
+        let address: Ipv4Addr = address
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid subnet address '{}'", cidr))?;
+        let prefix: u8 = prefix
+            .trim()
+            .parse()
+            .ok()
+            .filter(|p| *p <= 32)
+            .ok_or_else(|| format!("Invalid subnet prefix '{}'", cidr))?;
+        let subnet = Subnet::containing(address, prefix);
+        if subnet.hosts().len() > MAX_HOSTS {
+            return Err(format!(
+                "Subnet {} is too large to scan (at most {} hosts)",
+                cidr, MAX_HOSTS
+            ));
+        }
+        Ok(subnet)
+    }
+
+    pub fn containing(address: Ipv4Addr, prefix: u8) -> Self {
+        let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+        Subnet {
+            network: Ipv4Addr::from(u32::from(address) & mask),
+            prefix,
+        }
+    }
+
+    /// Host addresses, without the network and broadcast addresses.
+    pub fn hosts(&self) -> Vec<Ipv4Addr> {
+        let network = u32::from(self.network);
+        let size = 1u64 << (32 - self.prefix as u32);
+        if size <= 2 {
+            return (0..size)
+                .map(|i| Ipv4Addr::from(network + i as u32))
+                .collect();
+        }
+        (1..size - 1)
+            .take(MAX_HOSTS + 1)
+            .map(|i| Ipv4Addr::from(network + i as u32))
+            .collect()
+    }
+}
+
+/// The terminal's /24, found from the address the OS would route outbound
+/// traffic from. Nothing is sent.
+pub fn local_subnet() -> Result<Subnet, String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+    socket
+        .connect("8.8.8.8:80")
+        .map_err(|e| format!("No network route to scan from: {}", e))?;
+    match socket.local_addr().map_err(|e| e.to_string())?.ip() {
+        IpAddr::V4(address) if !address.is_loopback() && !address.is_unspecified() => {
+            Ok(Subnet::containing(address, 24))
+        }
+        other => Err(format!("Cannot scan from local address {}", other)),
+    }
+}
+
+/// Browses mDNS, then scans `subnet` without sending ESC/POS probes to
+/// hosts that advertised IPP, and merges what both found per host.
+pub fn discover(subnet: Subnet, ports: Ports) -> Vec<DiscoveredPrinter> {
+    let adverts = browse_mdns(MDNS_LISTEN).unwrap_or_default();
+    let ipp_hosts: Vec<Ipv4Addr> = adverts
+        .iter()
+        .filter(|advert| advert.service == IPP_SERVICE)
+        .map(|advert| advert.address)
+        .collect();
+    let mut found: BTreeMap<Ipv4Addr, DiscoveredPrinter> = scan(&subnet.hosts(), ports, &ipp_hosts)
+        .into_iter()
+        .map(|printer| (printer.host.parse().unwrap(), printer))
+        .collect();
+
+    for advert in adverts {
+        let printer = found
+            .entry(advert.address)
+            .or_insert_with(|| DiscoveredPrinter {
+                host: advert.address.to_string(),
+                ..DiscoveredPrinter::default()
+            });
+        printer.name = printer.name.take().or(Some(advert.instance.clone()));
+        printer.model = printer.model.take().or_else(|| advert.model());
+        if advert.service == IPP_SERVICE {
+            printer.ipp = true;
+        }
+        if !printer.open_ports.contains(&advert.port) {
+            printer.open_ports.push(advert.port);
+            printer.open_ports.sort_unstable();
+        }
+        if printer.printer.is_none() || advert.service == RAW_SERVICE {
+            printer.printer = Some(suggest(&advert.address.to_string(), &advert));
+        }
+    }
+    found.into_values().collect()
+}
+
+/// Connects to each port on every host and probes the hosts that answered.
+/// `ipp_hosts` are known to speak IPP and are not sent ESC/POS probes.
+pub fn scan(hosts: &[Ipv4Addr], ports: Ports, ipp_hosts: &[Ipv4Addr]) -> Vec<DiscoveredPrinter> {
+    let queue = Mutex::new(hosts.iter().copied());
+    let found = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..SCAN_THREADS.min(hosts.len()) {
+            scope.spawn(|| loop {
+                let Some(host) = queue.lock().unwrap_or_else(|e| e.into_inner()).next() else {
+                    break;
+                };
+                if let Some(printer) = probe_host(host, ports, ipp_hosts) {
+                    found
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push(printer);
+                }
+            });
+        }
+    });
+    let mut found = found.into_inner().unwrap_or_else(|e| e.into_inner());
+    found.sort_by_key(|printer| printer.host.parse::<Ipv4Addr>().ok());
+    found
+}
+
+fn probe_host(host: Ipv4Addr, ports: Ports, ipp_hosts: &[Ipv4Addr]) -> Option<DiscoveredPrinter> {
+    let open_ports: Vec<u16> = [ports.raw, ports.lpd, ports.ipp]
+        .into_iter()
+        .filter(|port| {
+            TcpStream::connect_timeout(&SocketAddr::new(host.into(), *port), CONNECT_TIMEOUT)
+                .is_ok()
+        })
+        .collect();
+    if open_ports.is_empty() {
+        return None;
+    }
+
+    let mut printer = DiscoveredPrinter {
+        host: host.to_string(),
+        open_ports,
+        ..DiscoveredPrinter::default()
+    };
+    let address = host.to_string();
+    let uri = format!("ipp://{}:{}/ipp/print", address, ports.ipp);
+    if printer.open_ports.contains(&ports.ipp) {
+        let attributes = IppClient::new(&uri).ok().and_then(|client| {
+            client
+                .with_connect_timeout(PROBE_TIMEOUT)
+                .with_timeout(PROBE_TIMEOUT)
+                .get_printer_attributes()
+                .ok()
+        });
+        if let Some(attributes) = attributes {
+            printer.ipp = true;
+            printer.name = attributes.name;
+            printer.model = attributes.make_and_model;
+        }
+    }
+    if printer.open_ports.contains(&ports.raw) {
+        if !printer.ipp && !ipp_hosts.contains(&host) {
+            if let Ok(model) = probe_escpos(SocketAddr::new(host.into(), ports.raw)) {
+                printer.escpos = true;
+                printer.model = model;
+            }
+        }
+        printer.printer = Some(PrinterDefinition::tcp(
+            &printer_id(&address),
+            &address,
+            ports.raw,
+        ));
+    }
+    if printer.ipp && printer.printer.is_none() {
+        printer.printer = Some(PrinterDefinition {
+            role: PrinterRole::Office,
+            transport: Transport::Ipp { uri },
+            ..PrinterDefinition::tcp(&printer_id(&address), &address, ports.raw)
+        });
+    }
+    if printer.printer.is_none() && printer.open_ports.contains(&ports.lpd) {
+        // Print servers that only take LPD; "lp" is the usual default
+        // queue, but the UI should let the user change it.
+        printer.printer = Some(PrinterDefinition {
+            transport: Transport::Lpd {
+                host: address.clone(),
+                port: ports.lpd,
+                queue: "lp".to_string(),
+            },
+            ..PrinterDefinition::tcp(&printer_id(&address), &address, ports.raw)
+        });
+    }
+    Some(printer)
+}
+
+/// Sends `DLE EOT` status queries and, if they are answered, asks for the
+/// model name with `GS I 67`. Printers that do not support `GS I` still
+/// count as ESC/POS.
+pub fn probe_escpos(addr: SocketAddr) -> io::Result<Option<String>> {
+    let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+    stream.set_read_timeout(Some(PROBE_TIMEOUT))?;
+    stream.set_write_timeout(Some(PROBE_TIMEOUT))?;
+    status::query(&mut stream)?;
+
+    stream.write_all(&[0x1d, 0x49, 0x43])?;
+    // Reply is `_`, the name, then NUL.
+    let mut reply = Vec::new();
+    let mut byte = [0u8; 1];
+    while stream.read_exact(&mut byte).is_ok() && byte[0] != 0 && reply.len() < 64 {
+        reply.push(byte[0]);
+    }
+    Ok(match reply.split_first() {
+        Some((b'_', name)) if !name.is_empty() => {
+            Some(String::from_utf8_lossy(name).trim().to_string())
+        }
+        _ => None,
+    })
+}
+
+fn printer_id(host: &str) -> String {
+    format!("printer-{}", host.replace(['.', ':'], "-"))
+}
+
+fn suggest(address: &str, advert: &MdnsAdvert) -> PrinterDefinition {
+    let id = printer_id(address);
+    if advert.service == IPP_SERVICE {
+        let path = advert
+            .txt
+            .get("rp")
+            .map(String::as_str)
+            .unwrap_or("ipp/print");
+        PrinterDefinition {
+            role: PrinterRole::Office,
+            transport: Transport::Ipp {
+                uri: format!("ipp://{}:{}/{}", address, advert.port, path),
+            },
+            ..PrinterDefinition::tcp(&id, address, Ports::STANDARD.raw)
+        }
+    } else {
+        PrinterDefinition::tcp(&id, address, advert.port)
+    }
+}
+
+/// A service instance resolved from mDNS PTR, SRV, TXT and A records.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MdnsAdvert {
+    pub service: String,
+    pub instance: String,
+    pub address: Ipv4Addr,
+    pub port: u16,
+    pub txt: HashMap<String, String>,
+}
+
+impl MdnsAdvert {
+    fn model(&self) -> Option<String> {
+        self.txt
+            .get("ty")
+            .or_else(|| self.txt.get("usb_MDL"))
+            .cloned()
+            .or_else(|| {
+                // product=(EPSON TM-T88V)
+                self.txt
+                    .get("product")
+                    .map(|p| p.trim_matches(['(', ')']).to_string())
+            })
+    }
+}
+
+/// Asks for both printer services with unicast responses requested, and
+/// collects answers for `listen`. Responders that only answer to the
+/// multicast group are missed.
+pub fn browse_mdns(listen: Duration) -> io::Result<Vec<MdnsAdvert>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.send_to(
+        &mdns_query(&[RAW_SERVICE, IPP_SERVICE]),
+        (MDNS_GROUP, MDNS_PORT),
+    )?;
+
+    let deadline = Instant::now() + listen;
+    let mut records = Vec::new();
+    let mut buf = [0u8; 9000];
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        match socket.recv_from(&mut buf) {
+            Ok((len, _)) => {
+                if let Ok(parsed) = parse_dns(&buf[..len]) {
+                    records.extend(parsed);
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                break
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(resolve_adverts(&records))
+}
+
+fn mdns_query(services: &[&str]) -> Vec<u8> {
+    let mut packet = vec![0, 0, 0, 0];
+    packet.extend_from_slice(&(services.len() as u16).to_be_bytes());
+    packet.extend_from_slice(&[0; 6]);
+    for service in services {
+        encode_name(&mut packet, service);
+        packet.extend_from_slice(&DNS_PTR.to_be_bytes());
+        // IN class with the unicast-response bit set
+        packet.extend_from_slice(&0x8001u16.to_be_bytes());
+    }
+    packet
+}
+
+fn encode_name(packet: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Record {
+    Ptr {
+        service: String,
+        instance: String,
+    },
+    Srv {
+        instance: String,
+        target: String,
+        port: u16,
+    },
+    Txt {
+        instance: String,
+        entries: Vec<(String, String)>,
+    },
+    A {
+        host: String,
+        address: Ipv4Addr,
+    },
+}
+
+fn parse_dns(packet: &[u8]) -> Result<Vec<Record>, String> {
+    let u16_at = |pos: usize| -> Result<u16, String> {
+        packet
+            .get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(|| "Truncated DNS packet".to_string())
+    };
+    let questions = u16_at(4)?;
+    let records = u16_at(6)? as usize + u16_at(8)? as usize + u16_at(10)? as usize;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(packet, pos)?.1 + 4;
+    }
+
+    let mut parsed = Vec::new();
+    for _ in 0..records {
+        let (name, next) = read_name(packet, pos)?;
+        let kind = u16_at(next)?;
+        let length = u16_at(next + 8)? as usize;
+        let data = next + 10;
+        let rdata = packet
+            .get(data..data + length)
+            .ok_or("Truncated DNS record")?;
+        pos = data + length;
+        match kind {
+            DNS_PTR => parsed.push(Record::Ptr {
+                service: name,
+                instance: read_name(packet, data)?.0,
+            }),
+            DNS_SRV if length >= 6 => parsed.push(Record::Srv {
+                instance: name,
+                port: u16::from_be_bytes([rdata[4], rdata[5]]),
+                target: read_name(packet, data + 6)?.0,
+            }),
+            DNS_TXT => {
+                let mut entries = Vec::new();
+                let mut i = 0;
+                while i < rdata.len() {
+                    let len = rdata[i] as usize;
+                    let entry =
+                        String::from_utf8_lossy(rdata.get(i + 1..i + 1 + len).unwrap_or(&[]));
+                    if let Some((key, value)) = entry.split_once('=') {
+                        entries.push((key.to_string(), value.to_string()));
+                    }
+                    i += 1 + len;
+                }
+                parsed.push(Record::Txt {
+                    instance: name,
+                    entries,
+                });
+            }
+            DNS_A if length == 4 => parsed.push(Record::A {
+                host: name,
+                address: Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]),
+            }),
+            _ => {}
+        }
+    }
+    Ok(parsed)
+}
+
+/// Reads a possibly compressed name at `pos`. Returns the name and the
+/// position after it.
+fn read_name(packet: &[u8], mut pos: usize) -> Result<(String, usize), String> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    for _ in 0..128 {
+        let len = *packet.get(pos).ok_or("Truncated DNS name")? as usize;
+        match len {
+            0 => {
+                return Ok((labels.join("."), end.unwrap_or(pos + 1)));
+            }
+            l if l & 0xc0 == 0xc0 => {
+                let low = *packet.get(pos + 1).ok_or("Truncated DNS name")? as usize;
+                end.get_or_insert(pos + 2);
+                pos = ((l & 0x3f) << 8) | low;
+            }
+            l => {
+                let label = packet
+                    .get(pos + 1..pos + 1 + l)
+                    .ok_or("Truncated DNS name")?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + l;
+            }
+        }
+    }
+    Err("DNS name compression loop".to_string())
+}
+
+fn resolve_adverts(records: &[Record]) -> Vec<MdnsAdvert> {
+    let mut adverts = Vec::new();
+    for record in records {
+        let Record::Ptr { service, instance } = record else {
+            continue;
+        };
+        let Some((target, port)) = records.iter().find_map(|r| match r {
+            Record::Srv {
+                instance: i,
+                target,
+                port,
+            } if i == instance => Some((target, *port)),
+            _ => None,
+        }) else {
+            continue;
+        };
+        let Some(address) = records.iter().find_map(|r| match r {
+            Record::A { host, address } if host == target => Some(*address),
+            _ => None,
+        }) else {
+            continue;
+        };
+        let txt = records
+            .iter()
+            .find_map(|r| match r {
+                Record::Txt {
+                    instance: i,
+                    entries,
+                } if i == instance => Some(entries.iter().cloned().collect()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let advert = MdnsAdvert {
+            service: service.clone(),
+            instance: instance
+                .strip_suffix(&format!(".{}", service))
+                .unwrap_or(instance)
+                .to_string(),
+            address,
+            port,
+            txt,
+        };
+        if !adverts.contains(&advert) {
+            adverts.push(advert);
+        }
+    }
+    adverts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn parses_subnets_and_lists_hosts() {
+        let subnet = Subnet::parse("192.168.1.77/24").unwrap();
+        assert_eq!(subnet.network, Ipv4Addr::new(192, 168, 1, 0));
+        let hosts = subnet.hosts();
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts[0], Ipv4Addr::new(192, 168, 1, 1));
+        assert_eq!(hosts[253], Ipv4Addr::new(192, 168, 1, 254));
+        assert_eq!(Subnet::parse("10.0.0.5/32").unwrap().hosts().len(), 1);
+        assert!(Subnet::parse("10.0.0.0/16").is_err());
+        assert!(Subnet::parse("10.0.0/24").is_err());
+    }
+
+    #[test]
+    fn probes_escpos_printer_for_status_and_model() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let raw = listener.local_addr().unwrap().port();
+        let printer = thread::spawn(move || {
+            // The scan's bare connect, then the probe.
+            let _ = listener.accept().unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut command = [0u8; 3];
+            while stream.read_exact(&mut command).is_ok() {
+                match command {
+                    [0x10, 0x04, _] => stream.write_all(&[0x12]).unwrap(),
+                    [0x1d, 0x49, 0x43] => stream.write_all(b"_TM-T20III\0").unwrap(),
+                    _ => break,
+                }
+            }
+        });
+
+        let ports = Ports {
+            raw,
+            lpd: 1,
+            ipp: 1,
+        };
+        let found = scan(&[Ipv4Addr::LOCALHOST], ports, &[]);
+        printer.join().unwrap();
+
+        assert_eq!(found.len(), 1);
+        assert!(found[0].escpos);
+        assert_eq!(found[0].open_ports, [raw]);
+        assert_eq!(found[0].model.as_deref(), Some("TM-T20III"));
+        let definition = found[0].printer.as_ref().unwrap();
+        assert_eq!(definition.id, "printer-127-0-0-1");
+        assert_eq!(
+            definition.transport,
+            Transport::Tcp {
+                host: "127.0.0.1".to_string(),
+                port: raw
+            }
+        );
+    }
+
+    #[test]
+    fn does_not_probe_hosts_that_speak_ipp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let raw = listener.local_addr().unwrap().port();
+        let ports = Ports {
+            raw,
+            lpd: 1,
+            ipp: 1,
+        };
+        let found = scan(&[Ipv4Addr::LOCALHOST], ports, &[Ipv4Addr::LOCALHOST]);
+
+        // Only the scan's bare connect reached the raw port.
+        listener.set_nonblocking(true).unwrap();
+        assert!(listener.accept().is_ok());
+        assert!(listener.accept().is_err());
+        assert_eq!(found.len(), 1);
+        assert!(!found[0].escpos);
+        assert!(found[0].printer.is_some());
+    }
+
+    #[test]
+    fn suggests_lpd_for_hosts_that_only_answer_on_515() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let lpd = listener.local_addr().unwrap().port();
+        let ports = Ports {
+            raw: 1,
+            lpd,
+            ipp: 1,
+        };
+        let found = scan(&[Ipv4Addr::LOCALHOST], ports, &[]);
+
+        assert_eq!(found.len(), 1);
+        assert!(!found[0].escpos);
+        assert_eq!(found[0].open_ports, [lpd]);
+        let definition = found[0].printer.as_ref().unwrap();
+        assert_eq!(
+            definition.transport,
+            Transport::Lpd {
+                host: "127.0.0.1".to_string(),
+                port: lpd,
+                queue: "lp".to_string()
+            }
+        );
+        assert!(definition.validate().is_empty());
+    }
+
+    /// Builds an mDNS response the way printers send them: PTR answer with
+    /// SRV, TXT and A records in the additional section, names compressed.
+    fn ipp_response() -> Vec<u8> {
+        let mut packet = vec![0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 3];
+        let service_at = packet.len();
+        encode_name(&mut packet, IPP_SERVICE);
+        packet.extend_from_slice(&[0, 12, 0, 1, 0, 0, 0x11, 0x94]);
+        let instance_at = packet.len() + 2;
+        let mut rdata = vec![11];
+        rdata.extend_from_slice(b"Back Office");
+        rdata.extend_from_slice(&[0xc0, service_at as u8]);
+        packet.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&rdata);
+        let pointer = [0xc0, instance_at as u8];
+
+        packet.extend_from_slice(&pointer);
+        packet.extend_from_slice(&[0, 33, 0x80, 1, 0, 0, 0, 120]);
+        let mut srv = vec![0, 0, 0, 0, 0x02, 0x77];
+        encode_name(&mut srv, "office.local");
+        packet.extend_from_slice(&(srv.len() as u16).to_be_bytes());
+        let host_at = packet.len() + 6;
+        packet.extend_from_slice(&srv);
+
+        packet.extend_from_slice(&pointer);
+        packet.extend_from_slice(&[0, 16, 0x80, 1, 0, 0, 0, 120]);
+        let mut txt = Vec::new();
+        for entry in ["rp=ipp/print", "ty=Brother HL-L2350DW"] {
+            txt.push(entry.len() as u8);
+            txt.extend_from_slice(entry.as_bytes());
+        }
+        packet.extend_from_slice(&(txt.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&txt);
+
+        packet.extend_from_slice(&[0xc0, host_at as u8]);
+        packet.extend_from_slice(&[0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4, 192, 168, 1, 60]);
+        packet
+    }
+
+    #[test]
+    fn resolves_mdns_ipp_advert_into_suggested_printer() {
+        let records = parse_dns(&ipp_response()).unwrap();
+        let adverts = resolve_adverts(&records);
+        assert_eq!(adverts.len(), 1);
+        let advert = &adverts[0];
+        assert_eq!(advert.instance, "Back Office");
+        assert_eq!(advert.address, Ipv4Addr::new(192, 168, 1, 60));
+        assert_eq!(advert.port, 631);
+        assert_eq!(advert.model().as_deref(), Some("Brother HL-L2350DW"));
+
+        let definition = suggest("192.168.1.60", advert);
+        assert_eq!(definition.role, PrinterRole::Office);
+        assert_eq!(
+            definition.transport,
+            Transport::Ipp {
+                uri: "ipp://192.168.1.60:631/ipp/print".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn query_asks_for_both_services() {
+        let query = mdns_query(&[RAW_SERVICE, IPP_SERVICE]);
+        assert_eq!(&query[4..6], [0, 2]);
+        assert!(query.windows(16).any(|w| w == b"\x0f_pdl-datastream"));
+        assert!(parse_dns(&query).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,603 @@
+use serde::{Deserialize, Serialize};
+
+use super::bitmap::{self, Bitmap};
+use super::config::{PrinterDefinition, PrinterLanguage};
+use super::escpos::{Align, Cut, EscPosBuilder, Hri, QrErrorCorrection, Symbology, Underline};
+use super::layout::{self, Cell};
+use super::logo::{Dither, LogoMode, LogoSettings};
+use super::star::StarBuilder;
+
+/// Structured print document sent from the frontend and rendered to ESC/POS
+/// or Star Line Mode on the Rust side.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Block {
+    /// One line of text; styles are reset after the line.
+    Text {
+        content: String,
+        #[serde(flatten)]
+        style: TextStyle,
+    },
+    /// Cells laid out across the printer's line, wrapping within their
+    /// columns; see [`layout::row`]. Rows always span the whole line, so the
+    /// style's `align` is not used.
+    Row {
+        cells: Vec<Cell>,
+        #[serde(flatten)]
+        style: TextStyle,
+    },
+    /// A full-width rule of `ch`.
+    Separator {
+        #[serde(default = "default_separator")]
+        ch: char,
+    },
+    Feed {
+        #[serde(default = "default_feed")]
+        lines: u8,
+    },
+    Cut {
+        #[serde(default)]
+        mode: Cut,
+    },
+    /// QR code; printed as a raster image on printers without native QR.
+    Qr {
+        data: String,
+        #[serde(default = "default_qr_size")]
+        size: u8,
+        #[serde(default)]
+        error_correction: QrErrorCorrection,
+        #[serde(default = "default_center")]
+        align: Align,
+    },
+    Barcode {
+        data: String,
+        #[serde(default)]
+        symbology: Symbology,
+        #[serde(default = "default_barcode_height")]
+        height: u8,
+        #[serde(default = "default_barcode_width")]
+        width: u8,
+        #[serde(default)]
+        hri: Hri,
+        #[serde(default = "default_center")]
+        align: Align,
+    },
+    /// The printer's configured logo, if it has one.
+    Logo,
+    /// A PNG or JPEG file, dithered and printed as a raster image.
+    Image {
+        path: String,
+        /// Width in dots; defaults to the image width, scaled down to fit.
+        #[serde(default)]
+        width: Option<u16>,
+        #[serde(default)]
+        dither: Dither,
+        #[serde(default = "default_threshold")]
+        threshold: u8,
+        #[serde(default = "default_center")]
+        align: Align,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TextStyle {
+    pub align: Align,
+    pub bold: bool,
+    pub underline: Underline,
+    pub invert: bool,
+    pub width: u8,
+    pub height: u8,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            align: Align::Left,
+            bold: false,
+            underline: Underline::None,
+            invert: false,
+            width: 1,
+            height: 1,
+        }
+    }
+}
+
+fn default_separator() -> char {
+    '-'
+}
+
+fn default_feed() -> u8 {
+    1
+}
+
+fn default_qr_size() -> u8 {
+    6
+}
+
+fn default_barcode_height() -> u8 {
+    80
+}
+
+fn default_barcode_width() -> u8 {
+    3
+}
+
+fn default_threshold() -> u8 {
+    128
+}
+
+fn default_center() -> Align {
+    Align::Center
+}
+
+impl Document {
+    /// Renders the document in `printer`'s command language, for its paper
+    /// width and capabilities.
+    pub fn render(&self, printer: &PrinterDefinition) -> Result<Vec<u8>, String> {
+        let (code_page, fallback) = (printer.code_page, &printer.code_page_fallback);
+        match printer.language {
+            PrinterLanguage::EscPos => {
+                self.render_with(printer, EscPosBuilder::with_code_page(code_page, fallback))
+            }
+            PrinterLanguage::Star => {
+                self.render_with(printer, StarBuilder::with_code_page(code_page, fallback))
+            }
+            PrinterLanguage::Zpl | PrinterLanguage::Tspl => Err(format!(
+                "Printer '{}' is a label printer and does not print documents",
+                printer.id
+            )),
+        }
+    }
+
+    fn render_with<B: CommandSet>(
+        &self,
+        printer: &PrinterDefinition,
+        mut builder: B,
+    ) -> Result<Vec<u8>, String> {
+        let columns = printer.columns();
+        builder.init();
+        for block in &self.blocks {
+            match block {
+                Block::Text { content, style } => {
+                    apply_style(&mut builder, style);
+                    builder.line(content);
+                    apply_style(&mut builder, &TextStyle::default());
+                }
+                Block::Row { cells, style } => {
+                    let style = TextStyle {
+                        align: Align::Left,
+                        ..*style
+                    };
+                    apply_style(&mut builder, &style);
+                    for line in layout::row(cells, columns / style.width.max(1) as usize) {
+                        builder.line(&line);
+                    }
+                    apply_style(&mut builder, &TextStyle::default());
+                }
+                Block::Separator { ch } => {
+                    builder.separator(*ch, columns);
+                }
+                Block::Feed { lines } => {
+                    builder.feed(*lines);
+                }
+                Block::Cut { mode } => {
+                    builder.cut(*mode);
+                }
+                Block::Qr {
+                    data,
+                    size,
+                    error_correction,
+                    align,
+                } => {
+                    builder.align(*align);
+                    if printer.native_qr {
+                        // The printer prints nothing for data it cannot
+                        // fit, so check it here rather than report success.
+                        bitmap::encode_qr(data, *error_correction)?;
+                        builder.qr(data, *size, *error_correction);
+                    } else {
+                        builder.raster(&Bitmap::qr(data, *size, *error_correction)?);
+                    }
+                    builder.newline().align(Align::Left);
+                }
+                Block::Barcode {
+                    data,
+                    symbology,
+                    height,
+                    width,
+                    hri,
+                    align,
+                } => {
+                    symbology.validate(data)?;
+                    builder
+                        .align(*align)
+                        .barcode(*symbology, data, *height, *width, *hri)
+                        .newline()
+                        .align(Align::Left);
+                }
+                Block::Logo => {
+                    if let Some(logo) = &printer.logo {
+                        builder.align(Align::Center);
+                        match logo.mode {
+                            LogoMode::Raster => {
+                                let bitmap = logo.bitmap(printer.dots())?;
+                                builder.raster(&bitmap);
+                            }
+                            LogoMode::Nv => {
+                                builder.nv_graphic_print(&logo.nv_key)?;
+                            }
+                        }
+                        builder.newline().align(Align::Left);
+                    }
+                }
+                Block::Image {
+                    path,
+                    width,
+                    dither,
+                    threshold,
+                    align,
+                } => {
+                    let image = LogoSettings {
+                        path: path.clone(),
+                        width: *width,
+                        dither: *dither,
+                        threshold: *threshold,
+                        mode: LogoMode::Raster,
+                        nv_key: String::new(),
+                    };
+                    let bitmap = image.bitmap(printer.dots())?;
+                    builder
+                        .align(*align)
+                        .raster(&bitmap)
+                        .newline()
+                        .align(Align::Left);
+                }
+            }
+        }
+        Ok(builder.build())
+    }
+
+    /// Plain-text rendering for printers that take text rather than ESC/POS,
+    /// such as office printers reached over IPP. Images and logos are left
+    /// out; QR codes and barcodes print their data.
+    pub fn render_text(&self, columns: usize) -> String {
+        let mut out = String::new();
+        let mut line = |content: &str, align: Align| {
+            let pad = columns.saturating_sub(content.chars().count());
+            let left = match align {
+                Align::Left => 0,
+                Align::Center => pad / 2,
+                Align::Right => pad,
+            };
+            out.push_str(&" ".repeat(left));
+            out.push_str(content.trim_end());
+            out.push('\n');
+        };
+        for block in &self.blocks {
+            match block {
+                Block::Text { content, style } => line(content, style.align),
+                Block::Row { cells, .. } => layout::row(cells, columns)
+                    .iter()
+                    .for_each(|row| line(row, Align::Left)),
+                Block::Separator { ch } => line(&ch.to_string().repeat(columns), Align::Left),
+                Block::Feed { lines } => (0..*lines).for_each(|_| line("", Align::Left)),
+                Block::Qr { data, align, .. } | Block::Barcode { data, align, .. } => {
+                    line(data, *align)
+                }
+                Block::Cut { .. } | Block::Logo | Block::Image { .. } => {}
+            }
+        }
+        out
+    }
+}
+
+/// The commands a document renders to, implemented for each command
+/// language a receipt printer can take.
+pub trait CommandSet {
+    fn init(&mut self) -> &mut Self;
+    fn line(&mut self, content: &str) -> &mut Self;
+    fn newline(&mut self) -> &mut Self;
+    fn bold(&mut self, enable: bool) -> &mut Self;
+    fn underline(&mut self, underline: Underline) -> &mut Self;
+    fn invert(&mut self, enable: bool) -> &mut Self;
+    fn align(&mut self, align: Align) -> &mut Self;
+    fn size(&mut self, width: u8, height: u8) -> &mut Self;
+    fn feed(&mut self, lines: u8) -> &mut Self;
+    fn cut(&mut self, cut: Cut) -> &mut Self;
+    fn qr(&mut self, data: &str, module_size: u8, ec: QrErrorCorrection) -> &mut Self;
+    fn barcode(
+        &mut self,
+        symbology: Symbology,
+        data: &str,
+        height: u8,
+        width: u8,
+        hri: Hri,
+    ) -> &mut Self;
+    fn raster(&mut self, bitmap: &Bitmap) -> &mut Self;
+    fn nv_graphic_print(&mut self, key: &str) -> Result<&mut Self, String>;
+    fn separator(&mut self, ch: char, length: usize) -> &mut Self;
+    fn build(&self) -> Vec<u8>;
+}
+
+/// Forwards [`CommandSet`] to the builder's inherent methods of the same
+/// name.
+macro_rules! command_set {
+    ($builder:ty) => {
+        impl CommandSet for $builder {
+            fn init(&mut self) -> &mut Self {
+                <$builder>::init(self)
+            }
+            fn line(&mut self, content: &str) -> &mut Self {
+                <$builder>::line(self, content)
+            }
+            fn newline(&mut self) -> &mut Self {
+                <$builder>::newline(self)
+            }
+            fn bold(&mut self, enable: bool) -> &mut Self {
+                <$builder>::bold(self, enable)
+            }
+            fn underline(&mut self, underline: Underline) -> &mut Self {
+                <$builder>::underline(self, underline)
+            }
+            fn invert(&mut self, enable: bool) -> &mut Self {
+                <$builder>::invert(self, enable)
+            }
+            fn align(&mut self, align: Align) -> &mut Self {
+                <$builder>::align(self, align)
+            }
+            fn size(&mut self, width: u8, height: u8) -> &mut Self {
+                <$builder>::size(self, width, height)
+            }
+            fn feed(&mut self, lines: u8) -> &mut Self {
+                <$builder>::feed(self, lines)
+            }
+            fn cut(&mut self, cut: Cut) -> &mut Self {
+                <$builder>::cut(self, cut)
+            }
+            fn qr(&mut self, data: &str, module_size: u8, ec: QrErrorCorrection) -> &mut Self {
+                <$builder>::qr(self, data, module_size, ec)
+            }
+            fn barcode(
+                &mut self,
+                symbology: Symbology,
+                data: &str,
+                height: u8,
+                width: u8,
+                hri: Hri,
+            ) -> &mut Self {
+                <$builder>::barcode(self, symbology, data, height, width, hri)
+            }
+            fn raster(&mut self, bitmap: &Bitmap) -> &mut Self {
+                <$builder>::raster(self, bitmap)
+            }
+            fn nv_graphic_print(&mut self, key: &str) -> Result<&mut Self, String> {
+                <$builder>::nv_graphic_print(self, key)
+            }
+            fn separator(&mut self, ch: char, length: usize) -> &mut Self {
+                <$builder>::separator(self, ch, length)
+            }
+            fn build(&self) -> Vec<u8> {
+                <$builder>::build(self)
+            }
+        }
+    };
+}
+
+command_set!(EscPosBuilder);
+command_set!(StarBuilder);
+
+fn apply_style<B: CommandSet>(builder: &mut B, style: &TextStyle) {
+    builder
+        .align(style.align)
+        .bold(style.bold)
+        .underline(style.underline)
+        .invert(style.invert)
+        .size(style.width, style.height);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn printer(paper_width: u16) -> PrinterDefinition {
+        PrinterDefinition {
+            paper_width,
+            ..PrinterDefinition::tcp("test", "127.0.0.1", 9100)
+        }
+    }
+
+    #[test]
+    fn parses_json_document() {
+        let document: Document = serde_json::from_value(json!({
+            "blocks": [
+                { "type": "text", "content": "CHEFCLOUD", "align": "center", "bold": true, "width": 2, "height": 2 },
+                { "type": "text", "content": "Plain" },
+                { "type": "separator", "ch": "=" },
+                { "type": "feed", "lines": 3 },
+                { "type": "cut" }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(document.blocks.len(), 5);
+        match &document.blocks[0] {
+            Block::Text { style, .. } => {
+                assert_eq!(style.align, Align::Center);
+                assert!(style.bold);
+                assert_eq!((style.width, style.height), (2, 2));
+            }
+            other => panic!("unexpected block {:?}", other),
+        }
+        assert_eq!(
+            document.blocks[1],
+            Block::Text {
+                content: "Plain".to_string(),
+                style: TextStyle::default()
+            }
+        );
+        assert_eq!(document.blocks[4], Block::Cut { mode: Cut::Full });
+    }
+
+    #[test]
+    fn renders_separator_to_column_width() {
+        let document = Document {
+            blocks: vec![Block::Separator { ch: '=' }, Block::Cut { mode: Cut::Full }],
+        };
+        let bytes = document.render(&printer(58)).unwrap();
+
+        let mut expected = vec![0x1b, 0x40, 0x1b, 0x74, 0x00];
+        expected.extend_from_slice(&[b'='; 32]);
+        expected.extend_from_slice(&[b'\n', 0x1d, 0x56, 0x00]);
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn rows_fill_the_printer_columns() {
+        let document: Document = serde_json::from_value(json!({
+            "blocks": [
+                { "type": "row", "cells": [
+                    { "content": "Burger" },
+                    { "content": "25.00", "width": 8, "align": "right" }
+                ] },
+                { "type": "row", "bold": true, "width": 2, "cells": [
+                    { "content": "TOTAL" },
+                    { "content": "29.50", "width": 6, "align": "right" }
+                ] }
+            ]
+        }))
+        .unwrap();
+
+        let mut narrow = printer(80);
+        narrow.columns = Some(42);
+        for (printer, columns) in [(printer(58), 32), (printer(80), 48), (narrow, 42)] {
+            let bytes = document.render(&printer).unwrap();
+            let item = format!("Burger{:>width$}\n", "25.00", width = columns - 6);
+            let total = format!("TOTAL{:>width$}\n", "29.50", width = columns / 2 - 5);
+            let contains = |line: &str| bytes.windows(line.len()).any(|w| w == line.as_bytes());
+            assert!(contains(&item), "{:?}", item);
+            assert!(contains(&total), "{:?}", total);
+            assert!(document.render_text(columns).starts_with(&item));
+        }
+    }
+
+    #[test]
+    fn renders_plain_text_for_office_printers() {
+        let document: Document = serde_json::from_value(json!({
+            "blocks": [
+                { "type": "logo" },
+                { "type": "text", "content": "Z REPORT", "align": "center", "bold": true },
+                { "type": "separator" },
+                { "type": "text", "content": "Total 12,000", "align": "right" },
+                { "type": "feed", "lines": 1 },
+                { "type": "cut" }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            document.render_text(20),
+            "      Z REPORT\n--------------------\n        Total 12,000\n\n"
+        );
+    }
+
+    #[test]
+    fn qr_falls_back_to_raster_without_native_support() {
+        let document: Document = serde_json::from_value(json!({
+            "blocks": [{ "type": "qr", "data": "https://chefcloud.app/feedback", "errorCorrection": "H" }]
+        }))
+        .unwrap();
+
+        let mut native = printer(80);
+        let bytes = document.render(&native).unwrap();
+        assert!(bytes.windows(3).any(|w| w == [0x1d, 0x28, 0x6b]));
+
+        native.native_qr = false;
+        let bytes = document.render(&native).unwrap();
+        assert!(bytes.windows(4).any(|w| w == [0x1d, 0x76, 0x30, 0]));
+        assert!(!bytes.windows(3).any(|w| w == [0x1d, 0x28, 0x6b]));
+    }
+
+    #[test]
+    fn rejects_qr_data_too_long_for_a_qr_code() {
+        let document: Document = serde_json::from_value(json!({
+            "blocks": [{ "type": "qr", "data": "x".repeat(3_000), "errorCorrection": "H" }]
+        }))
+        .unwrap();
+
+        let mut printer = printer(80);
+        assert!(document.render(&printer).is_err());
+        printer.native_qr = false;
+        assert!(document.render(&printer).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_barcode() {
+        let document: Document = serde_json::from_value(json!({
+            "blocks": [{ "type": "barcode", "symbology": "ean13", "data": "12AB" }]
+        }))
+        .unwrap();
+        assert!(document.render(&printer(80)).is_err());
+    }
+
+    #[test]
+    fn logo_block_is_skipped_without_a_configured_logo() {
+        let document = Document {
+            blocks: vec![Block::Logo],
+        };
+        assert_eq!(
+            document.render(&printer(80)).unwrap(),
+            [0x1b, 0x40, 0x1b, 0x74, 0x00]
+        );
+
+        let mut nv = printer(80);
+        nv.logo = Some(LogoSettings {
+            path: "/nonexistent/logo.png".to_string(),
+            width: None,
+            dither: Dither::Threshold,
+            threshold: 128,
+            mode: LogoMode::Nv,
+            nv_key: "LG".to_string(),
+        });
+        let bytes = document.render(&nv).unwrap();
+        assert!(bytes.windows(6).any(|w| w == [0x1d, 0x28, 0x4c, 6, 0, 48]));
+    }
+
+    #[test]
+    fn renders_star_line_mode_for_star_printers() {
+        let document: Document = serde_json::from_value(json!({
+            "blocks": [
+                { "type": "text", "content": "CHEFCLOUD", "align": "center", "bold": true },
+                { "type": "qr", "data": "A-1042" },
+                { "type": "cut", "mode": "partial" }
+            ]
+        }))
+        .unwrap();
+        let star = PrinterDefinition {
+            language: PrinterLanguage::Star,
+            native_qr: true,
+            ..printer(80)
+        };
+        let bytes = document.render(&star).unwrap();
+        assert!(bytes.starts_with(&[0x1b, 0x40, 0x1b, 0x1d, 0x74, 1]));
+        assert!(bytes.windows(4).any(|w| w == [0x1b, 0x1d, 0x61, 1]));
+        assert!(bytes.windows(2).any(|w| w == [0x1b, 0x45]));
+        assert!(bytes.windows(4).any(|w| w == [0x1b, 0x1d, 0x79, 0x50]));
+        assert!(bytes.ends_with(&[0x1b, 0x64, 3]));
+        // No ESC/POS QR (GS ( k) or cut (GS V).
+        assert!(!bytes
+            .windows(2)
+            .any(|w| w == [0x1d, 0x28] || w == [0x1d, 0x56]));
+
+        let labels = PrinterDefinition {
+            language: PrinterLanguage::Zpl,
+            ..printer(80)
+        };
+        assert!(document.render(&labels).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+//! Cash drawer kicks through the receipt printer's drawer connector, with a
+//! local audit log of every opening for no-sale reports.
+
+use serde::{Deserialize, Serialize};
+
+use super::config::{PrinterDefinition, PrinterLanguage, Transport};
+use super::escpos::EscPosBuilder;
+use super::jsonl::{JsonlLog, Timestamped};
+use super::star::StarBuilder;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DrawerSettings {
+    /// Drawer kick-out connector pin, 2 or 5.
+    pub pin: u8,
+    pub on_ms: u16,
+    pub off_ms: u16,
+}
+
+impl Default for DrawerSettings {
+    fn default() -> Self {
+        DrawerSettings {
+            pin: 2,
+            on_ms: 100,
+            off_ms: 200,
+        }
+    }
+}
+
+impl DrawerSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pin != 2 && self.pin != 5 {
+            return Err(format!("Drawer pin must be 2 or 5, got {}", self.pin));
+        }
+        // ESC p counts in 2 ms units, so 1 ms would send no pulse at all.
+        if !(2..=510).contains(&self.on_ms) || !(2..=510).contains(&self.off_ms) {
+            return Err("Drawer pulse times must be between 2 and 510 ms".to_string());
+        }
+        Ok(())
+    }
+
+    /// The drawer pulse in `language`. Star printers take these times for
+    /// pin 2 but pulse pin 5 for as long as their memory switch says.
+    pub fn kick_bytes(&self, language: PrinterLanguage) -> Vec<u8> {
+        match language {
+            PrinterLanguage::Star => StarBuilder::default()
+                .drawer_kick(self.pin, self.on_ms, self.off_ms)
+                .build(),
+            _ => EscPosBuilder::new()
+                .drawer_kick(self.pin, self.on_ms, self.off_ms)
+                .build(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DrawerEvent {
+    pub at: u64,
+    pub printer_id: String,
+    pub operator: String,
+    pub reason: String,
+    pub pin: u8,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Checks that `printer` can pulse a drawer: only ESC/POS and Star receipt
+/// printers have a drawer connector, and an IPP printer would print the
+/// kick as a page.
+pub fn check_printer(printer: &PrinterDefinition) -> Result<(), String> {
+    let receipt = matches!(
+        printer.language,
+        PrinterLanguage::EscPos | PrinterLanguage::Star
+    );
+    if !receipt || matches!(printer.transport, Transport::Ipp { .. }) {
+        return Err(format!(
+            "Printer '{}' has no cash drawer connector",
+            printer.id
+        ));
+    }
+    Ok(())
+}
+
+impl Timestamped for DrawerEvent {
+    fn at(&self) -> u64 {
+        self.at
+    }
+}
+
+/// Audit log at `{appDataDir}/drawer-audit.jsonl`.
+pub type DrawerLog = JsonlLog<DrawerEvent>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn validates_pin_and_pulse_times() {
+        assert!(DrawerSettings::default().validate().is_ok());
+        let bad_pin = DrawerSettings {
+            pin: 3,
+            ..DrawerSettings::default()
+        };
+        assert!(bad_pin.validate().is_err());
+        let too_long = DrawerSettings {
+            on_ms: 600,
+            ..DrawerSettings::default()
+        };
+        assert!(too_long.validate().is_err());
+        for (on_ms, off_ms) in [(1, 200), (100, 1), (100, 0)] {
+            let too_short = DrawerSettings {
+                on_ms,
+                off_ms,
+                ..DrawerSettings::default()
+            };
+            assert!(too_short.validate().is_err());
+        }
+        assert_eq!(
+            DrawerSettings::default().kick_bytes(PrinterLanguage::EscPos),
+            [0x1b, 0x70, 0x00, 50, 100]
+        );
+        assert_eq!(
+            DrawerSettings::default().kick_bytes(PrinterLanguage::Star),
+            [0x1b, 0x07, 10, 20, 0x07]
+        );
+    }
+
+    #[test]
+    fn only_receipt_printers_kick_drawers() {
+        let receipt = PrinterDefinition::tcp("receipt", "10.0.0.20", 9100);
+        assert!(check_printer(&receipt).is_ok());
+        let star = PrinterDefinition {
+            language: PrinterLanguage::Star,
+            ..receipt.clone()
+        };
+        assert!(check_printer(&star).is_ok());
+        let labels = PrinterDefinition {
+            language: PrinterLanguage::Zpl,
+            ..receipt.clone()
+        };
+        assert!(check_printer(&labels).is_err());
+        let office = PrinterDefinition {
+            transport: Transport::Ipp {
+                uri: "ipp://10.0.0.30/ipp/print".to_string(),
+            },
+            ..receipt
+        };
+        assert!(check_printer(&office).is_err());
+    }
+
+    #[test]
+    fn audit_log_appends_and_filters_by_time() {
+        let path = std::env::temp_dir()
+            .join(format!("chefcloud-drawer-{}", std::process::id()))
+            .join("drawer-audit.jsonl");
+        let _ = fs::remove_file(&path);
+        let log = DrawerLog::new(path);
+
+        for (at, reason) in [(1_000, "No sale"), (2_000, "Change for float")] {
+            log.record(&DrawerEvent {
+                at,
+                printer_id: "receipt".to_string(),
+                operator: "amina".to_string(),
+                reason: reason.to_string(),
+                pin: 2,
+                success: true,
+                error: None,
+            })
+            .unwrap();
+        }
+
+        assert_eq!(log.list(0).len(), 2);
+        let recent = log.list(1_500);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].reason, "Change for float");
+    }
+}
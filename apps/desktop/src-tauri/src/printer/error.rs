@@ -0,0 +1,132 @@
+//! Errors from sending a job to a printer. The `kind` lets the UI tell an
+//! unplugged printer from a slow or busy one without parsing messages.
+
+use std::fmt;
+use std::io;
+
+use serde::Serialize;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PrintErrorKind {
+    /// No route to the printer, an unknown host or device, or no answer to
+    /// the connect within its timeout.
+    Unreachable,
+    /// Connected, but a write or status read did not finish in time.
+    Timeout,
+    /// The printer's host refused the connection, e.g. nothing listens on
+    /// the port, or an LPD server refused the job.
+    Refused,
+    /// Any other failure while talking to the printer.
+    Io,
+    /// The printer answered but reported it cannot print.
+    NotReady,
+    /// The job was cancelled while it was being sent.
+    Cancelled,
+    /// The request itself was rejected: unknown printer, bad data or a
+    /// failed pre-flight check.
+    Invalid,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct PrintError {
+    pub kind: PrintErrorKind,
+    pub message: String,
+}
+
+impl PrintError {
+    pub fn new(kind: PrintErrorKind, message: impl Into<String>) -> Self {
+        PrintError {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn cancelled() -> Self {
+        PrintError::new(PrintErrorKind::Cancelled, "Print job cancelled")
+    }
+
+    /// Classifies a failure to open a connection to `target`. A connect that
+    /// times out means nothing answered, so it counts as unreachable.
+    pub fn connect(target: impl fmt::Display, e: io::Error) -> Self {
+        let kind = match e.kind() {
+            io::ErrorKind::ConnectionRefused => PrintErrorKind::Refused,
+            io::ErrorKind::TimedOut
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::NotFound
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::HostUnreachable
+            | io::ErrorKind::NetworkUnreachable => PrintErrorKind::Unreachable,
+            _ => PrintErrorKind::Io,
+        };
+        PrintError::new(
+            kind,
+            format!("Failed to connect to printer at {}: {}", target, e),
+        )
+    }
+
+    /// Classifies a failed write or read on an open connection.
+    pub fn io(context: &str, e: io::Error) -> Self {
+        let kind = match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => PrintErrorKind::Timeout,
+            io::ErrorKind::ConnectionRefused => PrintErrorKind::Refused,
+            _ => PrintErrorKind::Io,
+        };
+        PrintError::new(kind, format!("{}: {}", context, e))
+    }
+}
+
+impl fmt::Display for PrintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Config lookups, rendering and validation report plain strings; those are
+/// all problems with the request rather than the printer.
+impl From<String> for PrintError {
+    fn from(message: String) -> Self {
+        PrintError::new(PrintErrorKind::Invalid, message)
+    }
+}
+
+impl From<&str> for PrintError {
+    fn from(message: &str) -> Self {
+        PrintError::new(PrintErrorKind::Invalid, message)
+    }
+}
+
+/// The spool keeps the last error as text.
+impl From<PrintError> for String {
+    fn from(e: PrintError) -> Self {
+        e.message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn classifies_io_errors_by_stage() {
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        assert_eq!(
+            PrintError::connect("10.0.0.9:9100", refused).kind,
+            PrintErrorKind::Refused
+        );
+        let timed_out = || io::Error::from(io::ErrorKind::TimedOut);
+        assert_eq!(
+            PrintError::connect("10.0.0.9:9100", timed_out()).kind,
+            PrintErrorKind::Unreachable
+        );
+        assert_eq!(
+            PrintError::io("Failed to send data to printer", timed_out()).kind,
+            PrintErrorKind::Timeout
+        );
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        let error = PrintError::io("Failed to send data to printer", reset);
+        assert_eq!(error.kind, PrintErrorKind::Io);
+        assert_eq!(serde_json::to_value(&error).unwrap()["kind"], json!("io"));
+    }
+}
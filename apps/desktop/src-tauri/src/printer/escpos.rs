@@ -0,0 +1,517 @@
+use serde::{Deserialize, Serialize};
+
+use super::barcode;
+use super::bitmap::Bitmap;
+use super::codepage::CodePage;
+
+// ESC/POS control codes
+const ESC: u8 = 0x1b;
+const GS: u8 = 0x1d;
+const LF: u8 = 0x0a;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Underline {
+    #[default]
+    None,
+    Single,
+    Double,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Cut {
+    #[default]
+    Full,
+    Partial,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QrErrorCorrection {
+    L,
+    #[default]
+    M,
+    Q,
+    H,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Symbology {
+    #[default]
+    Code128,
+    Ean13,
+    Code39,
+}
+
+/// Position of the human-readable interpretation printed with a barcode.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Hri {
+    None,
+    Above,
+    #[default]
+    Below,
+    Both,
+}
+
+impl Symbology {
+    /// Checks `data` against the characters and lengths the symbology can
+    /// encode. Code 128 data that starts with `{` is taken as `GS k` code
+    /// set syntax (`{A`, `{B`, `{C`, and `{{` for a literal brace) and must
+    /// parse as such; other Code 128 data is plain text.
+    pub fn validate(&self, data: &str) -> Result<(), String> {
+        let valid = match self {
+            Symbology::Code128 if data.starts_with('{') => {
+                return barcode::encode(*self, data).map(|_| ())
+            }
+            Symbology::Code128 => {
+                !data.is_empty() && data.is_ascii() && code128_data(data).len() <= 255
+            }
+            Symbology::Ean13 => {
+                (data.len() == 12 || data.len() == 13) && data.bytes().all(|b| b.is_ascii_digit())
+            }
+            Symbology::Code39 => {
+                !data.is_empty()
+                    && data.len() <= 255
+                    && data.bytes().all(|b| {
+                        b.is_ascii_uppercase() || b.is_ascii_digit() || b" $%+-./".contains(&b)
+                    })
+            }
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("Invalid {:?} barcode data '{}'", self, data))
+        }
+    }
+}
+
+/// Code 128 data as sent in `GS k`. Plain text goes in code set B with
+/// each `{` doubled, so it cannot be read as a code set selector; data
+/// already in selector syntax is sent as is.
+pub fn code128_data(data: &str) -> String {
+    if data.starts_with('{') {
+        data.to_string()
+    } else {
+        format!("{{B{}", data.replace('{', "{{"))
+    }
+}
+
+/// NV graphics key codes are two printable ASCII characters.
+fn nv_key(key: &str) -> Result<[u8; 2], String> {
+    match key.as_bytes() {
+        [a, b] if (32..=126).contains(a) && (32..=126).contains(b) => Ok([*a, *b]),
+        _ => Err(format!(
+            "NV graphics key must be two printable characters, got '{}'",
+            key
+        )),
+    }
+}
+
+pub struct EscPosBuilder {
+    buf: Vec<u8>,
+    code_page: CodePage,
+    fallback: String,
+}
+
+impl Default for EscPosBuilder {
+    fn default() -> Self {
+        Self::with_code_page(CodePage::default(), "?")
+    }
+}
+
+impl EscPosBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder whose text is transcoded to `code_page`. Characters the code
+    /// page cannot represent are replaced with `fallback`.
+    pub fn with_code_page(code_page: CodePage, fallback: &str) -> Self {
+        EscPosBuilder {
+            buf: Vec::new(),
+            code_page,
+            fallback: fallback.to_string(),
+        }
+    }
+
+    pub fn init(&mut self) -> &mut Self {
+        // ESC @ - Initialize printer, ESC t n - Select character code table
+        let table = self.code_page.escpos_table();
+        self.raw(&[ESC, 0x40]).raw(&[ESC, 0x74, table])
+    }
+
+    pub fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn text(&mut self, content: &str) -> &mut Self {
+        let bytes = self.code_page.encode(content, &self.fallback);
+        self.raw(&bytes)
+    }
+
+    pub fn newline(&mut self) -> &mut Self {
+        self.raw(&[LF])
+    }
+
+    pub fn line(&mut self, content: &str) -> &mut Self {
+        self.text(content).newline()
+    }
+
+    pub fn bold(&mut self, enable: bool) -> &mut Self {
+        // ESC E n - Enable/disable bold
+        self.raw(&[ESC, 0x45, enable as u8])
+    }
+
+    pub fn underline(&mut self, underline: Underline) -> &mut Self {
+        // ESC - n - Underline mode (0 off, 1 one dot, 2 two dots)
+        let mode = match underline {
+            Underline::None => 0,
+            Underline::Single => 1,
+            Underline::Double => 2,
+        };
+        self.raw(&[ESC, 0x2d, mode])
+    }
+
+    pub fn invert(&mut self, enable: bool) -> &mut Self {
+        // GS B n - White/black reverse printing
+        self.raw(&[GS, 0x42, enable as u8])
+    }
+
+    pub fn align(&mut self, align: Align) -> &mut Self {
+        // ESC a n - Select justification
+        let code = match align {
+            Align::Left => 0,
+            Align::Center => 1,
+            Align::Right => 2,
+        };
+        self.raw(&[ESC, 0x61, code])
+    }
+
+    /// Character magnification, 1 to 8 in each direction.
+    pub fn size(&mut self, width: u8, height: u8) -> &mut Self {
+        // GS ! n - Set character size
+        let w = width.clamp(1, 8) - 1;
+        let h = height.clamp(1, 8) - 1;
+        self.raw(&[GS, 0x21, (w << 4) | h])
+    }
+
+    pub fn feed(&mut self, lines: u8) -> &mut Self {
+        // ESC d n - Print and feed n lines
+        self.raw(&[ESC, 0x64, lines])
+    }
+
+    pub fn cut(&mut self, cut: Cut) -> &mut Self {
+        // GS V m - Paper cut
+        let mode = match cut {
+            Cut::Full => 0,
+            Cut::Partial => 1,
+        };
+        self.raw(&[GS, 0x56, mode])
+    }
+
+    /// Pulses the cash drawer connector. `pin` is 2 or 5; times are in
+    /// milliseconds and rounded down to the printer's 2 ms units.
+    pub fn drawer_kick(&mut self, pin: u8, on_ms: u16, off_ms: u16) -> &mut Self {
+        // ESC p m t1 t2 - Generate pulse
+        let m = if pin == 5 { 1 } else { 0 };
+        let t1 = (on_ms / 2).min(255) as u8;
+        let t2 = (off_ms / 2).min(255) as u8;
+        self.raw(&[ESC, 0x70, m, t1, t2])
+    }
+
+    /// Native QR code (model 2). `module_size` is the dot size of one module,
+    /// 1 to 16.
+    pub fn qr(&mut self, data: &str, module_size: u8, ec: QrErrorCorrection) -> &mut Self {
+        let ec = match ec {
+            QrErrorCorrection::L => 48,
+            QrErrorCorrection::M => 49,
+            QrErrorCorrection::Q => 50,
+            QrErrorCorrection::H => 51,
+        };
+        let len = data.len() + 3;
+        // GS ( k - Select model 2, module size, error correction
+        self.raw(&[GS, 0x28, 0x6b, 4, 0, 49, 65, 50, 0])
+            .raw(&[GS, 0x28, 0x6b, 3, 0, 49, 67, module_size.clamp(1, 16)])
+            .raw(&[GS, 0x28, 0x6b, 3, 0, 49, 69, ec])
+            // GS ( k - Store data in the symbol storage area, then print it
+            .raw(&[
+                GS,
+                0x28,
+                0x6b,
+                (len & 0xff) as u8,
+                (len >> 8) as u8,
+                49,
+                80,
+                48,
+            ])
+            .raw(data.as_bytes())
+            .raw(&[GS, 0x28, 0x6b, 3, 0, 49, 81, 48])
+    }
+
+    /// 1D barcode. `height` is in dots, `width` is the module width (2-6).
+    /// Call [`Symbology::validate`] first; invalid data prints nothing or
+    /// garbage depending on the printer.
+    pub fn barcode(
+        &mut self,
+        symbology: Symbology,
+        data: &str,
+        height: u8,
+        width: u8,
+        hri: Hri,
+    ) -> &mut Self {
+        let hri = match hri {
+            Hri::None => 0,
+            Hri::Above => 1,
+            Hri::Below => 2,
+            Hri::Both => 3,
+        };
+        // GS H n - HRI position, GS h n - height, GS w n - module width
+        self.raw(&[GS, 0x48, hri])
+            .raw(&[GS, 0x68, height.max(1)])
+            .raw(&[GS, 0x77, width.clamp(2, 6)]);
+
+        // GS k m n d1...dn
+        let (m, payload) = match symbology {
+            Symbology::Code128 => (73, code128_data(data)),
+            Symbology::Ean13 => (67, data.to_string()),
+            Symbology::Code39 => (69, data.to_string()),
+        };
+        self.raw(&[GS, 0x6b, m, payload.len().min(255) as u8])
+            .raw(&payload.as_bytes()[..payload.len().min(255)])
+    }
+
+    /// Prints a 1-bit raster image.
+    pub fn raster(&mut self, bitmap: &Bitmap) -> &mut Self {
+        let x = bitmap.bytes_per_row();
+        let y = bitmap.height;
+        // GS v 0 m xL xH yL yH d1...dk
+        self.raw(&[
+            GS,
+            0x76,
+            0x30,
+            0,
+            (x & 0xff) as u8,
+            (x >> 8) as u8,
+            (y & 0xff) as u8,
+            (y >> 8) as u8,
+        ])
+        .raw(&bitmap.data)
+    }
+
+    /// Stores a bitmap as NV graphics under a two-character key. NV memory
+    /// wears with each write, so this is for one-off uploads, not every job.
+    pub fn nv_graphic_store(&mut self, key: &str, bitmap: &Bitmap) -> Result<&mut Self, String> {
+        let [kc1, kc2] = nv_key(key)?;
+        let len = 11 + bitmap.data.len();
+        if len > 0xffff {
+            return Err(format!("Logo is too large for NV memory ({} bytes)", len));
+        }
+        let (x, y) = (bitmap.width, bitmap.height);
+        // GS ( L pL pH m fn a kc1 kc2 b xL xH yL yH c d1...dk - Define NV graphics (raster)
+        Ok(self
+            .raw(&[
+                GS,
+                0x28,
+                0x4c,
+                (len & 0xff) as u8,
+                (len >> 8) as u8,
+                48,
+                67,
+                48,
+            ])
+            .raw(&[
+                kc1,
+                kc2,
+                1,
+                (x & 0xff) as u8,
+                (x >> 8) as u8,
+                (y & 0xff) as u8,
+                (y >> 8) as u8,
+                49,
+            ])
+            .raw(&bitmap.data))
+    }
+
+    pub fn nv_graphic_print(&mut self, key: &str) -> Result<&mut Self, String> {
+        let [kc1, kc2] = nv_key(key)?;
+        // GS ( L pL pH m fn kc1 kc2 x y - Print NV graphics data at 1x1
+        Ok(self.raw(&[GS, 0x28, 0x4c, 6, 0, 48, 69, kc1, kc2, 1, 1]))
+    }
+
+    pub fn separator(&mut self, ch: char, length: usize) -> &mut Self {
+        self.line(&ch.to_string().repeat(length))
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        self.buf.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_styles_and_text() {
+        let bytes = EscPosBuilder::new()
+            .init()
+            .align(Align::Center)
+            .bold(true)
+            .size(2, 2)
+            .line("CHEFCLOUD")
+            .size(1, 1)
+            .bold(false)
+            .build();
+
+        let mut expected = vec![
+            0x1b, 0x40, 0x1b, 0x74, 0x00, 0x1b, 0x61, 0x01, 0x1b, 0x45, 0x01, 0x1d, 0x21, 0x11,
+        ];
+        expected.extend_from_slice(b"CHEFCLOUD\n");
+        expected.extend_from_slice(&[0x1d, 0x21, 0x00, 0x1b, 0x45, 0x00]);
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn clamps_size_and_encodes_cut_feed_separator() {
+        let bytes = EscPosBuilder::new()
+            .size(0, 9)
+            .separator('=', 4)
+            .feed(3)
+            .cut(Cut::Partial)
+            .build();
+
+        assert_eq!(
+            bytes,
+            [0x1d, 0x21, 0x07, b'=', b'=', b'=', b'=', b'\n', 0x1b, 0x64, 0x03, 0x1d, 0x56, 0x01]
+        );
+    }
+
+    #[test]
+    fn encodes_underline_and_invert() {
+        let bytes = EscPosBuilder::new()
+            .underline(Underline::Double)
+            .invert(true)
+            .build();
+        assert_eq!(bytes, [0x1b, 0x2d, 0x02, 0x1d, 0x42, 0x01]);
+    }
+
+    #[test]
+    fn selects_code_page_and_transcodes_text() {
+        let bytes = EscPosBuilder::with_code_page(CodePage::Cp858, "?")
+            .init()
+            .text("Crème brûlée €4 🍮")
+            .build();
+        let mut expected = vec![0x1b, 0x40, 0x1b, 0x74, 19];
+        expected.extend_from_slice(b"Cr\x8ame br\x96l\x82e \xd54 ?");
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn encodes_native_qr_model_2() {
+        let bytes = EscPosBuilder::new()
+            .qr("ABC", 6, QrErrorCorrection::Q)
+            .build();
+        assert_eq!(
+            bytes,
+            [
+                0x1d, 0x28, 0x6b, 4, 0, 49, 65, 50, 0, // model 2
+                0x1d, 0x28, 0x6b, 3, 0, 49, 67, 6, // module size
+                0x1d, 0x28, 0x6b, 3, 0, 49, 69, 50, // error correction Q
+                0x1d, 0x28, 0x6b, 6, 0, 49, 80, 48, b'A', b'B', b'C', // store
+                0x1d, 0x28, 0x6b, 3, 0, 49, 81, 48, // print
+            ]
+        );
+    }
+
+    #[test]
+    fn encodes_barcodes_with_hri() {
+        let bytes = EscPosBuilder::new()
+            .barcode(Symbology::Code128, "A-102", 80, 3, Hri::Below)
+            .build();
+        let mut expected = vec![
+            0x1d, 0x48, 2, 0x1d, 0x68, 80, 0x1d, 0x77, 3, 0x1d, 0x6b, 73, 7,
+        ];
+        expected.extend_from_slice(b"{BA-102");
+        assert_eq!(bytes, expected);
+
+        // A literal brace is escaped so it cannot switch code sets.
+        let bytes = EscPosBuilder::new()
+            .barcode(Symbology::Code128, "T{C}12", 80, 3, Hri::None)
+            .build();
+        assert!(bytes.ends_with(b"\x1dk\x49\x09{BT{{C}12"));
+
+        let bytes = EscPosBuilder::new()
+            .barcode(Symbology::Ean13, "590123412345", 60, 2, Hri::None)
+            .build();
+        assert_eq!(&bytes[9..13], [0x1d, 0x6b, 67, 12]);
+    }
+
+    #[test]
+    fn validates_barcode_data() {
+        assert!(Symbology::Ean13.validate("5901234123457").is_ok());
+        assert!(Symbology::Ean13.validate("59012341234").is_err());
+        assert!(Symbology::Ean13.validate("59012341234X").is_err());
+        assert!(Symbology::Code39.validate("ORDER-102").is_ok());
+        assert!(Symbology::Code39.validate("order").is_err());
+        assert!(Symbology::Code128.validate("").is_err());
+        assert!(Symbology::Code128.validate("T{C}12").is_ok());
+        assert!(Symbology::Code128.validate("{C1234{BA").is_ok());
+        assert!(Symbology::Code128.validate("{BA{{1}").is_ok());
+        assert!(Symbology::Code128.validate("{x-102").is_err());
+        assert!(Symbology::Code128.validate("{C123").is_err());
+        assert!(Symbology::Code128.validate("{BA{D").is_err());
+    }
+
+    #[test]
+    fn encodes_raster_header() {
+        let mut bitmap = Bitmap::new(10, 2);
+        bitmap.set(0, 0);
+        let bytes = EscPosBuilder::new().raster(&bitmap).build();
+        assert_eq!(
+            bytes,
+            [0x1d, 0x76, 0x30, 0, 2, 0, 2, 0, 0x80, 0x00, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn encodes_nv_graphics_store_and_print() {
+        let mut bitmap = Bitmap::new(8, 1);
+        bitmap.set(0, 0);
+        let bytes = EscPosBuilder::new()
+            .nv_graphic_store("LG", &bitmap)
+            .unwrap()
+            .nv_graphic_print("LG")
+            .unwrap()
+            .build();
+        assert_eq!(
+            bytes,
+            [
+                0x1d, 0x28, 0x4c, 12, 0, 48, 67, 48, b'L', b'G', 1, 8, 0, 1, 0, 49,
+                0x80, // store
+                0x1d, 0x28, 0x4c, 6, 0, 48, 69, b'L', b'G', 1, 1, // print
+            ]
+        );
+        assert!(EscPosBuilder::new().nv_graphic_print("LOGO").is_err());
+    }
+
+    #[test]
+    fn encodes_drawer_kick_in_2ms_units() {
+        let bytes = EscPosBuilder::new()
+            .drawer_kick(2, 100, 200)
+            .drawer_kick(5, 1000, 0)
+            .build();
+        assert_eq!(bytes, [0x1b, 0x70, 0x00, 50, 100, 0x1b, 0x70, 0x01, 255, 0]);
+    }
+}
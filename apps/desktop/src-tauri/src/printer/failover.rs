@@ -0,0 +1,146 @@
+//! Moving jobs between the members of a printer group, and the log of each
+//! move at `{appDataDir}/failover.jsonl`.
+
+use serde::{Deserialize, Serialize};
+
+use super::config::{PrinterDefinition, PrinterLanguage, Transport};
+use super::document::CommandSet;
+use super::error::{PrintError, PrintErrorKind};
+use super::escpos::{Align, EscPosBuilder};
+use super::ipp;
+use super::jsonl::{JsonlLog, Timestamped};
+use super::star::StarBuilder;
+
+/// Whether a job that failed with `error` can move to another member. Only
+/// failures before any of the job reached the printer qualify: no
+/// connection, or a printer that reported it was not ready. A timeout or
+/// I/O error part way through may already have printed some of it, so the
+/// job stays with its printer rather than printing twice.
+pub fn can_reroute(error: &PrintError) -> bool {
+    matches!(
+        error.kind,
+        PrintErrorKind::Unreachable | PrintErrorKind::Refused | PrintErrorKind::NotReady
+    )
+}
+
+/// `bytes` prefixed with a "REROUTED FROM <from>" header for `printer`.
+pub fn reroute(printer: &PrinterDefinition, from: &str, bytes: &[u8]) -> Vec<u8> {
+    banner(printer, &format!("REROUTED FROM {}", from), bytes)
+}
+
+/// `bytes` prefixed with `header` in inverted bold for `printer`, or as a
+/// plain line for IPP text jobs. PDF jobs for IPP printers and labels cannot
+/// take a header and are returned unchanged.
+pub fn banner(printer: &PrinterDefinition, header: &str, bytes: &[u8]) -> Vec<u8> {
+    let (code_page, fallback) = (printer.code_page, &printer.code_page_fallback);
+    let mut marked = match (&printer.transport, printer.language) {
+        (_, language) if language.is_label() => return bytes.to_vec(),
+        (Transport::Ipp { .. }, _) if ipp::document_format(bytes).starts_with("text/plain") => {
+            format!("{}\n\n", header).into_bytes()
+        }
+        (Transport::Ipp { .. }, _) => return bytes.to_vec(),
+        (_, PrinterLanguage::Star) => {
+            header_bytes(StarBuilder::with_code_page(code_page, fallback), header)
+        }
+        _ => header_bytes(EscPosBuilder::with_code_page(code_page, fallback), header),
+    };
+    marked.extend_from_slice(bytes);
+    marked
+}
+
+fn header_bytes<B: CommandSet>(mut builder: B, header: &str) -> Vec<u8> {
+    builder
+        .init()
+        .align(Align::Center)
+        .invert(true)
+        .bold(true)
+        .line(&format!(" {} ", header))
+        .bold(false)
+        .invert(false)
+        .align(Align::Left)
+        .newline()
+        .build()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FailoverEvent {
+    pub at: u64,
+    pub job_id: String,
+    /// The printer that could not take the job.
+    pub from: String,
+    /// The next member it was moved to.
+    pub to: String,
+    pub reason: String,
+}
+
+impl Timestamped for FailoverEvent {
+    fn at(&self) -> u64 {
+        self.at
+    }
+}
+
+pub type FailoverLog = JsonlLog<FailoverEvent>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::decode::{self, Command};
+    use crate::printer::star;
+
+    #[test]
+    fn prefixes_rerouted_jobs_with_a_header() {
+        let printer = PrinterDefinition::tcp("cold", "10.0.0.22", 9100);
+        let job = EscPosBuilder::new().init().line("2x Burger").build();
+        let rerouted = reroute(&printer, "hot", &job);
+        assert!(rerouted.ends_with(&job));
+        let preview = decode::preview(&decode::decode(&rerouted), printer.columns());
+        assert_eq!(
+            preview.lines().next().unwrap(),
+            format!("{}** REROUTED FROM hot **", " ".repeat(14))
+        );
+        assert!(decode::decode(&rerouted)
+            .iter()
+            .any(|d| d.command == Command::Invert { on: true }));
+
+        let office = PrinterDefinition {
+            transport: Transport::Ipp {
+                uri: "ipp://10.0.0.50/ipp/print".to_string(),
+            },
+            ..printer
+        };
+        assert_eq!(
+            reroute(&office, "hot", b"Order 12\n"),
+            b"REROUTED FROM hot\n\nOrder 12\n"
+        );
+        assert_eq!(reroute(&office, "hot", b"%PDF-1.7\n"), b"%PDF-1.7\n");
+    }
+
+    #[test]
+    fn reroutes_only_jobs_that_never_reached_the_printer() {
+        let error = |kind| PrintError::new(kind, "failed");
+        assert!(can_reroute(&error(PrintErrorKind::Unreachable)));
+        assert!(can_reroute(&error(PrintErrorKind::Refused)));
+        assert!(can_reroute(&error(PrintErrorKind::NotReady)));
+        assert!(!can_reroute(&error(PrintErrorKind::Timeout)));
+        assert!(!can_reroute(&error(PrintErrorKind::Io)));
+        assert!(!can_reroute(&PrintError::cancelled()));
+    }
+
+    #[test]
+    fn star_headers_use_star_commands() {
+        let printer = PrinterDefinition {
+            language: PrinterLanguage::Star,
+            ..PrinterDefinition::tcp("cold", "10.0.0.22", 9100)
+        };
+        let rerouted = reroute(&printer, "hot", b"2x Burger\n");
+        let preview = star::preview(&rerouted, printer.columns(), printer.code_page);
+        assert_eq!(
+            preview.lines().next().unwrap(),
+            format!("{}REROUTED FROM hot", " ".repeat(15))
+        );
+        // ESC 4 - white/black reverse, not the ESC/POS GS B.
+        assert!(rerouted.windows(2).any(|w| w == [0x1b, 0x34]));
+        assert!(!rerouted.windows(2).any(|w| w == [0x1d, 0x42]));
+    }
+}
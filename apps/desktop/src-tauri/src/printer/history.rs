@@ -0,0 +1,275 @@
+//! Receipt history at `{appDataDir}/print-history.json`. Every receipt is
+//! kept with the exact bytes that were sent, in `print-history/<job id>.bin`,
+//! so a customer copy can be printed again without rebuilding it, and every
+//! reprint is recorded with who asked for it.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::config::PrinterDefinition;
+use super::error::{PrintError, PrintErrorKind};
+use super::failover;
+use super::payloads::PayloadStore;
+use super::spool::{now_millis, JobKind, PrintJob};
+
+/// Entries kept; the oldest are dropped first.
+const KEEP_ENTRIES: usize = 500;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// Not sent yet.
+    Pending,
+    Printed,
+    /// The last attempt failed. The spool may still retry it.
+    Failed,
+    Cancelled,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    /// The spool job that carried it.
+    pub job_id: String,
+    pub kind: JobKind,
+    pub order_id: Option<String>,
+    pub printer_id: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub outcome: Outcome,
+    pub error: Option<String>,
+    /// For reprints, the job id of the original receipt.
+    pub reprint_of: Option<String>,
+    /// Who asked for a reprint, and why.
+    pub operator: Option<String>,
+    pub reason: Option<String>,
+    /// Times the receipt has been reprinted. Always 0 on reprints.
+    pub reprints: u32,
+    /// Only set on entries being recorded. The history keeps the bytes in
+    /// the entry's payload file; older history files carry them inline.
+    #[serde(default, skip_serializing, with = "super::spool::base64_bytes")]
+    pub payload: Vec<u8>,
+}
+
+impl HistoryEntry {
+    pub fn new(job: &PrintJob, order_id: Option<String>) -> Self {
+        HistoryEntry {
+            job_id: job.id.clone(),
+            kind: job.kind,
+            order_id,
+            printer_id: job.printer_id.clone(),
+            created_at: job.created_at,
+            updated_at: job.created_at,
+            outcome: Outcome::Pending,
+            error: None,
+            reprint_of: None,
+            operator: None,
+            reason: None,
+            reprints: 0,
+            payload: job.payload.clone(),
+        }
+    }
+}
+
+/// `bytes` with a "COPY - REPRINT <n>" banner at the top.
+pub fn mark_copy(printer: &PrinterDefinition, reprint: u32, bytes: &[u8]) -> Vec<u8> {
+    failover::banner(printer, &format!("COPY - REPRINT {}", reprint), bytes)
+}
+
+pub struct PrintHistory {
+    path: PathBuf,
+    payloads: PayloadStore,
+    entries: Mutex<Vec<HistoryEntry>>,
+}
+
+impl PrintHistory {
+    /// Loads the history at `path`. A missing or unreadable file starts an
+    /// empty history. Bytes stored inline by older versions are moved to
+    /// payload files.
+    pub fn open(path: PathBuf) -> Self {
+        let payloads = PayloadStore::new(path.with_extension(""));
+        let mut entries: Vec<HistoryEntry> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        for entry in entries.iter_mut().filter(|e| !e.payload.is_empty()) {
+            if payloads.write(&entry.job_id, &entry.payload).is_ok() {
+                entry.payload = Vec::new();
+            }
+        }
+        PrintHistory {
+            path,
+            payloads,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    fn persist(&self, entries: &[HistoryEntry]) {
+        let Ok(json) = serde_json::to_string(entries) else {
+            return;
+        };
+        if let Some(dir) = self.path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        if fs::write(&tmp, json).is_ok() {
+            let _ = fs::rename(&tmp, &self.path);
+        }
+    }
+
+    fn update<T>(&self, f: impl FnOnce(&mut Vec<HistoryEntry>) -> T) -> T {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let result = f(&mut entries);
+        let excess = entries.len().saturating_sub(KEEP_ENTRIES);
+        for entry in entries.drain(..excess) {
+            self.payloads.remove(&entry.job_id);
+        }
+        self.persist(&entries);
+        result
+    }
+
+    pub fn record(&self, mut entry: HistoryEntry) {
+        if let Err(e) = self.payloads.write(&entry.job_id, &entry.payload) {
+            eprintln!("Receipt {} cannot be reprinted: {}", entry.job_id, e);
+        }
+        entry.payload = Vec::new();
+        self.update(|entries| entries.push(entry));
+    }
+
+    /// The bytes sent for job `job_id`.
+    pub fn payload(&self, job_id: &str) -> Result<Vec<u8>, String> {
+        self.payloads.read(job_id)
+    }
+
+    /// Records the outcome of an attempt to send job `job_id`. Jobs that are
+    /// not receipts have no entry and are ignored.
+    pub fn finish(&self, job_id: &str, result: &Result<String, PrintError>) {
+        self.update(|entries| {
+            let Some(entry) = entries.iter_mut().find(|e| e.job_id == job_id) else {
+                return;
+            };
+            entry.updated_at = now_millis();
+            match result {
+                Ok(_) => {
+                    entry.outcome = Outcome::Printed;
+                    entry.error = None;
+                }
+                Err(e) => {
+                    entry.outcome = match e.kind {
+                        PrintErrorKind::Cancelled => Outcome::Cancelled,
+                        _ => Outcome::Failed,
+                    };
+                    entry.error = Some(e.message.clone());
+                }
+            }
+        });
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<HistoryEntry> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().find(|e| e.job_id == job_id).cloned()
+    }
+
+    /// Counts a reprint of the receipt printed by `job_id`, or of the one a
+    /// reprint was copied from, and returns that original with its new
+    /// count.
+    pub fn count_reprint(&self, job_id: &str) -> Result<HistoryEntry, String> {
+        self.update(|entries| {
+            let entry = entries
+                .iter()
+                .find(|e| e.job_id == job_id)
+                .ok_or_else(|| format!("No receipt in the print history for job '{}'", job_id))?;
+            let original_id = entry
+                .reprint_of
+                .clone()
+                .unwrap_or_else(|| job_id.to_string());
+            let original = entries
+                .iter_mut()
+                .find(|e| e.job_id == original_id)
+                .ok_or_else(|| format!("The original of job '{}' is no longer kept", job_id))?;
+            original.reprints += 1;
+            Ok(original.clone())
+        })
+    }
+
+    /// Entries newest first, optionally only those for `order_id`.
+    pub fn list(&self, order_id: Option<&str>, limit: usize) -> Vec<HistoryEntry> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .iter()
+            .rev()
+            .filter(|e| order_id.is_none() || e.order_id.as_deref() == order_id)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::decode;
+    use crate::printer::escpos::EscPosBuilder;
+    use crate::printer::spool::Spool;
+
+    fn temp_history(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("chefcloud-history-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn records_outcomes_and_counts_reprints() {
+        let dir = temp_history("reprints");
+        let spool = Spool::open(dir.join("print-spool.json"));
+        let history = PrintHistory::open(dir.join("print-history.json"));
+        let job = spool.submit("receipt", JobKind::Receipt, b"receipt".to_vec());
+        history.record(HistoryEntry::new(&job, Some("A-1042".to_string())));
+
+        let refused = PrintError::new(PrintErrorKind::Refused, "Connection refused");
+        history.finish(&job.id, &Err(refused));
+        assert_eq!(history.get(&job.id).unwrap().outcome, Outcome::Failed);
+        history.finish(&job.id, &Ok("Printed".to_string()));
+        let printed = history.get(&job.id).unwrap();
+        assert_eq!(printed.outcome, Outcome::Printed);
+        assert_eq!(printed.error, None);
+
+        let original = history.count_reprint(&job.id).unwrap();
+        assert_eq!(original.reprints, 1);
+        let copy = spool.submit("receipt", JobKind::Receipt, b"copy".to_vec());
+        history.record(HistoryEntry {
+            reprint_of: Some(job.id.clone()),
+            operator: Some("amina".to_string()),
+            ..HistoryEntry::new(&copy, original.order_id.clone())
+        });
+        // Reprinting the copy counts against the original.
+        assert_eq!(history.count_reprint(&copy.id).unwrap().job_id, job.id);
+        assert!(history.count_reprint("missing").is_err());
+
+        let reopened = PrintHistory::open(dir.join("print-history.json"));
+        let listed = reopened.list(Some("A-1042"), 10);
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].job_id, copy.id);
+        assert_eq!(listed[1].reprints, 2);
+        assert_eq!(reopened.payload(&job.id).unwrap(), b"receipt");
+        // Neither the index nor a listing carries the bytes.
+        let json = fs::read_to_string(dir.join("print-history.json")).unwrap();
+        assert!(!json.contains("payload"));
+        assert!(!serde_json::to_string(&listed).unwrap().contains("payload"));
+        assert!(reopened.list(Some("A-9999"), 10).is_empty());
+    }
+
+    #[test]
+    fn marks_copies_with_a_banner() {
+        let printer = PrinterDefinition::tcp("receipt", "10.0.0.20", 9100);
+        let receipt = EscPosBuilder::new().init().line("Total 12.00").build();
+        let copy = mark_copy(&printer, 2, &receipt);
+        assert!(copy.ends_with(&receipt));
+        let preview = decode::preview(&decode::decode(&copy), printer.columns());
+        assert!(preview.lines().next().unwrap().contains("COPY - REPRINT 2"));
+    }
+}
@@ -0,0 +1,910 @@
+//! Minimal IPP/1.1 client (RFC 8010/8011) for office printers and CUPS
+//! queues: Print-Job, Get-Printer-Attributes and Get-Job-Attributes over
+//! plain HTTP.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::error::{PrintError, PrintErrorKind};
+use super::status::PrinterStatus;
+
+/// Office printers can take a while to accept a large PDF, so Print-Job
+/// waits at least this long for its writes and reply.
+const IPP_TIMEOUT: Duration = Duration::from_secs(30);
+
+const OPERATION_ATTRIBUTES: u8 = 0x01;
+const JOB_ATTRIBUTES: u8 = 0x02;
+const END_OF_ATTRIBUTES: u8 = 0x03;
+const PRINTER_ATTRIBUTES: u8 = 0x04;
+
+const PRINT_JOB: u16 = 0x0002;
+const GET_JOB_ATTRIBUTES: u16 = 0x0009;
+const GET_PRINTER_ATTRIBUTES: u16 = 0x000b;
+
+const TAG_INTEGER: u8 = 0x21;
+const TAG_BOOLEAN: u8 = 0x22;
+const TAG_ENUM: u8 = 0x23;
+const TAG_TEXT: u8 = 0x41;
+const TAG_NAME: u8 = 0x42;
+const TAG_KEYWORD: u8 = 0x44;
+const TAG_URI: u8 = 0x45;
+const TAG_CHARSET: u8 = 0x47;
+const TAG_LANGUAGE: u8 = 0x48;
+const TAG_MIME_TYPE: u8 = 0x49;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum IppValue {
+    Integer(i32),
+    Boolean(bool),
+    String(String),
+    /// Values this client does not interpret (dates, resolutions, ranges).
+    Bytes(Vec<u8>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attribute {
+    pub tag: u8,
+    pub name: String,
+    pub values: Vec<IppValue>,
+}
+
+impl Attribute {
+    pub fn new(tag: u8, name: &str, value: IppValue) -> Self {
+        Attribute {
+            tag,
+            name: name.to_string(),
+            values: vec![value],
+        }
+    }
+
+    pub fn string(tag: u8, name: &str, value: &str) -> Self {
+        Self::new(tag, name, IppValue::String(value.to_string()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+    pub tag: u8,
+    pub attributes: Vec<Attribute>,
+}
+
+/// An IPP request or response. `code` is the operation id in requests and
+/// the status code in responses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub code: u16,
+    pub request_id: u32,
+    pub groups: Vec<Group>,
+    pub data: Vec<u8>,
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![1, 1];
+        out.extend_from_slice(&self.code.to_be_bytes());
+        out.extend_from_slice(&self.request_id.to_be_bytes());
+        for group in &self.groups {
+            out.push(group.tag);
+            for attribute in &group.attributes {
+                for (i, value) in attribute.values.iter().enumerate() {
+                    let name = if i == 0 {
+                        attribute.name.as_bytes()
+                    } else {
+                        &[]
+                    };
+                    let value = match value {
+                        IppValue::Integer(n) => n.to_be_bytes().to_vec(),
+                        IppValue::Boolean(b) => vec![*b as u8],
+                        IppValue::String(s) => s.as_bytes().to_vec(),
+                        IppValue::Bytes(b) => b.clone(),
+                    };
+                    out.push(attribute.tag);
+                    out.extend_from_slice(&(name.len() as u16).to_be_bytes());
+                    out.extend_from_slice(name);
+                    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+                    out.extend_from_slice(&value);
+                }
+            }
+        }
+        out.push(END_OF_ATTRIBUTES);
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = ByteReader { bytes, pos: 0 };
+        reader.take(2)?;
+        let code = reader.u16()?;
+        let request_id = u32::from_be_bytes(reader.take(4)?.try_into().unwrap());
+        let mut groups: Vec<Group> = Vec::new();
+        loop {
+            let tag = reader.u8()?;
+            if tag == END_OF_ATTRIBUTES {
+                break;
+            }
+            if tag < 0x10 {
+                groups.push(Group {
+                    tag,
+                    attributes: Vec::new(),
+                });
+                continue;
+            }
+            let name_len = reader.u16()? as usize;
+            let name = String::from_utf8_lossy(reader.take(name_len)?).into_owned();
+            let value_len = reader.u16()? as usize;
+            let raw = reader.take(value_len)?;
+            let value = match tag {
+                TAG_INTEGER | TAG_ENUM if raw.len() == 4 => {
+                    IppValue::Integer(i32::from_be_bytes(raw.try_into().unwrap()))
+                }
+                TAG_BOOLEAN if raw.len() == 1 => IppValue::Boolean(raw[0] != 0),
+                TAG_TEXT..=TAG_MIME_TYPE => {
+                    IppValue::String(String::from_utf8_lossy(raw).into_owned())
+                }
+                _ => IppValue::Bytes(raw.to_vec()),
+            };
+            let group = groups
+                .last_mut()
+                .ok_or("IPP attribute outside of a group")?;
+            match group.attributes.last_mut() {
+                Some(attribute) if name.is_empty() => attribute.values.push(value),
+                _ => group.attributes.push(Attribute {
+                    tag,
+                    name,
+                    values: vec![value],
+                }),
+            }
+        }
+        Ok(Message {
+            code,
+            request_id,
+            groups,
+            data: bytes[reader.pos..].to_vec(),
+        })
+    }
+
+    /// First attribute called `name` in any group with `group_tag`.
+    pub fn attribute(&self, group_tag: u8, name: &str) -> Option<&Attribute> {
+        self.groups
+            .iter()
+            .filter(|group| group.tag == group_tag)
+            .flat_map(|group| &group.attributes)
+            .find(|attribute| attribute.name == name)
+    }
+
+    fn integer(&self, group_tag: u8, name: &str) -> Option<i32> {
+        match self.attribute(group_tag, name)?.values.first()? {
+            IppValue::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn strings(&self, group_tag: u8, name: &str) -> Vec<String> {
+        self.attribute(group_tag, name)
+            .map(|attribute| {
+                attribute
+                    .values
+                    .iter()
+                    .filter_map(|value| match value {
+                        IppValue::String(s) => Some(s.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn string(&self, group_tag: u8, name: &str) -> Option<String> {
+        self.strings(group_tag, name).into_iter().next()
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos + n;
+        if end > self.bytes.len() {
+            return Err("Truncated IPP message".to_string());
+        }
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum IppJobState {
+    Pending,
+    PendingHeld,
+    Processing,
+    ProcessingStopped,
+    Canceled,
+    Aborted,
+    Completed,
+    Unknown,
+}
+
+impl IppJobState {
+    fn from_enum(value: i32) -> Self {
+        match value {
+            3 => IppJobState::Pending,
+            4 => IppJobState::PendingHeld,
+            5 => IppJobState::Processing,
+            6 => IppJobState::ProcessingStopped,
+            7 => IppJobState::Canceled,
+            8 => IppJobState::Aborted,
+            9 => IppJobState::Completed,
+            _ => IppJobState::Unknown,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IppJob {
+    pub id: i32,
+    pub state: IppJobState,
+    pub state_reasons: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IppPrinterState {
+    Idle,
+    Processing,
+    Stopped,
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IppPrinterAttributes {
+    pub name: Option<String>,
+    pub make_and_model: Option<String>,
+    pub state: IppPrinterState,
+    pub state_reasons: Vec<String>,
+    pub document_formats: Vec<String>,
+}
+
+impl IppPrinterAttributes {
+    /// Maps `printer-state-reasons` keywords onto the ESC/POS status fields.
+    pub fn status(&self) -> PrinterStatus {
+        let reason = |prefix: &str| self.state_reasons.iter().any(|r| r.starts_with(prefix));
+        PrinterStatus {
+            online: self.state != IppPrinterState::Stopped && !reason("offline"),
+            cover_open: reason("cover-open") || reason("door-open"),
+            paper_near_end: reason("media-low"),
+            paper_end: reason("media-empty") || reason("media-needed"),
+            unrecoverable_error: reason("other-error"),
+            auto_recoverable_error: reason("media-jam"),
+            ..PrinterStatus::default()
+        }
+    }
+}
+
+/// Picks an IPP `document-format` for a spooled payload.
+pub fn document_format(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(b"%PDF-") {
+        "application/pdf"
+    } else if std::str::from_utf8(bytes).is_ok_and(|text| {
+        text.chars()
+            .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t' | '\x0c'))
+    }) {
+        "text/plain; charset=utf-8"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// Client for one printer URI, e.g. `ipp://192.168.1.50/ipp/print` or
+/// `ipp://cups.local:631/printers/Office`.
+pub struct IppClient {
+    uri: String,
+    host: String,
+    port: u16,
+    path: String,
+    connect_timeout: Duration,
+    timeout: Duration,
+}
+
+impl IppClient {
+    pub fn new(uri: &str) -> Result<Self, String> {
+        let rest = uri
+            .strip_prefix("ipp://")
+            .or_else(|| uri.strip_prefix("http://"))
+            .ok_or_else(|| format!("Unsupported IPP URI '{}': expected ipp://", uri))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/ipp/print"),
+        };
+        let (host, port) = match authority.strip_prefix('[') {
+            // [IPv6]:port
+            Some(bracketed) => match bracketed.split_once(']') {
+                Some((host, after)) => (host, after.strip_prefix(':')),
+                None => return Err(format!("Invalid host in IPP URI '{}'", uri)),
+            },
+            None => match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = match port {
+            Some(port) => port
+                .parse()
+                .map_err(|_| format!("Invalid port in IPP URI '{}'", uri))?,
+            None => 631,
+        };
+        if host.is_empty() {
+            return Err(format!("Missing host in IPP URI '{}'", uri));
+        }
+        Ok(IppClient {
+            uri: uri.to_string(),
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            connect_timeout: IPP_TIMEOUT,
+            timeout: IPP_TIMEOUT,
+        })
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Overrides the read and write timeout of every request but Print-Job,
+    /// which waits at least `IPP_TIMEOUT`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn print_job(
+        &self,
+        job_name: &str,
+        user: &str,
+        format: &str,
+        data: &[u8],
+    ) -> Result<IppJob, PrintError> {
+        let response = self.request(
+            PRINT_JOB,
+            vec![
+                Attribute::string(TAG_NAME, "requesting-user-name", user),
+                Attribute::string(TAG_NAME, "job-name", job_name),
+                Attribute::string(TAG_MIME_TYPE, "document-format", format),
+            ],
+            data,
+        )?;
+        job_from(&response)
+    }
+
+    pub fn get_job_attributes(&self, job_id: i32) -> Result<IppJob, PrintError> {
+        let response = self.request(
+            GET_JOB_ATTRIBUTES,
+            vec![
+                Attribute::new(TAG_INTEGER, "job-id", IppValue::Integer(job_id)),
+                Attribute {
+                    tag: TAG_KEYWORD,
+                    name: "requested-attributes".to_string(),
+                    values: ["job-id", "job-state", "job-state-reasons"]
+                        .iter()
+                        .map(|s| IppValue::String(s.to_string()))
+                        .collect(),
+                },
+            ],
+            &[],
+        )?;
+        job_from(&response)
+    }
+
+    pub fn get_printer_attributes(&self) -> Result<IppPrinterAttributes, PrintError> {
+        let requested = [
+            "printer-name",
+            "printer-make-and-model",
+            "printer-state",
+            "printer-state-reasons",
+            "document-format-supported",
+        ];
+        let response = self.request(
+            GET_PRINTER_ATTRIBUTES,
+            vec![Attribute {
+                tag: TAG_KEYWORD,
+                name: "requested-attributes".to_string(),
+                values: requested
+                    .iter()
+                    .map(|s| IppValue::String(s.to_string()))
+                    .collect(),
+            }],
+            &[],
+        )?;
+        let state = match response.integer(PRINTER_ATTRIBUTES, "printer-state") {
+            Some(3) => IppPrinterState::Idle,
+            Some(4) => IppPrinterState::Processing,
+            Some(5) => IppPrinterState::Stopped,
+            _ => IppPrinterState::Unknown,
+        };
+        Ok(IppPrinterAttributes {
+            name: response.string(PRINTER_ATTRIBUTES, "printer-name"),
+            make_and_model: response.string(PRINTER_ATTRIBUTES, "printer-make-and-model"),
+            state,
+            state_reasons: response
+                .strings(PRINTER_ATTRIBUTES, "printer-state-reasons")
+                .into_iter()
+                .filter(|r| r != "none")
+                .collect(),
+            document_formats: response.strings(PRINTER_ATTRIBUTES, "document-format-supported"),
+        })
+    }
+
+    /// Sends one request with the standard operation attributes followed by
+    /// `attributes`, and checks the response status. Error statuses for a
+    /// bad request count as invalid, and a printer that is busy or not
+    /// accepting jobs as not ready.
+    fn request(
+        &self,
+        operation: u16,
+        attributes: Vec<Attribute>,
+        data: &[u8],
+    ) -> Result<Message, PrintError> {
+        let mut operation_attributes = vec![
+            Attribute::string(TAG_CHARSET, "attributes-charset", "utf-8"),
+            Attribute::string(TAG_LANGUAGE, "attributes-natural-language", "en"),
+            Attribute::string(TAG_URI, "printer-uri", &self.uri),
+        ];
+        operation_attributes.extend(attributes);
+        let body = Message {
+            code: operation,
+            request_id: 1,
+            groups: vec![Group {
+                tag: OPERATION_ATTRIBUTES,
+                attributes: operation_attributes,
+            }],
+            data: data.to_vec(),
+        }
+        .encode();
+
+        let response = self
+            .post(self.connect()?, self.timeout_for(operation), &body)
+            .map_err(|e| PrintError::io(&format!("IPP request to {} failed", self.uri), e))?;
+        let message = Message::decode(&response).map_err(|e| {
+            PrintError::new(
+                PrintErrorKind::Io,
+                format!("Bad IPP response from {}: {}", self.uri, e),
+            )
+        })?;
+        // successful-ok through successful-ok-events-complete
+        if message.code > 0x00ff {
+            let kind = match message.code {
+                0x0400..=0x04ff => PrintErrorKind::Invalid,
+                // server-error-not-accepting-jobs, server-error-busy
+                0x0506 | 0x0507 => PrintErrorKind::NotReady,
+                _ => PrintErrorKind::Io,
+            };
+            let detail = message
+                .string(OPERATION_ATTRIBUTES, "status-message")
+                .unwrap_or_default();
+            let message = format!(
+                "IPP printer {} returned status 0x{:04x} {}",
+                self.uri, message.code, detail
+            );
+            return Err(PrintError::new(kind, message.trim_end()));
+        }
+        Ok(message)
+    }
+
+    /// The read and write timeout for `operation`.
+    fn timeout_for(&self, operation: u16) -> Duration {
+        if operation == PRINT_JOB {
+            self.timeout.max(IPP_TIMEOUT)
+        } else {
+            self.timeout
+        }
+    }
+
+    fn connect(&self) -> Result<TcpStream, PrintError> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Cannot resolve {}: {}", self.host, e),
+                )
+            })
+            .and_then(|mut addrs| {
+                addrs
+                    .next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No addresses found"))
+            })
+            .map_err(|e| PrintError::connect(&self.uri, e))?;
+        TcpStream::connect_timeout(&addr, self.connect_timeout)
+            .map_err(|e| PrintError::connect(&self.uri, e))
+    }
+
+    fn post(&self, mut stream: TcpStream, timeout: Duration, body: &[u8]) -> io::Result<Vec<u8>> {
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let host = if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        };
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/ipp\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            host,
+            body.len()
+        )?;
+        stream.write_all(body)?;
+        stream.flush()?;
+        read_http_response(BufReader::new(stream))
+    }
+}
+
+fn job_from(response: &Message) -> Result<IppJob, PrintError> {
+    let id = response
+        .integer(JOB_ATTRIBUTES, "job-id")
+        .ok_or_else(|| PrintError::new(PrintErrorKind::Io, "IPP response has no job-id"))?;
+    Ok(IppJob {
+        id,
+        state: response
+            .integer(JOB_ATTRIBUTES, "job-state")
+            .map(IppJobState::from_enum)
+            .unwrap_or(IppJobState::Unknown),
+        state_reasons: response
+            .strings(JOB_ATTRIBUTES, "job-state-reasons")
+            .into_iter()
+            .filter(|r| r != "none")
+            .collect(),
+    })
+}
+
+/// Reads an HTTP/1.1 response and returns its body, handling both
+/// `Content-Length` and chunked transfer encoding.
+fn read_http_response<R: BufRead>(mut reader: R) -> io::Result<Vec<u8>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+    let status = status_line.split_whitespace().nth(1).unwrap_or("");
+    if status != "200" {
+        return Err(invalid(format!("HTTP {}", status_line.trim())));
+    }
+
+    let mut content_length = None;
+    let mut chunked = false;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse::<usize>().ok();
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.eq_ignore_ascii_case("chunked");
+            }
+        }
+    }
+
+    let mut body = Vec::new();
+    if chunked {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size)?;
+            let size = size.trim().split(';').next().unwrap_or("");
+            let size = usize::from_str_radix(size, 16)
+                .map_err(|_| invalid(format!("Bad chunk size '{}'", size)))?;
+            if size == 0 {
+                break;
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..])?;
+            let mut crlf = String::new();
+            reader.read_line(&mut crlf)?;
+        }
+    } else if let Some(length) = content_length {
+        body.resize(length, 0);
+        reader.read_exact(&mut body)?;
+    } else {
+        reader.read_to_end(&mut body)?;
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /// Local IPP stand-in: accepts one request and answers with `respond`.
+    /// Joining the handle returns the decoded request.
+    fn stand_in(
+        chunked: bool,
+        respond: impl FnOnce(&Message) -> Message + Send + 'static,
+    ) -> (String, JoinHandle<Message>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!(
+            "ipp://127.0.0.1:{}/printers/Office",
+            listener.local_addr().unwrap().port()
+        );
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("Content-Length: ") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let request = Message::decode(&body).unwrap();
+
+            let response = respond(&request).encode();
+            let mut stream = reader.into_inner();
+            if chunked {
+                let (head, tail) = response.split_at(response.len() / 2);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"
+                )
+                .unwrap();
+                for chunk in [head, tail] {
+                    write!(stream, "{:x}\r\n", chunk.len()).unwrap();
+                    stream.write_all(chunk).unwrap();
+                    stream.write_all(b"\r\n").unwrap();
+                }
+                stream.write_all(b"0\r\n\r\n").unwrap();
+            } else {
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/ipp\r\nContent-Length: {}\r\n\r\n",
+                    response.len()
+                )
+                .unwrap();
+                stream.write_all(&response).unwrap();
+            }
+            request
+        });
+        (uri, handle)
+    }
+
+    fn response(status: u16, tag: u8, attributes: Vec<Attribute>) -> Message {
+        Message {
+            code: status,
+            request_id: 1,
+            groups: vec![
+                Group {
+                    tag: OPERATION_ATTRIBUTES,
+                    attributes: vec![Attribute::string(
+                        TAG_CHARSET,
+                        "attributes-charset",
+                        "utf-8",
+                    )],
+                },
+                Group { tag, attributes },
+            ],
+            data: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn message_round_trips_with_multi_valued_attributes() {
+        let message = response(
+            0,
+            PRINTER_ATTRIBUTES,
+            vec![
+                Attribute::new(TAG_ENUM, "printer-state", IppValue::Integer(3)),
+                Attribute {
+                    tag: TAG_MIME_TYPE,
+                    name: "document-format-supported".to_string(),
+                    values: vec![
+                        IppValue::String("application/pdf".to_string()),
+                        IppValue::String("text/plain".to_string()),
+                    ],
+                },
+                Attribute::new(TAG_BOOLEAN, "color-supported", IppValue::Boolean(false)),
+            ],
+        );
+        assert_eq!(Message::decode(&message.encode()).unwrap(), message);
+        assert!(Message::decode(&[1, 1, 0]).is_err());
+    }
+
+    #[test]
+    fn print_job_submits_document_and_returns_job() {
+        let (uri, server) = stand_in(false, |_| {
+            response(
+                0,
+                JOB_ATTRIBUTES,
+                vec![
+                    Attribute::new(TAG_INTEGER, "job-id", IppValue::Integer(42)),
+                    Attribute::new(TAG_ENUM, "job-state", IppValue::Integer(3)),
+                    Attribute::string(TAG_KEYWORD, "job-state-reasons", "none"),
+                ],
+            )
+        });
+        let client = IppClient::new(&uri).unwrap();
+        let pdf = b"%PDF-1.4 end of day";
+        let job = client
+            .print_job("End of day", "chefcloud", document_format(pdf), pdf)
+            .unwrap();
+        assert_eq!(job.id, 42);
+        assert_eq!(job.state, IppJobState::Pending);
+        assert!(job.state_reasons.is_empty());
+
+        let request = server.join().unwrap();
+        assert_eq!(request.code, PRINT_JOB);
+        assert_eq!(request.data, pdf);
+        assert_eq!(
+            request.string(OPERATION_ATTRIBUTES, "printer-uri").unwrap(),
+            uri
+        );
+        assert_eq!(
+            request
+                .string(OPERATION_ATTRIBUTES, "document-format")
+                .unwrap(),
+            "application/pdf"
+        );
+    }
+
+    #[test]
+    fn reads_printer_attributes_from_chunked_response() {
+        let (uri, server) = stand_in(true, |_| {
+            response(
+                0,
+                PRINTER_ATTRIBUTES,
+                vec![
+                    Attribute::string(TAG_TEXT, "printer-make-and-model", "HP LaserJet M404"),
+                    Attribute::new(TAG_ENUM, "printer-state", IppValue::Integer(5)),
+                    Attribute {
+                        tag: TAG_KEYWORD,
+                        name: "printer-state-reasons".to_string(),
+                        values: vec![
+                            IppValue::String("media-empty-error".to_string()),
+                            IppValue::String("door-open-report".to_string()),
+                        ],
+                    },
+                ],
+            )
+        });
+        let attributes = IppClient::new(&uri)
+            .unwrap()
+            .get_printer_attributes()
+            .unwrap();
+        assert_eq!(
+            attributes.make_and_model.as_deref(),
+            Some("HP LaserJet M404")
+        );
+        assert_eq!(attributes.state, IppPrinterState::Stopped);
+        let status = attributes.status();
+        assert!(!status.online);
+        assert!(status.paper_end);
+        assert!(status.cover_open);
+
+        let request = server.join().unwrap();
+        assert_eq!(request.code, GET_PRINTER_ATTRIBUTES);
+    }
+
+    #[test]
+    fn get_job_attributes_and_error_status() {
+        let (uri, server) = stand_in(false, |request| {
+            assert_eq!(request.integer(OPERATION_ATTRIBUTES, "job-id"), Some(42));
+            response(
+                0,
+                JOB_ATTRIBUTES,
+                vec![
+                    Attribute::new(TAG_INTEGER, "job-id", IppValue::Integer(42)),
+                    Attribute::new(TAG_ENUM, "job-state", IppValue::Integer(9)),
+                ],
+            )
+        });
+        let job = IppClient::new(&uri)
+            .unwrap()
+            .get_job_attributes(42)
+            .unwrap();
+        assert_eq!(job.state, IppJobState::Completed);
+        server.join().unwrap();
+
+        // client-error-not-found
+        let (uri, server) = stand_in(false, |_| response(0x0406, JOB_ATTRIBUTES, Vec::new()));
+        let err = IppClient::new(&uri)
+            .unwrap()
+            .get_job_attributes(7)
+            .unwrap_err();
+        assert!(err.message.contains("0x0406"), "{}", err);
+        assert_eq!(err.kind, PrintErrorKind::Invalid);
+        server.join().unwrap();
+
+        // server-error-busy
+        let (uri, server) = stand_in(false, |_| response(0x0507, JOB_ATTRIBUTES, Vec::new()));
+        let err = IppClient::new(&uri)
+            .unwrap()
+            .get_job_attributes(7)
+            .unwrap_err();
+        assert_eq!(err.kind, PrintErrorKind::NotReady);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn classifies_connection_failures() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let uri = format!("ipp://127.0.0.1:{}/ipp/print", port);
+        let err = IppClient::new(&uri)
+            .unwrap()
+            .get_printer_attributes()
+            .unwrap_err();
+        assert_eq!(err.kind, PrintErrorKind::Refused);
+    }
+
+    #[test]
+    fn print_job_waits_at_least_the_ipp_timeout() {
+        let client = IppClient::new("ipp://office.local/ipp/print")
+            .unwrap()
+            .with_timeout(Duration::from_secs(10));
+        assert_eq!(client.timeout_for(PRINT_JOB), IPP_TIMEOUT);
+        assert_eq!(
+            client.timeout_for(GET_JOB_ATTRIBUTES),
+            Duration::from_secs(10)
+        );
+        let slow = client.with_timeout(Duration::from_secs(60));
+        assert_eq!(slow.timeout_for(PRINT_JOB), Duration::from_secs(60));
+
+        // Connecting has its own, usually shorter, timeout.
+        let client = slow.with_connect_timeout(Duration::from_secs(3));
+        assert_eq!(client.connect_timeout, Duration::from_secs(3));
+        assert_eq!(
+            client.timeout_for(GET_JOB_ATTRIBUTES),
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn parses_uris_and_sniffs_formats() {
+        let client = IppClient::new("ipp://office.local/ipp/print").unwrap();
+        assert_eq!((client.host.as_str(), client.port), ("office.local", 631));
+        let client = IppClient::new("ipp://10.0.0.9:8631/printers/A4").unwrap();
+        assert_eq!(
+            (client.host.as_str(), client.port, client.path.as_str()),
+            ("10.0.0.9", 8631, "/printers/A4")
+        );
+        assert!(IppClient::new("ipps://secure/ipp").is_err());
+
+        assert_eq!(document_format(b"%PDF-1.7"), "application/pdf");
+        assert_eq!(
+            document_format("Z-report\n".as_bytes()),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(document_format(b"\x1b@receipt"), "application/octet-stream");
+    }
+}
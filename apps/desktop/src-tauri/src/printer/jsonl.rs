@@ -0,0 +1,100 @@
+//! Append-only JSON Lines logs for local audit trails, such as cash drawer
+//! openings and failovers. Each event is one line, so recording never
+//! rewrites earlier events.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// An event that knows when it happened (unix ms).
+pub trait Timestamped {
+    fn at(&self) -> u64;
+}
+
+pub struct JsonlLog<T> {
+    path: PathBuf,
+    lock: Mutex<()>,
+    events: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Timestamped> JsonlLog<T> {
+    pub fn new(path: PathBuf) -> Self {
+        JsonlLog {
+            path,
+            lock: Mutex::new(()),
+            events: PhantomData,
+        }
+    }
+
+    pub fn record(&self, event: &T) -> Result<(), String> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let line = serde_json::to_string(event).map_err(|e| e.to_string())?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open {}: {}", self.path.display(), e))?;
+        writeln!(file, "{}", line)
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+
+    /// Events recorded at or after `since` (unix ms), oldest first. Lines
+    /// that do not parse, such as one cut short by a crash, are skipped.
+    pub fn list(&self, since: u64) -> Vec<T> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        fs::read_to_string(&self.path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| serde_json::from_str::<T>(line).ok())
+            .filter(|event| event.at() >= since)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Note {
+        at: u64,
+        text: String,
+    }
+
+    impl Timestamped for Note {
+        fn at(&self) -> u64 {
+            self.at
+        }
+    }
+
+    #[test]
+    fn skips_lines_that_do_not_parse() {
+        let path = std::env::temp_dir()
+            .join(format!("chefcloud-jsonl-{}", std::process::id()))
+            .join("notes.jsonl");
+        let _ = fs::remove_file(&path);
+        let log = JsonlLog::<Note>::new(path.clone());
+        let note = |at, text: &str| Note {
+            at,
+            text: text.to_string(),
+        };
+
+        log.record(&note(1_000, "first")).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{{\"at\": 1500, \"te").unwrap();
+        writeln!(file).unwrap();
+        log.record(&note(2_000, "second")).unwrap();
+
+        assert_eq!(log.list(0), [note(1_000, "first"), note(2_000, "second")]);
+    }
+}
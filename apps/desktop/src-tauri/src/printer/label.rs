@@ -0,0 +1,430 @@
+//! Label documents for Zebra (ZPL II) and TSC (TSPL) label printers, such as
+//! delivery bag labels and prep date/allergen labels.
+
+use serde::{Deserialize, Serialize};
+
+use super::config::{PrinterDefinition, PrinterLanguage};
+use super::escpos::Symbology;
+
+/// A label, laid out in millimetres from its top-left corner.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Label {
+    pub width: f32,
+    pub height: f32,
+    /// Gap between labels on the roll, for TSPL media sensing.
+    #[serde(default = "default_gap")]
+    pub gap: f32,
+    #[serde(default = "default_copies")]
+    pub copies: u32,
+    pub elements: Vec<LabelElement>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum LabelElement {
+    Text {
+        x: f32,
+        y: f32,
+        content: String,
+        /// Character height.
+        #[serde(default = "default_text_height")]
+        height: f32,
+    },
+    Barcode {
+        x: f32,
+        y: f32,
+        data: String,
+        #[serde(default)]
+        symbology: Symbology,
+        #[serde(default = "default_barcode_height")]
+        height: f32,
+        /// Narrow bar width in dots.
+        #[serde(default = "default_module")]
+        module: u8,
+        /// Print the data as text under the bars.
+        #[serde(default = "default_hri")]
+        hri: bool,
+    },
+    Qr {
+        x: f32,
+        y: f32,
+        data: String,
+        /// Module size in dots.
+        #[serde(default = "default_cell")]
+        cell: u8,
+    },
+    /// A rectangle outline; a box with a height of one line width draws a
+    /// horizontal rule.
+    Box {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        #[serde(default = "default_thickness")]
+        thickness: f32,
+    },
+}
+
+fn default_gap() -> f32 {
+    3.0
+}
+
+fn default_copies() -> u32 {
+    1
+}
+
+fn default_text_height() -> f32 {
+    3.0
+}
+
+fn default_barcode_height() -> f32 {
+    10.0
+}
+
+fn default_module() -> u8 {
+    2
+}
+
+fn default_hri() -> bool {
+    true
+}
+
+fn default_cell() -> u8 {
+    4
+}
+
+fn default_thickness() -> f32 {
+    0.3
+}
+
+impl Label {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.width > 0.0 && self.height > 0.0) {
+            return Err("Label width and height must be positive".to_string());
+        }
+        if self.copies == 0 {
+            return Err("Label copies must be at least 1".to_string());
+        }
+        for (i, element) in self.elements.iter().enumerate() {
+            let (x, y, right, bottom) = match element {
+                LabelElement::Text { x, y, content, .. } => {
+                    if content.is_empty() {
+                        return Err(format!("elements[{}]: text is empty", i));
+                    }
+                    (*x, *y, *x, *y)
+                }
+                LabelElement::Barcode {
+                    x,
+                    y,
+                    data,
+                    symbology,
+                    ..
+                } => {
+                    symbology
+                        .validate(data)
+                        .map_err(|e| format!("elements[{}]: {}", i, e))?;
+                    (*x, *y, *x, *y)
+                }
+                LabelElement::Qr { x, y, data, .. } => {
+                    if data.is_empty() {
+                        return Err(format!("elements[{}]: QR data is empty", i));
+                    }
+                    (*x, *y, *x, *y)
+                }
+                LabelElement::Box {
+                    x,
+                    y,
+                    width,
+                    height,
+                    ..
+                } => (*x, *y, x + width, y + height),
+            };
+            let inside = x >= 0.0 && y >= 0.0 && right <= self.width && bottom <= self.height;
+            if !inside {
+                return Err(format!(
+                    "elements[{}]: outside the {}x{} mm label",
+                    i, self.width, self.height
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders `label` in the command language of `printer`.
+pub fn render(label: &Label, printer: &PrinterDefinition) -> Result<Vec<u8>, String> {
+    label.validate()?;
+    match printer.language {
+        PrinterLanguage::Zpl => Ok(zpl(label, printer.dpi).into_bytes()),
+        PrinterLanguage::Tspl => Ok(tspl(label, printer.dpi).into_bytes()),
+        PrinterLanguage::EscPos | PrinterLanguage::Star => Err(format!(
+            "Printer '{}' does not print ZPL or TSPL labels",
+            printer.id
+        )),
+    }
+}
+
+fn dots(mm: f32, dpi: u16) -> u32 {
+    (mm * dpi as f32 / 25.4).round().max(0.0) as u32
+}
+
+fn zpl(label: &Label, dpi: u16) -> String {
+    let d = |mm: f32| dots(mm, dpi);
+    // ^CI28 - UTF-8 field data
+    let mut out = format!(
+        "^XA\n^CI28\n^PW{}\n^LL{}\n",
+        d(label.width),
+        d(label.height)
+    );
+    for element in &label.elements {
+        let line = match element {
+            LabelElement::Text {
+                x,
+                y,
+                content,
+                height,
+            } => format!(
+                "^FO{},{}^A0N,{h},{h}^FH^FD{}^FS",
+                d(*x),
+                d(*y),
+                zpl_field(content),
+                h = d(*height)
+            ),
+            LabelElement::Barcode {
+                x,
+                y,
+                data,
+                symbology,
+                height,
+                module,
+                hri,
+            } => {
+                let hri = if *hri { 'Y' } else { 'N' };
+                let h = d(*height);
+                let barcode = match symbology {
+                    Symbology::Code128 => format!("^BCN,{},{},N,N", h, hri),
+                    Symbology::Ean13 => format!("^BEN,{},{},N", h, hri),
+                    Symbology::Code39 => format!("^B3N,N,{},{},N", h, hri),
+                };
+                format!(
+                    "^FO{},{}^BY{}{}^FH^FD{}^FS",
+                    d(*x),
+                    d(*y),
+                    module,
+                    barcode,
+                    zpl_field(data)
+                )
+            }
+            // Model 2, error correction M, automatic input mode
+            LabelElement::Qr { x, y, data, cell } => format!(
+                "^FO{},{}^BQN,2,{}^FH^FDMA,{}^FS",
+                d(*x),
+                d(*y),
+                (*cell).clamp(1, 10),
+                zpl_field(data)
+            ),
+            LabelElement::Box {
+                x,
+                y,
+                width,
+                height,
+                thickness,
+            } => {
+                let t = d(*thickness).max(1);
+                format!(
+                    "^FO{},{}^GB{},{},{}^FS",
+                    d(*x),
+                    d(*y),
+                    d(*width).max(t),
+                    d(*height).max(t),
+                    t
+                )
+            }
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out.push_str(&format!("^PQ{}\n^XZ\n", label.copies));
+    out
+}
+
+/// Field data with `^FH` hex escapes for the characters ZPL treats as
+/// command prefixes.
+fn zpl_field(text: &str) -> String {
+    text.replace('_', "_5F")
+        .replace('^', "_5E")
+        .replace('~', "_7E")
+}
+
+fn tspl(label: &Label, dpi: u16) -> String {
+    let d = |mm: f32| dots(mm, dpi);
+    let mut out = format!(
+        "SIZE {} mm,{} mm\r\nGAP {} mm,0 mm\r\nDIRECTION 1\r\nCODEPAGE UTF-8\r\nCLS\r\n",
+        label.width, label.height, label.gap
+    );
+    for element in &label.elements {
+        let line = match element {
+            // Font "0" is the scalable font, sized in points.
+            LabelElement::Text {
+                x,
+                y,
+                content,
+                height,
+            } => {
+                let points = (height * 72.0 / 25.4).round().max(1.0);
+                format!(
+                    "TEXT {},{},\"0\",0,{p},{p},\"{}\"",
+                    d(*x),
+                    d(*y),
+                    tspl_string(content),
+                    p = points
+                )
+            }
+            LabelElement::Barcode {
+                x,
+                y,
+                data,
+                symbology,
+                height,
+                module,
+                hri,
+            } => {
+                let (kind, wide) = match symbology {
+                    Symbology::Code128 => ("128", *module),
+                    Symbology::Ean13 => ("EAN13", *module),
+                    Symbology::Code39 => ("39", module.saturating_mul(2)),
+                };
+                format!(
+                    "BARCODE {},{},\"{}\",{},{},0,{},{},\"{}\"",
+                    d(*x),
+                    d(*y),
+                    kind,
+                    d(*height),
+                    *hri as u8,
+                    module,
+                    wide,
+                    tspl_string(data)
+                )
+            }
+            LabelElement::Qr { x, y, data, cell } => format!(
+                "QRCODE {},{},M,{},A,0,\"{}\"",
+                d(*x),
+                d(*y),
+                (*cell).clamp(1, 10),
+                tspl_string(data)
+            ),
+            LabelElement::Box {
+                x,
+                y,
+                width,
+                height,
+                thickness,
+            } => format!(
+                "BOX {},{},{},{},{}",
+                d(*x),
+                d(*y),
+                d(x + width),
+                d(y + height),
+                d(*thickness).max(1)
+            ),
+        };
+        out.push_str(&line);
+        out.push_str("\r\n");
+    }
+    out.push_str(&format!("PRINT 1,{}\r\n", label.copies));
+    out
+}
+
+/// A TSPL string literal body; `\["]` stands for a double quote.
+fn tspl_string(text: &str) -> String {
+    text.replace('"', "\\[\"]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn bag_label() -> Label {
+        serde_json::from_value(json!({
+            "width": 50, "height": 30, "copies": 2,
+            "elements": [
+                { "type": "text", "x": 2, "y": 2, "content": "Order #A-1042 ^ \"Ama\"", "height": 4 },
+                { "type": "barcode", "x": 2, "y": 10, "data": "A1042", "height": 8 },
+                { "type": "qr", "x": 35, "y": 10, "data": "https://chefcloud.app/t/A1042" },
+                { "type": "box", "x": 1, "y": 1, "width": 48, "height": 28 }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn label_printer(language: PrinterLanguage) -> PrinterDefinition {
+        PrinterDefinition {
+            language,
+            ..PrinterDefinition::tcp("bags", "10.0.0.40", 9100)
+        }
+    }
+
+    #[test]
+    fn renders_zpl_at_printer_dpi() {
+        let zpl = render(&bag_label(), &label_printer(PrinterLanguage::Zpl)).unwrap();
+        assert_eq!(
+            String::from_utf8(zpl).unwrap(),
+            concat!(
+                "^XA\n^CI28\n^PW400\n^LL240\n",
+                "^FO16,16^A0N,32,32^FH^FDOrder #A-1042 _5E \"Ama\"^FS\n",
+                "^FO16,80^BY2^BCN,64,Y,N,N^FH^FDA1042^FS\n",
+                "^FO280,80^BQN,2,4^FH^FDMA,https://chefcloud.app/t/A1042^FS\n",
+                "^FO8,8^GB384,224,2^FS\n",
+                "^PQ2\n^XZ\n",
+            )
+        );
+    }
+
+    #[test]
+    fn renders_tspl() {
+        let tspl = render(&bag_label(), &label_printer(PrinterLanguage::Tspl)).unwrap();
+        assert_eq!(
+            String::from_utf8(tspl).unwrap(),
+            concat!(
+                "SIZE 50 mm,30 mm\r\nGAP 3 mm,0 mm\r\nDIRECTION 1\r\nCODEPAGE UTF-8\r\nCLS\r\n",
+                "TEXT 16,16,\"0\",0,11,11,\"Order #A-1042 ^ \\[\"]Ama\\[\"]\"\r\n",
+                "BARCODE 16,80,\"128\",64,1,0,2,2,\"A1042\"\r\n",
+                "QRCODE 280,80,M,4,A,0,\"https://chefcloud.app/t/A1042\"\r\n",
+                "BOX 8,8,392,232,2\r\n",
+                "PRINT 1,2\r\n",
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_labels_and_receipt_printers() {
+        let mut label = bag_label();
+        label.elements.push(LabelElement::Box {
+            x: 40.0,
+            y: 0.0,
+            width: 20.0,
+            height: 5.0,
+            thickness: 0.3,
+        });
+        let err = render(&label, &label_printer(PrinterLanguage::Zpl)).unwrap_err();
+        assert!(err.starts_with("elements[4]: outside"), "{}", err);
+
+        label.elements.truncate(1);
+        label.elements.push(LabelElement::Barcode {
+            x: 0.0,
+            y: 0.0,
+            data: "12AB".to_string(),
+            symbology: Symbology::Ean13,
+            height: 10.0,
+            module: 2,
+            hri: true,
+        });
+        let err = render(&label, &label_printer(PrinterLanguage::Zpl)).unwrap_err();
+        assert!(err.starts_with("elements[1]: Invalid Ean13"), "{}", err);
+
+        let err = render(&bag_label(), &label_printer(PrinterLanguage::EscPos)).unwrap_err();
+        assert_eq!(err, "Printer 'bags' does not print ZPL or TSPL labels");
+    }
+}
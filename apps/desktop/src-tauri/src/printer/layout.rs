@@ -0,0 +1,237 @@
+//! Column layout for receipt lines. A row is a list of cells across the
+//! printer's line, each aligned within its own column and either wrapped
+//! onto further lines or cut short with an ellipsis, so item names, prices
+//! and totals line up whatever the paper width.
+
+use serde::{Deserialize, Serialize};
+
+use super::escpos::Align;
+
+/// Between neighbouring cells.
+const GAP: &str = " ";
+const ELLIPSIS: &str = "...";
+
+/// What a cell does with text wider than its column.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    /// Continue on the next lines, breaking between words where possible.
+    #[default]
+    Wrap,
+    /// Keep to one line, ending in "...".
+    Ellipsis,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Cell {
+    pub content: String,
+    /// Width in characters. Cells without one share the rest of the line.
+    #[serde(default)]
+    pub width: Option<usize>,
+    #[serde(default)]
+    pub align: Align,
+    #[serde(default)]
+    pub overflow: Overflow,
+}
+
+impl Cell {
+    /// A left-aligned, wrapping cell that takes a share of the free width.
+    pub fn new(content: impl Into<String>) -> Self {
+        Cell {
+            content: content.into(),
+            ..Cell::default()
+        }
+    }
+
+    /// A cell exactly `width` characters wide.
+    pub fn fixed(content: impl Into<String>, width: usize, align: Align) -> Self {
+        Cell {
+            content: content.into(),
+            width: Some(width),
+            align,
+            ..Cell::default()
+        }
+    }
+}
+
+/// Lays `cells` out on lines `columns` characters wide, with as many lines
+/// as the tallest cell needs. Fixed widths are granted left to right while
+/// the line has room; the remaining width is split evenly between the
+/// other cells. Lines have trailing spaces removed.
+pub fn row(cells: &[Cell], columns: usize) -> Vec<String> {
+    let widths = widths(cells, columns);
+    let cell_lines: Vec<Vec<String>> = cells
+        .iter()
+        .zip(&widths)
+        .map(|(cell, &width)| match cell.overflow {
+            // Squeezed out by the fixed cells before it.
+            _ if width == 0 => Vec::new(),
+            Overflow::Wrap => wrap(&cell.content, width),
+            Overflow::Ellipsis => vec![ellipsize(&cell.content, width)],
+        })
+        .collect();
+    let height = cell_lines.iter().map(Vec::len).max().unwrap_or(0).max(1);
+
+    (0..height)
+        .map(|i| {
+            let mut line = String::new();
+            for (j, (cell, &width)) in cells.iter().zip(&widths).enumerate() {
+                if j > 0 {
+                    line.push_str(GAP);
+                }
+                let text = cell_lines[j].get(i).map(String::as_str).unwrap_or("");
+                line.push_str(&pad(text, width, cell.align));
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+fn widths(cells: &[Cell], columns: usize) -> Vec<usize> {
+    let mut free = columns.saturating_sub(GAP.len() * cells.len().saturating_sub(1));
+    let mut widths: Vec<usize> = cells
+        .iter()
+        .map(|cell| {
+            let width = cell.width.unwrap_or(0).min(free);
+            free -= width;
+            width
+        })
+        .collect();
+    let shared = cells.iter().filter(|cell| cell.width.is_none()).count();
+    let mut nth = 0;
+    for (cell, width) in cells.iter().zip(&mut widths) {
+        if cell.width.is_none() {
+            // The first shared cells get the odd characters.
+            *width = free / shared + usize::from(nth < free % shared);
+            nth += 1;
+        }
+    }
+    widths
+}
+
+/// Breaks `text` into lines at most `width` characters long, between words
+/// where it can and inside words longer than a line.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut used = 0;
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        while !word.is_empty() {
+            let needed = if used == 0 {
+                word.len()
+            } else {
+                used + 1 + word.len()
+            };
+            if needed <= width {
+                if used > 0 {
+                    line.push(' ');
+                }
+                line.extend(word.drain(..));
+                used = needed;
+            } else if used > 0 {
+                lines.push(std::mem::take(&mut line));
+                used = 0;
+            } else {
+                lines.push(word.drain(..width).collect());
+            }
+        }
+    }
+    if used > 0 || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// `text` cut to `width` characters, ending in "..." when it did not fit.
+pub fn ellipsize(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    if width <= ELLIPSIS.len() {
+        return text.chars().take(width).collect();
+    }
+    let kept: String = text.chars().take(width - ELLIPSIS.len()).collect();
+    format!("{}{}", kept.trim_end(), ELLIPSIS)
+}
+
+fn pad(text: &str, width: usize, align: Align) -> String {
+    let space = width.saturating_sub(text.chars().count());
+    let left = match align {
+        Align::Left => 0,
+        Align::Center => space / 2,
+        Align::Right => space,
+    };
+    format!("{}{}{}", " ".repeat(left), text, " ".repeat(space - left))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts_line_up_at_the_right_margin() {
+        for columns in [32, 42, 48] {
+            let lines: Vec<String> = [("Burger", "25.00"), ("Chips", "4.50"), ("TOTAL", "129.50")]
+                .iter()
+                .flat_map(|(label, amount)| {
+                    row(
+                        &[Cell::new(*label), Cell::fixed(*amount, 8, Align::Right)],
+                        columns,
+                    )
+                })
+                .collect();
+            assert!(
+                lines.iter().all(|l| l.chars().count() == columns),
+                "{:?}",
+                lines
+            );
+            assert!(lines[2].starts_with("TOTAL ") && lines[2].ends_with(" 129.50"));
+        }
+    }
+
+    #[test]
+    fn long_cells_wrap_inside_their_column() {
+        let cells = [
+            Cell::fixed("2x", 3, Align::Left),
+            Cell::new("Grilled tilapia with matoke and groundnut sauce"),
+            Cell::fixed("48,000", 7, Align::Right),
+        ];
+        let lines = row(&cells, 32);
+        assert_eq!(
+            lines,
+            [
+                "2x  Grilled tilapia with  48,000",
+                "    matoke and groundnut",
+                "    sauce",
+            ]
+        );
+        assert_eq!(
+            wrap("Supercalifragilistic", 8),
+            ["Supercal", "ifragili", "stic"]
+        );
+        assert_eq!(wrap("", 8), [""]);
+    }
+
+    #[test]
+    fn ellipsis_and_centred_cells_keep_to_one_line() {
+        let cells = [
+            Cell {
+                overflow: Overflow::Ellipsis,
+                ..Cell::new("Chocolate fudge brownie sundae")
+            },
+            Cell::new("ok"),
+        ];
+        // 31 characters after the gap, split 16 / 15.
+        assert_eq!(row(&cells, 32), ["Chocolate fud... ok"]);
+        assert_eq!(ellipsize("Tea", 2), "Te");
+
+        let centred = [Cell {
+            align: Align::Center,
+            ..Cell::new("TABLE 7")
+        }];
+        assert_eq!(row(&centred, 11), ["  TABLE 7"]);
+    }
+}
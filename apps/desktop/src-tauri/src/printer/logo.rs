@@ -0,0 +1,228 @@
+//! PNG/JPEG logos converted to 1-bit bitmaps for raster printing. Converted
+//! bitmaps are cached per file, size and dithering settings so only the
+//! first print pays for decoding and dithering.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use image::imageops::FilterType;
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+use super::bitmap::Bitmap;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum Dither {
+    #[default]
+    FloydSteinberg,
+    Threshold,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogoMode {
+    /// Send the bitmap with every job (`GS v 0`).
+    #[default]
+    Raster,
+    /// Print a copy stored in the printer's NV memory (`GS ( L`). Upload it
+    /// once with `store_printer_logo`.
+    Nv,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LogoSettings {
+    pub path: String,
+    /// Width in dots. Defaults to the image width, scaled down to fit the
+    /// paper.
+    #[serde(default)]
+    pub width: Option<u16>,
+    #[serde(default)]
+    pub dither: Dither,
+    /// Luminance below which a pixel prints black, 0-255.
+    #[serde(default = "default_threshold")]
+    pub threshold: u8,
+    #[serde(default)]
+    pub mode: LogoMode,
+    /// Two-character NV graphics key code.
+    #[serde(default = "default_nv_key")]
+    pub nv_key: String,
+}
+
+fn default_threshold() -> u8 {
+    128
+}
+
+fn default_nv_key() -> String {
+    "LG".to_string()
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: String,
+    modified: Option<SystemTime>,
+    width: usize,
+    dither: Dither,
+    threshold: u8,
+}
+
+fn cache() -> &'static Mutex<HashMap<CacheKey, Arc<Bitmap>>> {
+    static CACHE: OnceLock<Mutex<HashMap<CacheKey, Arc<Bitmap>>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+impl LogoSettings {
+    /// Loads the logo as a bitmap at most `max_dots` wide.
+    pub fn bitmap(&self, max_dots: usize) -> Result<Arc<Bitmap>, String> {
+        let path = Path::new(&self.path);
+        let modified = path.metadata().and_then(|m| m.modified()).ok();
+        let key = CacheKey {
+            path: self.path.clone(),
+            modified,
+            width: self
+                .width
+                .map(usize::from)
+                .unwrap_or(max_dots)
+                .min(max_dots),
+            dither: self.dither,
+            threshold: self.threshold,
+        };
+        if let Some(bitmap) = cache().lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+            return Ok(bitmap.clone());
+        }
+
+        let image =
+            image::open(path).map_err(|e| format!("Failed to load logo {}: {}", self.path, e))?;
+        let gray = flatten_alpha(&image);
+        let scaled = fit_width(&gray, key.width, self.width.is_some());
+        let bitmap = Arc::new(dither(&scaled, self.dither, self.threshold));
+
+        cache()
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, bitmap.clone());
+        Ok(bitmap)
+    }
+}
+
+/// Grayscale with transparent pixels composited onto white paper.
+fn flatten_alpha(image: &image::DynamicImage) -> GrayImage {
+    let rgba = image.to_rgba8();
+    GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+        let alpha = a as f32 / 255.0;
+        image::Luma([(luma * alpha + 255.0 * (1.0 - alpha)).round() as u8])
+    })
+}
+
+/// Scales down to `width` dots, or to exactly `width` when `exact` is set.
+fn fit_width(gray: &GrayImage, width: usize, exact: bool) -> GrayImage {
+    let current = gray.width() as usize;
+    if current == 0 || (current <= width && !exact) || current == width {
+        return gray.clone();
+    }
+    let height = (gray.height() as usize * width / current).max(1);
+    image::imageops::resize(gray, width as u32, height as u32, FilterType::Triangle)
+}
+
+pub fn dither(gray: &GrayImage, method: Dither, threshold: u8) -> Bitmap {
+    let (width, height) = (gray.width() as usize, gray.height() as usize);
+    let mut bitmap = Bitmap::new(width, height);
+    let threshold = threshold as f32;
+
+    match method {
+        Dither::Threshold => {
+            for (x, y, pixel) in gray.enumerate_pixels() {
+                if (pixel.0[0] as f32) < threshold {
+                    bitmap.set(x as usize, y as usize);
+                }
+            }
+        }
+        Dither::FloydSteinberg => {
+            let mut levels: Vec<f32> = gray.pixels().map(|p| p.0[0] as f32).collect();
+            for y in 0..height {
+                for x in 0..width {
+                    let old = levels[y * width + x];
+                    let new = if old < threshold { 0.0 } else { 255.0 };
+                    if new == 0.0 {
+                        bitmap.set(x, y);
+                    }
+                    let error = old - new;
+                    let mut spread = |dx: isize, dy: usize, weight: f32| {
+                        let nx = x as isize + dx;
+                        let ny = y + dy;
+                        if nx >= 0 && (nx as usize) < width && ny < height {
+                            levels[ny * width + nx as usize] += error * weight;
+                        }
+                    };
+                    spread(1, 0, 7.0 / 16.0);
+                    spread(-1, 1, 3.0 / 16.0);
+                    spread(0, 1, 5.0 / 16.0);
+                    spread(1, 1, 1.0 / 16.0);
+                }
+            }
+        }
+    }
+    bitmap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn black_pixels(bitmap: &Bitmap) -> u32 {
+        bitmap.data.iter().map(|b| b.count_ones()).sum()
+    }
+
+    #[test]
+    fn threshold_splits_gradient() {
+        let gray = GrayImage::from_fn(8, 1, |x, _| image::Luma([(x * 32) as u8]));
+        let bitmap = dither(&gray, Dither::Threshold, 128);
+        assert_eq!(bitmap.data, [0xf0]);
+    }
+
+    #[test]
+    fn floyd_steinberg_mid_gray_is_half_black() {
+        let gray = GrayImage::from_pixel(64, 64, image::Luma([128]));
+        let bitmap = dither(&gray, Dither::FloydSteinberg, 128);
+        let black = black_pixels(&bitmap);
+        assert!((1900..=2200).contains(&black), "black = {}", black);
+    }
+
+    #[test]
+    fn loads_png_scaled_to_paper_width_and_caches_it() {
+        let dir = std::env::temp_dir().join(format!("chefcloud-logo-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("logo.png");
+        // Transparent background with a black bar across the middle.
+        image::RgbaImage::from_fn(800, 200, |_, y| {
+            if (80..120).contains(&y) {
+                image::Rgba([0, 0, 0, 255])
+            } else {
+                image::Rgba([0, 0, 0, 0])
+            }
+        })
+        .save(&path)
+        .unwrap();
+
+        let settings = LogoSettings {
+            path: path.to_string_lossy().into_owned(),
+            width: None,
+            dither: Dither::Threshold,
+            threshold: 128,
+            mode: LogoMode::Raster,
+            nv_key: default_nv_key(),
+        };
+        let bitmap = settings.bitmap(576).unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (576, 144));
+        assert!(!bitmap.get(10, 5));
+        assert!(bitmap.get(10, 72));
+
+        let again = settings.bitmap(576).unwrap();
+        assert!(Arc::ptr_eq(&bitmap, &again));
+    }
+}
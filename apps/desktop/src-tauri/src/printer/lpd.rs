@@ -0,0 +1,245 @@
+//! LPD (RFC 1179) client for printers behind print servers that only take
+//! jobs on port 515. Each job is one "receive job" command carrying a data
+//! file with the raw printer bytes and a control file that prints it
+//! unfiltered.
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use super::transport::PrinterTransport;
+
+/// Job numbers are three digits and only need to be unique per host for as
+/// long as the server keeps the files.
+static NEXT_JOB: AtomicU32 = AtomicU32::new(0);
+
+/// Control file `P` and `J` lines.
+const USER: &str = "chefcloud";
+
+/// An open connection to an LPD queue. Writes are collected until `flush`,
+/// which sends them as one job, since LPD needs each file's length up
+/// front. There is no status channel, so reads return end of file.
+pub struct LpdTransport {
+    stream: TcpStream,
+    queue: String,
+    data: Vec<u8>,
+    sent: bool,
+}
+
+impl LpdTransport {
+    pub fn new(stream: TcpStream, queue: &str) -> Self {
+        LpdTransport {
+            stream,
+            queue: queue.to_string(),
+            data: Vec::new(),
+            sent: false,
+        }
+    }
+
+    fn send_job(&mut self) -> io::Result<()> {
+        let host = host_name();
+        let job = (std::process::id() + NEXT_JOB.fetch_add(1, Ordering::Relaxed)) % 1000;
+        let data_file = format!("dfA{:03}{}", job, host);
+        let control_file = format!("cfA{:03}{}", job, host);
+        let control = control_file_for(&host, &data_file);
+
+        // 02 queue LF - Receive a printer job
+        self.command(&format!("\x02{}\n", self.queue))?;
+        // 03 count SP name LF - Receive data file, then the file and a NUL
+        self.command(&format!("\x03{} {}\n", self.data.len(), data_file))?;
+        let data = std::mem::take(&mut self.data);
+        self.stream.write_all(&data)?;
+        self.command("\0")?;
+        // 02 count SP name LF - Receive control file
+        self.command(&format!("\x02{} {}\n", control.len(), control_file))?;
+        self.stream.write_all(control.as_bytes())?;
+        self.command("\0")
+    }
+
+    /// Sends `command` and waits for the server's one-byte acknowledgement.
+    fn command(&mut self, command: &str) -> io::Result<()> {
+        self.stream.write_all(command.as_bytes())?;
+        self.stream.flush()?;
+        let mut ack = [0u8; 1];
+        match self.stream.read(&mut ack)? {
+            0 => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "LPD server closed the connection",
+            )),
+            _ if ack[0] == 0 => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!(
+                    "LPD queue '{}' refused the job (code {})",
+                    self.queue, ack[0]
+                ),
+            )),
+        }
+    }
+}
+
+/// Prints the data file with `l`, which passes control characters through
+/// as ESC/POS and Star commands need, then removes it.
+fn control_file_for(host: &str, data_file: &str) -> String {
+    format!(
+        "H{host}\nP{user}\nJ{user}\nl{file}\nU{file}\nN{user}\n",
+        host = host,
+        user = USER,
+        file = data_file
+    )
+}
+
+/// The terminal's host name as LPD file names carry it: at most 31
+/// characters from `[A-Za-z0-9.-]`.
+fn host_name() -> String {
+    let name: String = std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '.')
+        .take(31)
+        .collect();
+    if name.is_empty() {
+        USER.to_string()
+    } else {
+        name
+    }
+}
+
+impl Read for LpdTransport {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+impl Write for LpdTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.data.is_empty() {
+            return Ok(());
+        }
+        if self.sent {
+            return Err(io::Error::other(
+                "LPD connection already carried a job; open a new one",
+            ));
+        }
+        self.sent = true;
+        self.send_job()
+    }
+}
+
+impl PrinterTransport for LpdTransport {
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        // Bounds the wait for each acknowledgement.
+        self.stream.set_read_timeout(Some(timeout))
+    }
+
+    fn set_write_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.stream.set_write_timeout(Some(timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::config::Transport;
+    use crate::printer::error::PrintErrorKind;
+    use crate::printer::spool::CancelToken;
+    use crate::printer::transport::{self, Timeouts};
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// What the fake daemon received for one job.
+    #[derive(Debug, Default)]
+    struct Received {
+        queue: String,
+        /// (name, contents) in the order they arrived.
+        files: Vec<(String, Vec<u8>)>,
+    }
+
+    /// Accepts one connection and plays an LPD server that takes jobs for
+    /// queue `accept` and refuses any other.
+    fn fake_lpd(accept: &'static str) -> (u16, thread::JoinHandle<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let daemon = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut received = Received::default();
+            let mut line = Vec::new();
+            if reader.read_until(b'\n', &mut line).unwrap() == 0 {
+                return received;
+            }
+            received.queue = String::from_utf8_lossy(&line[1..line.len() - 1]).into_owned();
+            if line[0] != 0x02 || received.queue != accept {
+                writer.write_all(&[1]).unwrap();
+                return received;
+            }
+            writer.write_all(&[0]).unwrap();
+            loop {
+                line.clear();
+                if reader.read_until(b'\n', &mut line).unwrap() == 0 {
+                    return received;
+                }
+                let header = String::from_utf8_lossy(&line[1..line.len() - 1]).into_owned();
+                let (count, name) = header.split_once(' ').unwrap();
+                writer.write_all(&[0]).unwrap();
+                let mut contents = vec![0u8; count.parse::<usize>().unwrap() + 1];
+                reader.read_exact(&mut contents).unwrap();
+                assert_eq!(contents.pop(), Some(0));
+                received.files.push((name.to_string(), contents));
+                writer.write_all(&[0]).unwrap();
+            }
+        });
+        (port, daemon)
+    }
+
+    fn lpd(port: u16, queue: &str) -> Transport {
+        Transport::Lpd {
+            host: "127.0.0.1".to_string(),
+            port,
+            queue: queue.to_string(),
+        }
+    }
+
+    #[test]
+    fn sends_a_job_with_data_and_control_files() {
+        let (port, daemon) = fake_lpd("kitchen");
+        let mut printer = transport::open(&lpd(port, "kitchen"), &Timeouts::default()).unwrap();
+        let job = [b"\x1b@".as_slice(), &[b'x'; 5000], b"\x1dV\x00"].concat();
+        transport::write_all(&mut *printer, &job, &CancelToken::default()).unwrap();
+        drop(printer);
+
+        let received = daemon.join().unwrap();
+        assert_eq!(received.queue, "kitchen");
+        let [(data_name, data), (control_name, control)] = &received.files[..] else {
+            panic!("expected two files, got {:?}", received);
+        };
+        assert!(data_name.starts_with("dfA"));
+        assert_eq!(control_name[3..], data_name[3..]);
+        assert_eq!(data, &job);
+        let control = String::from_utf8(control.clone()).unwrap();
+        assert!(control.lines().any(|l| l == format!("l{}", data_name)));
+        assert!(control.lines().any(|l| l == format!("U{}", data_name)));
+        assert!(control.starts_with('H'));
+    }
+
+    #[test]
+    fn rejected_queues_are_refused() {
+        let (port, daemon) = fake_lpd("kitchen");
+        let mut printer = transport::open(&lpd(port, "bar"), &Timeouts::default()).unwrap();
+        let err =
+            transport::write_all(&mut *printer, b"\x1b@", &CancelToken::default()).unwrap_err();
+        assert_eq!(err.kind, PrintErrorKind::Refused);
+        assert!(err.message.contains("'bar'"), "{}", err);
+        drop(printer);
+        assert!(daemon.join().unwrap().files.is_empty());
+    }
+}
@@ -0,0 +1,30 @@
+//! Printer registry, encoding and transport for the desktop POS backend.
+
+pub mod barcode;
+pub mod bitmap;
+pub mod codepage;
+pub mod config;
+pub mod decode;
+pub mod discovery;
+pub mod document;
+pub mod drawer;
+pub mod error;
+pub mod escpos;
+pub mod failover;
+pub mod history;
+pub mod ipp;
+pub mod jsonl;
+pub mod label;
+pub mod layout;
+pub mod logo;
+pub mod lpd;
+pub mod payloads;
+pub mod preview;
+pub mod reload;
+pub mod routing;
+pub mod simulate;
+pub mod spool;
+pub mod star;
+pub mod status;
+pub mod templates;
+pub mod transport;
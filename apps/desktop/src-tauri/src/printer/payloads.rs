@@ -0,0 +1,62 @@
+//! Job bytes stored one file per job, `<dir>/<id>.bin`, beside the spool
+//! and history indexes, so a state change rewrites only the small index and
+//! not every job's bytes.
+
+use std::fs;
+use std::path::PathBuf;
+
+pub struct PayloadStore {
+    dir: PathBuf,
+}
+
+impl PayloadStore {
+    pub fn new(dir: PathBuf) -> Self {
+        PayloadStore { dir }
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.bin", id))
+    }
+
+    /// Writes the bytes for job `id` through a temporary file, so a crash
+    /// never leaves a truncated payload behind.
+    pub fn write(&self, id: &str, bytes: &[u8]) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        let path = self.path(id);
+        let tmp = path.with_extension("bin.tmp");
+        fs::write(&tmp, bytes).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &path).map_err(|e| {
+            let _ = fs::remove_file(&tmp);
+            format!("Failed to replace {}: {}", path.display(), e)
+        })
+    }
+
+    pub fn read(&self, id: &str) -> Result<Vec<u8>, String> {
+        let path = self.path(id);
+        fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+    }
+
+    pub fn remove(&self, id: &str) {
+        let _ = fs::remove_file(self.path(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_reads_and_removes_payloads() {
+        let dir = std::env::temp_dir().join(format!("chefcloud-payloads-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let store = PayloadStore::new(dir.clone());
+
+        store.write("18c2-0001", b"\x1b@receipt").unwrap();
+        assert_eq!(store.read("18c2-0001").unwrap(), b"\x1b@receipt");
+        assert!(!dir.join("18c2-0001.bin.tmp").exists());
+
+        store.remove("18c2-0001");
+        assert!(store.read("18c2-0001").is_err());
+    }
+}
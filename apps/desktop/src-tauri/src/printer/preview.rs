@@ -0,0 +1,664 @@
+//! PNG previews of receipts for the UI and email receipts. The ESC/POS
+//! stream is played on a virtual printer as wide as the real one, drawing
+//! text with an embedded bitmap font the size of ESC/POS font A.
+
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageEncoder};
+use serde::{Deserialize, Serialize};
+
+use super::barcode;
+use super::bitmap::Bitmap;
+use super::config::{PrinterDefinition, PrinterLanguage};
+use super::decode::{self, Command};
+use super::document::Document;
+use super::escpos::{Align, QrErrorCorrection, Symbology};
+use super::templates::{self, ReceiptData};
+
+/// DejaVu Sans Mono cut to 12x24 dots by `fonts/build_receipt_font.py`.
+/// Each record is a little-endian code point and 24 big-endian rows with
+/// the leftmost dot in the top bit, sorted by code point.
+const FONT: &[u8] = include_bytes!("../../fonts/receipt-12x24.bin");
+const GLYPH_WIDTH: usize = 12;
+const GLYPH_HEIGHT: usize = 24;
+const GLYPH_RECORD: usize = 4 + 2 * GLYPH_HEIGHT;
+/// Default line spacing (`ESC 2`) in dots.
+const LINE_SPACING: usize = 30;
+/// Characters between the default horizontal tab stops.
+const TAB_STOP: usize = 8;
+/// Gap between a barcode and its human-readable text.
+const HRI_GAP: usize = 4;
+/// Blank paper below the last printed line.
+const BOTTOM_MARGIN: usize = 24;
+/// About 3.7 m of paper at 203 dpi; longer streams are almost certainly a
+/// runaway feed.
+const MAX_HEIGHT: usize = 30_000;
+
+const WHITE: u8 = 255;
+const BLACK: u8 = 0;
+/// Cuts are marked in grey so they do not read as printed dots.
+const CUT_MARK: u8 = 160;
+
+/// What to preview: a document, a receipt laid out by the receipt template,
+/// or raw ESC/POS bytes.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum PreviewSource {
+    Document {
+        document: Document,
+    },
+    Receipt {
+        data: Box<ReceiptData>,
+    },
+    EscPos {
+        #[serde(with = "super::spool::base64_bytes")]
+        data: Vec<u8>,
+    },
+}
+
+/// A rendered preview, one pixel per printer dot.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptImage {
+    pub width: usize,
+    pub height: usize,
+    #[serde(with = "super::spool::base64_bytes")]
+    pub png: Vec<u8>,
+}
+
+/// Renders `source` as it would print on `printer`. Documents for Star
+/// printers lay out the same as for ESC/POS ones, so they are previewed
+/// through the ESC/POS renderer.
+pub fn render(source: &PreviewSource, printer: &PrinterDefinition) -> Result<ReceiptImage, String> {
+    if printer.language.is_label() {
+        return Err(format!(
+            "Printer '{}' is a label printer and has no receipt preview",
+            printer.id
+        ));
+    }
+    let escpos = PrinterDefinition {
+        language: PrinterLanguage::EscPos,
+        ..printer.clone()
+    };
+    let bytes = match source {
+        PreviewSource::Document { document } => document.render(&escpos)?,
+        PreviewSource::Receipt { data } => {
+            templates::receipt(data, printer.columns()).render(&escpos)?
+        }
+        PreviewSource::EscPos { data } => data.clone(),
+    };
+    rasterize(&bytes, printer)
+}
+
+/// Draws an ESC/POS stream at `printer`'s dot width and encodes it as an
+/// 8-bit greyscale PNG.
+pub fn rasterize(bytes: &[u8], printer: &PrinterDefinition) -> Result<ReceiptImage, String> {
+    let mut paper = Paper::new(printer);
+    for decoded in decode::decode(bytes) {
+        paper.apply(&decoded.command, &bytes[decoded.offset..])?;
+    }
+    let (width, height, pixels) = paper.finish()?;
+
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(&pixels, width as u32, height as u32, ColorType::L8)
+        .map_err(|e| format!("Failed to encode receipt preview: {}", e))?;
+    Ok(ReceiptImage { width, height, png })
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Style {
+    bold: bool,
+    /// Underline thickness in dots, 0-2.
+    underline: usize,
+    invert: bool,
+    width: usize,
+    height: usize,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            bold: false,
+            underline: 0,
+            invert: false,
+            width: 1,
+            height: 1,
+        }
+    }
+}
+
+/// `GS h`, `GS w` and `GS H` settings, with the printer defaults.
+#[derive(Clone, Copy, Debug)]
+struct BarcodeSettings {
+    height: usize,
+    module: usize,
+    hri: u8,
+}
+
+impl Default for BarcodeSettings {
+    fn default() -> Self {
+        BarcodeSettings {
+            height: 162,
+            module: 3,
+            hri: 0,
+        }
+    }
+}
+
+/// The QR symbol being set up with `GS ( k`.
+#[derive(Clone, Debug)]
+struct QrSettings {
+    size: u8,
+    error_correction: QrErrorCorrection,
+    data: String,
+}
+
+impl Default for QrSettings {
+    fn default() -> Self {
+        QrSettings {
+            size: 3,
+            error_correction: QrErrorCorrection::L,
+            data: String::new(),
+        }
+    }
+}
+
+/// The virtual printer: paper printed so far and the print buffer.
+struct Paper<'a> {
+    printer: &'a PrinterDefinition,
+    width: usize,
+    pixels: Vec<u8>,
+    /// Top of the next line, in dots.
+    y: usize,
+    line: Vec<(char, Style)>,
+    line_width: usize,
+    /// Alignment in effect when the buffered line started.
+    line_align: Align,
+    align: Align,
+    style: Style,
+    barcode: BarcodeSettings,
+    qr: QrSettings,
+    cuts: Vec<usize>,
+}
+
+impl<'a> Paper<'a> {
+    fn new(printer: &'a PrinterDefinition) -> Self {
+        Paper {
+            printer,
+            width: printer.dots(),
+            pixels: Vec::new(),
+            y: 0,
+            line: Vec::new(),
+            line_width: 0,
+            line_align: Align::Left,
+            align: Align::Left,
+            style: Style::default(),
+            barcode: BarcodeSettings::default(),
+            qr: QrSettings::default(),
+            cuts: Vec::new(),
+        }
+    }
+
+    /// Applies one decoded command; `raw` is the stream from its first byte,
+    /// for the payloads the decoder does not keep.
+    fn apply(&mut self, command: &Command, raw: &[u8]) -> Result<(), String> {
+        match command {
+            Command::Text { text } => self.text(text)?,
+            Command::LineFeed => self.print_line()?,
+            Command::Init => {
+                self.line.clear();
+                self.line_width = 0;
+                self.align = Align::Left;
+                self.style = Style::default();
+                self.barcode = BarcodeSettings::default();
+                self.qr = QrSettings::default();
+            }
+            Command::Bold { on } => self.style.bold = *on,
+            Command::Underline { mode } => self.style.underline = (*mode).min(2) as usize,
+            Command::Invert { on } => self.style.invert = *on,
+            Command::Align { align } => self.align = *align,
+            Command::PrintMode { mode } => {
+                self.style.bold = mode & 0x08 != 0;
+                self.style.height = if mode & 0x10 != 0 { 2 } else { 1 };
+                self.style.width = if mode & 0x20 != 0 { 2 } else { 1 };
+                self.style.underline = if mode & 0x80 != 0 { 1 } else { 0 };
+            }
+            Command::Size { width, height } => {
+                self.style.width = *width as usize;
+                self.style.height = *height as usize;
+            }
+            Command::Feed { lines } => {
+                // ESC d prints the buffer and feeds n lines in all, so a
+                // buffered line counts as the first.
+                let mut lines = *lines as usize;
+                if !self.line.is_empty() {
+                    self.print_line()?;
+                    lines = lines.saturating_sub(1);
+                }
+                for _ in 0..lines {
+                    self.print_line()?;
+                }
+            }
+            Command::Cut { .. } => {
+                self.flush_line()?;
+                self.cuts.push(self.y);
+            }
+            Command::BarcodeHeight { dots } => self.barcode.height = (*dots).max(1) as usize,
+            Command::BarcodeWidth { module } => self.barcode.module = (*module).max(1) as usize,
+            Command::BarcodeHri { position } => self.barcode.hri = *position,
+            Command::Barcode { system, data } => {
+                let symbology = match system {
+                    73 => Symbology::Code128,
+                    2 | 67 => Symbology::Ean13,
+                    4 | 69 => Symbology::Code39,
+                    _ => return Ok(()),
+                };
+                self.flush_line()?;
+                // Data the printer cannot encode prints nothing.
+                if let Ok(bars) = barcode::encode(symbology, data) {
+                    self.barcode(&bars)?;
+                }
+            }
+            // GS ( k pL pH cn fn n: the parameter follows the function.
+            Command::Qr { function: 67, .. } => {
+                self.qr.size = raw.get(7).copied().unwrap_or(3).clamp(1, 16);
+            }
+            Command::Qr { function: 69, .. } => {
+                self.qr.error_correction = match raw.get(7) {
+                    Some(49) => QrErrorCorrection::M,
+                    Some(50) => QrErrorCorrection::Q,
+                    Some(51) => QrErrorCorrection::H,
+                    _ => QrErrorCorrection::L,
+                };
+            }
+            Command::Qr {
+                function: 80,
+                data: Some(data),
+            } => self.qr.data = data.clone(),
+            Command::Qr { function: 81, .. } => {
+                self.flush_line()?;
+                let qr = &self.qr;
+                if let Ok(bitmap) = Bitmap::qr(&qr.data, qr.size, qr.error_correction) {
+                    // The printer leaves out the software renderer's quiet zone.
+                    let quiet = 4 * qr.size as usize;
+                    self.image(&bitmap, quiet)?;
+                }
+            }
+            Command::Raster { width, height } => {
+                // GS v 0 m xL xH yL yH, then the rows.
+                let data = raw[8..8 + width / 8 * height].to_vec();
+                let bitmap = Bitmap {
+                    width: *width,
+                    height: *height,
+                    data,
+                };
+                self.flush_line()?;
+                self.image(&bitmap, 0)?;
+            }
+            Command::NvGraphic { function: 69 } => {
+                // The stored graphic is the configured logo; without one
+                // there is nothing to show.
+                self.flush_line()?;
+                if let Some(Ok(bitmap)) = self.printer.logo.as_ref().map(|l| l.bitmap(self.width)) {
+                    self.image(&bitmap, 0)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Makes the paper at least `height` dots long.
+    fn extend_to(&mut self, height: usize) -> Result<(), String> {
+        if height > MAX_HEIGHT {
+            return Err(format!(
+                "Receipt is longer than {} dots and cannot be previewed",
+                MAX_HEIGHT
+            ));
+        }
+        if self.pixels.len() < height * self.width {
+            self.pixels.resize(height * self.width, WHITE);
+        }
+        Ok(())
+    }
+
+    fn dot(&mut self, x: usize, y: usize) {
+        if x < self.width {
+            self.pixels[y * self.width + x] = BLACK;
+        }
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), String> {
+        for c in text.chars() {
+            if c == '\t' {
+                let column = self.line_width / GLYPH_WIDTH;
+                let stop = (column / TAB_STOP + 1) * TAB_STOP;
+                for _ in column..stop {
+                    self.push(' ')?;
+                }
+            } else {
+                self.push(c)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds `c` to the buffer, printing the line first when `c` would not
+    /// fit on it.
+    fn push(&mut self, c: char) -> Result<(), String> {
+        let width = GLYPH_WIDTH * self.style.width;
+        if !self.line.is_empty() && self.line_width + width > self.width {
+            self.print_line()?;
+        }
+        if self.line.is_empty() {
+            self.line_align = self.align;
+        }
+        self.line.push((c, self.style));
+        self.line_width += width;
+        Ok(())
+    }
+
+    /// Prints the buffered line, if any, without feeding an empty one.
+    fn flush_line(&mut self) -> Result<(), String> {
+        if self.line.is_empty() {
+            Ok(())
+        } else {
+            self.print_line()
+        }
+    }
+
+    /// Prints the buffer and moves to the next line, as LF does.
+    fn print_line(&mut self) -> Result<(), String> {
+        let line = std::mem::take(&mut self.line);
+        let line_width = std::mem::take(&mut self.line_width);
+        let height = line
+            .iter()
+            .map(|(_, style)| GLYPH_HEIGHT * style.height)
+            .max()
+            .unwrap_or(GLYPH_HEIGHT);
+        self.extend_to(self.y + height + LINE_SPACING - GLYPH_HEIGHT)?;
+
+        let mut x = self.aligned(self.line_align, line_width);
+        for (c, style) in line {
+            let top = self.y + height - GLYPH_HEIGHT * style.height;
+            self.glyph(c, style, x, top);
+            x += GLYPH_WIDTH * style.width;
+        }
+        self.y += height + LINE_SPACING - GLYPH_HEIGHT;
+        Ok(())
+    }
+
+    fn aligned(&self, align: Align, width: usize) -> usize {
+        let space = self.width.saturating_sub(width);
+        match align {
+            Align::Left => 0,
+            Align::Center => space / 2,
+            Align::Right => space,
+        }
+    }
+
+    fn glyph(&mut self, c: char, style: Style, x: usize, top: usize) {
+        let rows = glyph_rows(c);
+        for gy in 0..GLYPH_HEIGHT {
+            let mut row = u16::from_be_bytes([rows[2 * gy], rows[2 * gy + 1]]);
+            if style.bold {
+                // Double-strike one dot to the right, as the printers do.
+                row |= row >> 1;
+            }
+            let underlined = !style.invert && gy >= GLYPH_HEIGHT - style.underline;
+            for gx in 0..GLYPH_WIDTH {
+                let on = row & (0x8000 >> gx) != 0 || underlined;
+                if on != style.invert {
+                    for sy in 0..style.height {
+                        for sx in 0..style.width {
+                            self.dot(x + gx * style.width + sx, top + gy * style.height + sy);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Prints `bitmap` at the current alignment, leaving out `inset` dots
+    /// of margin on each side.
+    fn image(&mut self, bitmap: &Bitmap, inset: usize) -> Result<(), String> {
+        let width = bitmap.width.saturating_sub(2 * inset);
+        let height = bitmap.height.saturating_sub(2 * inset);
+        self.extend_to(self.y + height)?;
+        let left = self.aligned(self.align, width);
+        for y in 0..height {
+            for x in 0..width {
+                if bitmap.get(x + inset, y + inset) {
+                    self.dot(left + x, self.y + y);
+                }
+            }
+        }
+        self.y += height;
+        Ok(())
+    }
+
+    fn barcode(&mut self, bars: &barcode::Bars) -> Result<(), String> {
+        let module = self.barcode.module;
+        let width = bars.modules.len() * module;
+        if width > self.width {
+            // Too wide for the paper: the printer skips it.
+            return Ok(());
+        }
+        let left = self.aligned(self.align, width);
+        if self.barcode.hri & 1 != 0 {
+            self.hri(&bars.text, left, width)?;
+        }
+        self.extend_to(self.y + self.barcode.height)?;
+        for (i, _) in bars.modules.iter().enumerate().filter(|(_, bar)| **bar) {
+            for x in i * module..(i + 1) * module {
+                for y in self.y..self.y + self.barcode.height {
+                    self.dot(left + x, y);
+                }
+            }
+        }
+        self.y += self.barcode.height;
+        if self.barcode.hri & 2 != 0 {
+            self.hri(&bars.text, left, width)?;
+        }
+        Ok(())
+    }
+
+    /// Human-readable text centred on a barcode at `left`, `width` dots wide.
+    fn hri(&mut self, text: &str, left: usize, width: usize) -> Result<(), String> {
+        self.extend_to(self.y + GLYPH_HEIGHT + 2 * HRI_GAP)?;
+        let text_width = text.chars().count() * GLYPH_WIDTH;
+        let mut x = left + width.saturating_sub(text_width) / 2;
+        for c in text.chars() {
+            self.glyph(c, Style::default(), x, self.y + HRI_GAP);
+            x += GLYPH_WIDTH;
+        }
+        self.y += GLYPH_HEIGHT + 2 * HRI_GAP;
+        Ok(())
+    }
+
+    /// Prints what is left in the buffer and marks the cuts between
+    /// receipts. Returns the width, height and pixels.
+    fn finish(mut self) -> Result<(usize, usize, Vec<u8>), String> {
+        self.flush_line()?;
+        let height = self.y + BOTTOM_MARGIN;
+        self.extend_to(height)?;
+        for &cut in self.cuts.iter().filter(|&&cut| cut < self.y) {
+            let row = &mut self.pixels[cut * self.width..(cut + 1) * self.width];
+            for (x, pixel) in row.iter_mut().enumerate() {
+                if x / 8 % 2 == 0 {
+                    *pixel = CUT_MARK;
+                }
+            }
+        }
+        Ok((self.width, height, self.pixels))
+    }
+}
+
+/// The font rows for `c`, or for `?` when the font has no glyph for it.
+fn glyph_rows(c: char) -> &'static [u8] {
+    let code_point = |record: usize| {
+        let at = record * GLYPH_RECORD;
+        u32::from_le_bytes([FONT[at], FONT[at + 1], FONT[at + 2], FONT[at + 3]])
+    };
+    let (mut low, mut high) = (0, FONT.len() / GLYPH_RECORD);
+    while low < high {
+        let mid = (low + high) / 2;
+        match code_point(mid).cmp(&(c as u32)) {
+            std::cmp::Ordering::Less => low = mid + 1,
+            std::cmp::Ordering::Greater => high = mid,
+            std::cmp::Ordering::Equal => {
+                let at = mid * GLYPH_RECORD + 4;
+                return &FONT[at..at + 2 * GLYPH_HEIGHT];
+            }
+        }
+    }
+    glyph_rows('?')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::escpos::EscPosBuilder;
+    use serde_json::json;
+
+    fn printer(paper_width: u16) -> PrinterDefinition {
+        PrinterDefinition {
+            paper_width,
+            ..PrinterDefinition::tcp("test", "127.0.0.1", 9100)
+        }
+    }
+
+    /// Decodes the PNG and returns the bounding box of its black dots as
+    /// (left, top, right, bottom), exclusive.
+    fn ink(image: &ReceiptImage) -> (usize, usize, usize, usize) {
+        let decoded = image::load_from_memory(&image.png).unwrap().to_luma8();
+        assert_eq!(
+            (decoded.width() as usize, decoded.height() as usize),
+            (image.width, image.height)
+        );
+        let mut bounds = (usize::MAX, usize::MAX, 0, 0);
+        for (x, y, pixel) in decoded.enumerate_pixels() {
+            if pixel.0[0] == BLACK {
+                let (x, y) = (x as usize, y as usize);
+                bounds = (
+                    bounds.0.min(x),
+                    bounds.1.min(y),
+                    bounds.2.max(x + 1),
+                    bounds.3.max(y + 1),
+                );
+            }
+        }
+        bounds
+    }
+
+    #[test]
+    fn every_code_page_character_has_a_glyph() {
+        let question = glyph_rows('?');
+        assert!(question.iter().any(|&b| b != 0));
+        assert!(glyph_rows(' ').iter().all(|&b| b == 0));
+        for c in "Crème brûlée €5 Борщ ░█".chars().filter(|&c| c != '?') {
+            assert_ne!(glyph_rows(c), question, "{:?}", c);
+        }
+        assert_eq!(glyph_rows('🍜'), question);
+    }
+
+    #[test]
+    fn text_is_laid_out_at_the_printer_dot_width() {
+        let mut builder = EscPosBuilder::new();
+        builder.init().line("Hello");
+        let image = rasterize(&builder.build(), &printer(58)).unwrap();
+        assert_eq!(image.width, 384);
+        assert_eq!(image.height, LINE_SPACING + BOTTOM_MARGIN);
+        let (left, top, right, bottom) = ink(&image);
+        assert!(
+            left < GLYPH_WIDTH && right <= 5 * GLYPH_WIDTH,
+            "{}..{}",
+            left,
+            right
+        );
+        assert!(top > 0 && bottom <= GLYPH_HEIGHT);
+
+        let mut builder = EscPosBuilder::new();
+        builder
+            .init()
+            .align(Align::Center)
+            .size(2, 2)
+            .line("AB")
+            .size(1, 1)
+            .line(&"x".repeat(60));
+        let image = rasterize(&builder.build(), &printer(80)).unwrap();
+        assert_eq!(image.width, 576);
+        let (left, _, right, _) = ink(&image);
+        // 60 characters wrap after the 48 that fit on 80 mm paper.
+        assert_eq!(
+            image.height,
+            2 * GLYPH_HEIGHT + 3 * LINE_SPACING - GLYPH_HEIGHT + BOTTOM_MARGIN
+        );
+        assert!(left < 576 / 2 - 2 * GLYPH_WIDTH);
+        assert!(right > 576 - GLYPH_WIDTH);
+    }
+
+    #[test]
+    fn documents_draw_barcodes_qr_codes_and_cuts() {
+        let document: Document = serde_json::from_value(json!({
+            "blocks": [
+                { "type": "barcode", "data": "A-1042", "width": 2, "height": 80 },
+                { "type": "cut" },
+                { "type": "qr", "data": "https://chefcloud.app", "size": 4 },
+                { "type": "cut" }
+            ]
+        }))
+        .unwrap();
+        let source = PreviewSource::Document { document };
+        let image = render(&source, &printer(80)).unwrap();
+        let decoded = image::load_from_memory(&image.png).unwrap().to_luma8();
+
+        let bars = barcode::encode(Symbology::Code128, "{BA-1042").unwrap();
+        let bar_rows: Vec<u32> = (0..image.height as u32)
+            .filter(|&y| {
+                (0..576)
+                    .filter(|&x| decoded.get_pixel(x, y).0[0] == BLACK)
+                    .count()
+                    > 40
+            })
+            .collect();
+        assert!(bar_rows.len() >= 80);
+        let first_row: Vec<bool> = (0..576)
+            .map(|x| decoded.get_pixel(x, bar_rows[0]).0[0] == BLACK)
+            .collect();
+        let left = first_row.iter().position(|&b| b).unwrap();
+        assert_eq!(left, (576 - bars.modules.len() * 2) / 2);
+        assert!(bars
+            .modules
+            .iter()
+            .enumerate()
+            .all(|(i, &bar)| first_row[left + 2 * i] == bar));
+
+        // Only the cut between the two receipts is marked.
+        let marks = (0..image.height as u32)
+            .filter(|&y| decoded.get_pixel(0, y).0[0] == CUT_MARK)
+            .count();
+        assert_eq!(marks, 1);
+    }
+
+    #[test]
+    fn receipts_preview_for_star_but_not_label_printers() {
+        let source: PreviewSource = serde_json::from_value(json!({
+            "type": "escPos",
+            "data": "G0BTdGFyCg=="
+        }))
+        .unwrap();
+        let star = PrinterDefinition {
+            language: PrinterLanguage::Star,
+            ..printer(58)
+        };
+        assert_eq!(render(&source, &star).unwrap().width, 384);
+        let label = PrinterDefinition {
+            language: PrinterLanguage::Zpl,
+            ..printer(58)
+        };
+        assert!(render(&source, &label)
+            .unwrap_err()
+            .contains("label printer"));
+    }
+}
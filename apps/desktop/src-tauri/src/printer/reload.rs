@@ -0,0 +1,136 @@
+//! The active printer config, loaded once at startup and swapped in place
+//! when `~/.chefcloud/printer.json` changes on disk.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use super::config::{load_config, PrinterConfig};
+
+/// How often the config file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Modification time and size of the config file; `None` when it is missing.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: Option<&PathBuf>) -> Stamp {
+    let metadata = fs::metadata(path?).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+pub struct ConfigStore {
+    path: Option<PathBuf>,
+    current: RwLock<Arc<PrinterConfig>>,
+    seen: Mutex<Stamp>,
+}
+
+impl ConfigStore {
+    /// Loads the config at `path`. An invalid file is reported on stderr and
+    /// the defaults are used until it is fixed.
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let seen = stamp(path.as_ref());
+        let config = load_config(path.as_deref()).unwrap_or_else(|e| {
+            eprintln!("Using default printer config: {}", e);
+            load_config(None).unwrap_or_default()
+        });
+        ConfigStore {
+            path,
+            current: RwLock::new(Arc::new(config)),
+            seen: Mutex::new(seen),
+        }
+    }
+
+    /// A snapshot of the active config. It stays consistent for the caller
+    /// even if a reload swaps in a new one meanwhile.
+    pub fn get(&self) -> Arc<PrinterConfig> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Re-reads the file if it changed since it was last seen. Returns the
+    /// new config when it was swapped in, `None` when nothing changed, and an
+    /// error, keeping the previous config, when the new file is invalid.
+    pub fn reload_if_changed(&self) -> Result<Option<Arc<PrinterConfig>>, String> {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let now = stamp(self.path.as_ref());
+        if now == *seen {
+            return Ok(None);
+        }
+        *seen = now;
+
+        let config = Arc::new(load_config(self.path.as_deref())?);
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        if **current == *config {
+            return Ok(None);
+        }
+        *current = config.clone();
+        Ok(Some(config))
+    }
+}
+
+/// Polls the config file every second and reports each reload, or each
+/// rejected change, to `on_reload`.
+pub fn spawn_watcher<F>(store: Arc<ConfigStore>, on_reload: F)
+where
+    F: Fn(Result<Arc<PrinterConfig>, String>) + Send + 'static,
+{
+    thread::spawn(move || loop {
+        thread::sleep(WATCH_INTERVAL);
+        match store.reload_if_changed() {
+            Ok(Some(config)) => on_reload(Ok(config)),
+            Ok(None) => {}
+            Err(e) => on_reload(Err(e)),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::config::Transport;
+
+    #[test]
+    fn swaps_valid_changes_and_keeps_last_good_config() {
+        let dir = std::env::temp_dir().join(format!("chefcloud-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("printer.json");
+        let _ = fs::remove_file(&path);
+
+        let store = ConfigStore::open(Some(path.clone()));
+        assert_eq!(*store.get(), PrinterConfig::default());
+        assert_eq!(store.reload_if_changed(), Ok(None));
+
+        fs::write(
+            &path,
+            r#"{ "simulate": false, "printers": [
+                { "id": "bar", "transport": { "type": "tcp", "host": "10.0.0.30" } } ] }"#,
+        )
+        .unwrap();
+        let reloaded = store.reload_if_changed().unwrap().unwrap();
+        assert_eq!(reloaded.printers[0].id, "bar");
+        assert!(Arc::ptr_eq(&reloaded, &store.get()));
+
+        // Longer than the valid file, so the change is seen even if the
+        // modification time does not move.
+        fs::write(
+            &path,
+            r#"{ "simulate": false, "printers": [
+                { "id": "bar", "transport": { "type": "tcp", "host": "not a host!" } } ] }"#,
+        )
+        .unwrap();
+        let err = store.reload_if_changed().unwrap_err();
+        assert!(err.contains("printers[0].transport.host"), "{}", err);
+        assert_eq!(
+            store.get().printers[0].transport,
+            Transport::Tcp {
+                host: "10.0.0.30".to_string(),
+                port: 9100
+            }
+        );
+        assert_eq!(store.reload_if_changed(), Ok(None));
+    }
+}
@@ -0,0 +1,294 @@
+//! Kitchen station routing: which station each menu item goes to, and which
+//! printer each station prints on. The table comes from the server and is
+//! cached at `{appDataDir}/station-routes.json` so that orders taken while
+//! offline still reach the right stations.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+
+use super::config::PrinterConfig;
+use super::spool::now_millis;
+use super::templates::{KitchenTicketData, KitchenTicketItem};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutingTable {
+    /// Menu item id to station. Takes precedence over the item's category.
+    #[serde(default)]
+    pub items: BTreeMap<String, String>,
+    /// Menu category id to station.
+    #[serde(default)]
+    pub categories: BTreeMap<String, String>,
+    /// Station to printer or printer group id. Stations without an entry
+    /// print on the first `kitchen` printer.
+    #[serde(default)]
+    pub stations: BTreeMap<String, String>,
+    /// Station for items that neither map matches.
+    #[serde(default = "default_station")]
+    pub default_station: String,
+    /// When the table was last received from the server (unix ms).
+    #[serde(default)]
+    pub synced_at: u64,
+}
+
+fn default_station() -> String {
+    "KITCHEN".to_string()
+}
+
+impl Default for RoutingTable {
+    fn default() -> Self {
+        RoutingTable {
+            items: BTreeMap::new(),
+            categories: BTreeMap::new(),
+            stations: BTreeMap::new(),
+            default_station: default_station(),
+            synced_at: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderItem {
+    #[serde(default)]
+    pub item_id: Option<String>,
+    #[serde(default)]
+    pub category_id: Option<String>,
+    pub name: String,
+    pub quantity: u32,
+    #[serde(default)]
+    pub modifiers: Vec<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+/// An order to be split into kitchen tickets.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct KitchenOrder {
+    pub order_number: String,
+    #[serde(default)]
+    pub table_number: Option<String>,
+    pub items: Vec<OrderItem>,
+    pub timestamp: String,
+}
+
+/// One station's share of an order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StationTicket {
+    /// `None` when the station has no printer mapped, or one that is not
+    /// configured.
+    pub printer_id: Option<String>,
+    /// The printer the station is mapped to when `config` does not have it.
+    pub missing_printer: Option<String>,
+    pub ticket: KitchenTicketData,
+}
+
+/// The outcome of printing one station's ticket.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StationPrint {
+    pub station: String,
+    pub printer_id: Option<String>,
+    /// Set when the station's printer is not configured and the ticket fell
+    /// back to the default kitchen printer.
+    pub missing_printer: Option<String>,
+    pub message: Option<String>,
+    pub error: Option<String>,
+}
+
+impl RoutingTable {
+    pub fn station_for(&self, item: &OrderItem) -> &str {
+        let by_item = item.item_id.as_ref().and_then(|id| self.items.get(id));
+        let by_category = item
+            .category_id
+            .as_ref()
+            .and_then(|id| self.categories.get(id));
+        by_item.or(by_category).unwrap_or(&self.default_station)
+    }
+
+    /// Splits `order` into one ticket per station, in the order each station
+    /// first appears among the items. A station mapped to a printer that
+    /// `config` does not have prints on the first `kitchen` printer, like an
+    /// unmapped one, so its ticket is still queued; `missing_printer` says
+    /// which printer it was meant for.
+    pub fn split(&self, order: &KitchenOrder, config: &PrinterConfig) -> Vec<StationTicket> {
+        let mut tickets: Vec<StationTicket> = Vec::new();
+        for item in &order.items {
+            let station = self.station_for(item);
+            let index = match tickets.iter().position(|t| t.ticket.station == station) {
+                Some(index) => index,
+                None => {
+                    let mapped = self.stations.get(station).cloned();
+                    let (printer_id, missing_printer) = match mapped {
+                        Some(id) if config.printer(Some(id.as_str())).is_err() => (None, Some(id)),
+                        mapped => (mapped, None),
+                    };
+                    tickets.push(StationTicket {
+                        printer_id,
+                        missing_printer,
+                        ticket: KitchenTicketData {
+                            order_number: order.order_number.clone(),
+                            table_number: order.table_number.clone(),
+                            station: station.to_string(),
+                            items: Vec::new(),
+                            timestamp: order.timestamp.clone(),
+                        },
+                    });
+                    tickets.len() - 1
+                }
+            };
+            tickets[index].ticket.items.push(KitchenTicketItem {
+                name: item.name.clone(),
+                quantity: item.quantity,
+                modifiers: item.modifiers.clone(),
+                notes: item.notes.clone(),
+            });
+        }
+        tickets
+    }
+}
+
+/// The cached routing table.
+pub struct RoutingStore {
+    path: PathBuf,
+    table: RwLock<RoutingTable>,
+}
+
+impl RoutingStore {
+    /// Loads the cache at `path`. A missing or unreadable cache routes every
+    /// item to the default station until the server sends a table.
+    pub fn open(path: PathBuf) -> Self {
+        let table = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| match serde_json::from_str(&json) {
+                Ok(table) => Some(table),
+                Err(e) => {
+                    eprintln!("Ignoring station routes cache {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        RoutingStore {
+            path,
+            table: RwLock::new(table),
+        }
+    }
+
+    pub fn get(&self) -> RoutingTable {
+        self.table.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Stores a table received from the server, stamping `synced_at`.
+    pub fn replace(&self, mut table: RoutingTable) -> Result<RoutingTable, String> {
+        table.synced_at = now_millis();
+        let json = serde_json::to_string_pretty(&table).map_err(|e| e.to_string())?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &self.path)
+            .map_err(|e| format!("Failed to replace {}: {}", self.path.display(), e))?;
+        *self.table.write().unwrap_or_else(|e| e.into_inner()) = table.clone();
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(item_id: &str, category_id: &str, name: &str) -> OrderItem {
+        OrderItem {
+            item_id: Some(item_id.to_string()),
+            category_id: Some(category_id.to_string()),
+            name: name.to_string(),
+            quantity: 1,
+            modifiers: Vec::new(),
+            notes: None,
+        }
+    }
+
+    #[test]
+    fn splits_orders_by_item_then_category() {
+        let table: RoutingTable = serde_json::from_value(json!({
+            "items": { "item-fries": "FRYER" },
+            "categories": { "cat-grill": "GRILL", "cat-drinks": "BAR" },
+            "stations": { "GRILL": "kitchen-hot", "FRYER": "kitchen", "BAR": "bar" }
+        }))
+        .unwrap();
+        let config = PrinterConfig::from_json(&json!({
+            "printers": [
+                { "id": "kitchen", "role": "kitchen", "transport": { "type": "tcp", "host": "10.0.0.21" } },
+                { "id": "kitchen-hot", "role": "kitchen", "transport": { "type": "tcp", "host": "10.0.0.22" } }
+            ]
+        }))
+        .unwrap();
+        let order = KitchenOrder {
+            order_number: "A-1042".to_string(),
+            table_number: Some("12".to_string()),
+            items: vec![
+                item("item-burger", "cat-grill", "Burger"),
+                item("item-cola", "cat-drinks", "Cola"),
+                item("item-fries", "cat-grill", "Fries"),
+                item("item-steak", "cat-grill", "Steak"),
+                item("item-soup", "cat-soups", "Soup"),
+            ],
+            timestamp: "2024-01-15T19:30:00Z".to_string(),
+        };
+
+        let tickets = table.split(&order, &config);
+        let summary: Vec<(&str, Option<&str>, Vec<&str>)> = tickets
+            .iter()
+            .map(|t| {
+                (
+                    t.ticket.station.as_str(),
+                    t.printer_id.as_deref(),
+                    t.ticket.items.iter().map(|i| i.name.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("GRILL", Some("kitchen-hot"), vec!["Burger", "Steak"]),
+                // "bar" is not configured.
+                ("BAR", None, vec!["Cola"]),
+                ("FRYER", Some("kitchen"), vec!["Fries"]),
+                ("KITCHEN", None, vec!["Soup"]),
+            ]
+        );
+        let missing: Vec<Option<&str>> = tickets
+            .iter()
+            .map(|t| t.missing_printer.as_deref())
+            .collect();
+        assert_eq!(missing, [None, Some("bar"), None, None]);
+        assert_eq!(tickets[0].ticket.table_number.as_deref(), Some("12"));
+    }
+
+    #[test]
+    fn caches_the_table_on_disk() {
+        let dir = std::env::temp_dir().join(format!("chefcloud-routes-{}", std::process::id()));
+        let path = dir.join("station-routes.json");
+        let _ = fs::remove_file(&path);
+
+        let store = RoutingStore::open(path.clone());
+        assert_eq!(store.get(), RoutingTable::default());
+
+        let mut table = RoutingTable::default();
+        table.stations.insert("BAR".to_string(), "bar".to_string());
+        let saved = store.replace(table).unwrap();
+        assert!(saved.synced_at > 0);
+        assert_eq!(RoutingStore::open(path.clone()).get(), saved);
+
+        fs::write(&path, "{ not json").unwrap();
+        assert_eq!(RoutingStore::open(path).get(), RoutingTable::default());
+    }
+}
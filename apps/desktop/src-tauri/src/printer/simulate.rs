@@ -0,0 +1,211 @@
+//! Simulated printing. With `simulate` on, each job is written to
+//! `{appDataDir}/print-sim/` as the raw bytes (`.bin`) and a decoded text
+//! preview (`.txt`) instead of being sent to a printer.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use serde::Serialize;
+
+use super::config::{PrinterDefinition, PrinterLanguage, Transport};
+use super::decode;
+use super::ipp;
+use super::spool::now_millis;
+use super::star;
+
+/// Captures kept on disk; older ones are removed as new ones arrive.
+const KEEP_CAPTURES: usize = 200;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedPrint {
+    pub id: String,
+    /// Listed captures give the id as kept in the file name, with characters
+    /// other than letters, digits, `-` and `_` replaced by `_`.
+    pub printer_id: String,
+    pub created_at: u64,
+    pub size: u64,
+    pub bin_path: String,
+    pub txt_path: String,
+    pub preview: String,
+}
+
+pub struct SimulatedPrinter {
+    dir: PathBuf,
+    counter: AtomicU32,
+}
+
+impl SimulatedPrinter {
+    pub fn new(dir: PathBuf) -> Self {
+        SimulatedPrinter {
+            dir,
+            counter: AtomicU32::new(0),
+        }
+    }
+
+    /// Writes `bytes` and their preview for `printer`.
+    pub fn capture(
+        &self,
+        printer: &PrinterDefinition,
+        bytes: &[u8],
+    ) -> Result<SimulatedPrint, String> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        let created_at = now_millis();
+        let n = self.counter.fetch_add(1, Ordering::Relaxed) % 10_000;
+        // Zero-padded so that file names sort by time.
+        let id = format!("{:013}-{:04}-{}", created_at, n, file_safe(&printer.id));
+
+        let bin_path = self.dir.join(format!("{}.bin", id));
+        let txt_path = self.dir.join(format!("{}.txt", id));
+        let preview = preview(printer, bytes);
+        fs::write(&bin_path, bytes)
+            .and_then(|_| fs::write(&txt_path, &preview))
+            .map_err(|e| format!("Failed to write simulated print: {}", e))?;
+        self.prune();
+
+        Ok(SimulatedPrint {
+            id,
+            printer_id: printer.id.clone(),
+            created_at,
+            size: bytes.len() as u64,
+            bin_path: bin_path.to_string_lossy().into_owned(),
+            txt_path: txt_path.to_string_lossy().into_owned(),
+            preview,
+        })
+    }
+
+    /// Captured prints, newest first.
+    pub fn list(&self, limit: usize) -> Vec<SimulatedPrint> {
+        self.ids()
+            .iter()
+            .rev()
+            .take(limit)
+            .filter_map(|id| self.load(id))
+            .collect()
+    }
+
+    /// Capture ids, oldest first.
+    fn ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension()? != "bin" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_str()?.to_string())
+            })
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    fn load(&self, id: &str) -> Option<SimulatedPrint> {
+        let mut parts = id.splitn(3, '-');
+        let created_at = parts.next()?.parse().ok()?;
+        let printer_id = parts.nth(1)?.to_string();
+        let bin_path = self.dir.join(format!("{}.bin", id));
+        let txt_path = self.dir.join(format!("{}.txt", id));
+        Some(SimulatedPrint {
+            id: id.to_string(),
+            printer_id,
+            created_at,
+            size: fs::metadata(&bin_path).ok()?.len(),
+            preview: fs::read_to_string(&txt_path).unwrap_or_default(),
+            bin_path: bin_path.to_string_lossy().into_owned(),
+            txt_path: txt_path.to_string_lossy().into_owned(),
+        })
+    }
+
+    fn prune(&self) {
+        let ids = self.ids();
+        let excess = ids.len().saturating_sub(KEEP_CAPTURES);
+        for id in &ids[..excess] {
+            let _ = fs::remove_file(self.dir.join(format!("{}.bin", id)));
+            let _ = fs::remove_file(self.dir.join(format!("{}.txt", id)));
+        }
+    }
+}
+
+/// IPP printers are sent plain text or PDF rather than ESC/POS, and label
+/// printers are sent ZPL or TSPL, which is readable as it is. Star printers
+/// are decoded from Star Line Mode.
+fn preview(printer: &PrinterDefinition, bytes: &[u8]) -> String {
+    if printer.language.is_label() {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    if printer.language == PrinterLanguage::Star {
+        return star::preview(bytes, printer.columns(), printer.code_page);
+    }
+    match &printer.transport {
+        Transport::Ipp { .. } => {
+            let format = ipp::document_format(bytes);
+            if format.starts_with("text/plain") {
+                String::from_utf8_lossy(bytes).into_owned()
+            } else {
+                format!("[{}, {} bytes]\n", format, bytes.len())
+            }
+        }
+        _ => decode::preview(&decode::decode(bytes), printer.columns()),
+    }
+}
+
+/// Printer ids come from the config file; keep them to characters that are
+/// safe in a file name on every platform.
+fn file_safe(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::escpos::{Cut, EscPosBuilder};
+
+    #[test]
+    fn captures_bytes_and_preview_and_lists_newest_first() {
+        let dir = std::env::temp_dir().join(format!("chefcloud-sim-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let sim = SimulatedPrinter::new(dir.clone());
+        let printer = PrinterDefinition::tcp("bar/1", "127.0.0.1", 9100);
+
+        let first = sim.capture(&printer, b"first\n").unwrap();
+        let bytes = EscPosBuilder::new()
+            .init()
+            .line("Table 4")
+            .cut(Cut::Full)
+            .build();
+        let second = sim.capture(&printer, &bytes).unwrap();
+
+        assert_eq!(fs::read(&second.bin_path).unwrap(), bytes);
+        assert!(
+            second.preview.starts_with("Table 4\n"),
+            "{}",
+            second.preview
+        );
+        assert!(second.preview.contains(" CUT "));
+        assert_eq!(
+            fs::read_to_string(&second.txt_path).unwrap(),
+            second.preview
+        );
+
+        let listed = sim.list(10);
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].id, second.id);
+        assert_eq!(listed[0].printer_id, "bar_1");
+        assert_eq!(listed[0].size, bytes.len() as u64);
+        assert_eq!(listed[1].preview, first.preview);
+        assert_eq!(sim.list(1).len(), 1);
+    }
+}
@@ -0,0 +1,573 @@
+//! Durable print spool. Every job is persisted to `{appDataDir}/print-spool.json`,
+//! with its bytes in `print-spool/<id>.bin`, before it is sent, so a printer
+//! that is off or out of paper delays a ticket instead of losing it.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::payloads::PayloadStore;
+
+/// Attempts before a job is marked failed and left for a manual retry.
+const MAX_ATTEMPTS: u32 = 8;
+const BASE_BACKOFF_MS: u64 = 2_000;
+const MAX_BACKOFF_MS: u64 = 60_000;
+/// Finished jobs kept for listing and reprints.
+const KEEP_FINISHED: usize = 200;
+/// Failed jobs kept for a manual retry; older ones are dropped.
+const KEEP_FAILED: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Sending,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum JobKind {
+    Raw,
+    Document,
+    Receipt,
+    KitchenTicket,
+    ShiftReport,
+    Label,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PrintJob {
+    pub id: String,
+    pub printer_id: String,
+    pub kind: JobKind,
+    pub state: JobState,
+    pub attempts: u32,
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// Earliest time (unix ms) the worker may try a queued job again.
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+    /// Only set on jobs handed out to be sent. The spool keeps the bytes
+    /// in the job's payload file; older spool files carry them inline.
+    #[serde(default, skip_serializing, with = "base64_bytes")]
+    pub payload: Vec<u8>,
+}
+
+/// Set by [`Spool::cancel`] to stop a job that is being sent. Senders check
+/// it before connecting and between writes.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Spool {
+    path: PathBuf,
+    payloads: PayloadStore,
+    jobs: Mutex<Vec<PrintJob>>,
+    /// Tokens for jobs that are `sending`, by job id.
+    tokens: Mutex<HashMap<String, CancelToken>>,
+    wake: Condvar,
+    counter: AtomicU32,
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn backoff(attempts: u32) -> u64 {
+    let exp = attempts.saturating_sub(1).min(16);
+    (BASE_BACKOFF_MS << exp).min(MAX_BACKOFF_MS)
+}
+
+impl Spool {
+    /// Loads the spool at `path`. Jobs left mid-send by a previous run are
+    /// queued again, and bytes stored inline by older versions are moved to
+    /// payload files.
+    pub fn open(path: PathBuf) -> Self {
+        let payloads = PayloadStore::new(path.with_extension(""));
+        let mut jobs = load(&path);
+        for job in jobs.iter_mut() {
+            if job.state == JobState::Sending {
+                job.state = JobState::Queued;
+            }
+            if !job.payload.is_empty() && payloads.write(&job.id, &job.payload).is_ok() {
+                job.payload = Vec::new();
+            }
+        }
+        Spool {
+            path,
+            payloads,
+            jobs: Mutex::new(jobs),
+            tokens: Mutex::new(HashMap::new()),
+            wake: Condvar::new(),
+            counter: AtomicU32::new(0),
+        }
+    }
+
+    fn next_id(&self) -> String {
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        format!("{:x}-{:04x}", now_millis(), n & 0xffff)
+    }
+
+    fn persist(&self, jobs: &[PrintJob]) {
+        let Ok(json) = serde_json::to_string(jobs) else {
+            return;
+        };
+        if let Some(dir) = self.path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        if fs::write(&tmp, json).is_ok() {
+            let _ = fs::rename(&tmp, &self.path);
+        }
+    }
+
+    fn update<T>(&self, f: impl FnOnce(&mut Vec<PrintJob>) -> T) -> T {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let result = f(&mut jobs);
+        for job in prune(&mut jobs) {
+            self.payloads.remove(&job.id);
+        }
+        self.persist(&jobs);
+        result
+    }
+
+    fn new_job(
+        &self,
+        printer_id: &str,
+        kind: JobKind,
+        payload: Vec<u8>,
+        state: JobState,
+    ) -> PrintJob {
+        let now = now_millis();
+        PrintJob {
+            id: self.next_id(),
+            printer_id: printer_id.to_string(),
+            kind,
+            state,
+            attempts: 0,
+            created_at: now,
+            updated_at: now,
+            next_attempt_at: now,
+            last_error: None,
+            payload,
+        }
+    }
+
+    /// Records a job that the caller is about to send itself. The job is
+    /// claimed (`sending`) so the worker leaves it alone until
+    /// [`Spool::finish`] is called.
+    pub fn submit(&self, printer_id: &str, kind: JobKind, payload: Vec<u8>) -> PrintJob {
+        let job = self.new_job(printer_id, kind, payload, JobState::Sending);
+        self.push(&job);
+        job
+    }
+
+    /// Adds `job` to the index, with its bytes in its payload file.
+    fn push(&self, job: &PrintJob) {
+        if let Err(e) = self.payloads.write(&job.id, &job.payload) {
+            eprintln!("Print job {} will not survive a restart: {}", job.id, e);
+        }
+        self.update(|jobs| {
+            jobs.push(PrintJob {
+                payload: Vec::new(),
+                ..job.clone()
+            })
+        });
+    }
+
+    /// The token that cancels job `id` while it is being sent.
+    pub fn cancel_token(&self, id: &str) -> CancelToken {
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens.entry(id.to_string()).or_default().clone()
+    }
+
+    /// Records the outcome of a send. Failures are queued for the worker
+    /// with exponential backoff until `MAX_ATTEMPTS` is reached. A job
+    /// cancelled mid-send stays cancelled unless it finished printing.
+    pub fn finish(&self, id: &str, result: &Result<String, String>) {
+        self.update(|jobs| {
+            self.tokens
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(id);
+            let Some(job) = jobs.iter_mut().find(|j| j.id == id) else {
+                return;
+            };
+            let now = now_millis();
+            job.attempts += 1;
+            job.updated_at = now;
+            match result {
+                Ok(_) => {
+                    job.state = JobState::Done;
+                    job.last_error = None;
+                }
+                Err(e) => {
+                    job.last_error = Some(e.clone());
+                    if job.state == JobState::Cancelled {
+                        return;
+                    }
+                    if job.attempts >= MAX_ATTEMPTS {
+                        job.state = JobState::Failed;
+                    } else {
+                        job.state = JobState::Queued;
+                        job.next_attempt_at = now + backoff(job.attempts);
+                    }
+                }
+            }
+        });
+        self.wake.notify_all();
+    }
+
+    /// Claims the oldest queued job that is due, marking it `sending`, and
+    /// loads its bytes. A job whose bytes are gone is marked failed.
+    pub fn claim_due(&self, now: u64) -> Option<PrintJob> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let mut changed = false;
+        let mut claimed = None;
+        while let Some(job) = jobs
+            .iter_mut()
+            .find(|j| j.state == JobState::Queued && j.next_attempt_at <= now)
+        {
+            changed = true;
+            job.updated_at = now;
+            match self.payloads.read(&job.id) {
+                Ok(payload) => {
+                    job.state = JobState::Sending;
+                    claimed = Some(PrintJob {
+                        payload,
+                        ..job.clone()
+                    });
+                    break;
+                }
+                Err(e) => {
+                    job.state = JobState::Failed;
+                    job.last_error = Some(e);
+                }
+            }
+        }
+        if changed {
+            self.persist(&jobs);
+        }
+        claimed
+    }
+
+    pub fn list(&self) -> Vec<PrintJob> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Queues a failed or cancelled job for an immediate new attempt.
+    pub fn retry(&self, id: &str) -> Result<PrintJob, String> {
+        let job = self.update(|jobs| -> Result<PrintJob, String> {
+            let job = jobs
+                .iter_mut()
+                .find(|j| j.id == id)
+                .ok_or_else(|| format!("Unknown print job '{}'", id))?;
+            if matches!(job.state, JobState::Sending | JobState::Done) {
+                return Err(format!(
+                    "Print job '{}' cannot be retried while {:?}",
+                    id, job.state
+                ));
+            }
+            job.state = JobState::Queued;
+            job.attempts = 0;
+            job.next_attempt_at = 0;
+            job.updated_at = now_millis();
+            Ok(job.clone())
+        })?;
+        self.wake.notify_all();
+        Ok(job)
+    }
+
+    /// Cancels a job that has not printed yet. A job being sent is stopped
+    /// before its next write.
+    pub fn cancel(&self, id: &str) -> Result<PrintJob, String> {
+        self.update(|jobs| {
+            let job = jobs
+                .iter_mut()
+                .find(|j| j.id == id)
+                .ok_or_else(|| format!("Unknown print job '{}'", id))?;
+            if job.state == JobState::Done {
+                return Err(format!(
+                    "Print job '{}' cannot be cancelled while {:?}",
+                    id, job.state
+                ));
+            }
+            if job.state == JobState::Sending {
+                self.cancel_token(id).cancel();
+            }
+            job.state = JobState::Cancelled;
+            job.updated_at = now_millis();
+            Ok(job.clone())
+        })
+    }
+
+    /// Queues a copy of an earlier job's bytes as a new job.
+    pub fn reprint(&self, id: &str) -> Result<PrintJob, String> {
+        let original = self
+            .list()
+            .into_iter()
+            .find(|j| j.id == id)
+            .ok_or_else(|| format!("Unknown print job '{}'", id))?;
+        let payload = self.payloads.read(id)?;
+        let job = self.new_job(
+            &original.printer_id,
+            original.kind,
+            payload,
+            JobState::Queued,
+        );
+        self.push(&job);
+        self.wake.notify_all();
+        Ok(job)
+    }
+
+    /// Blocks until a job may be due or `timeout` elapses.
+    fn wait(&self, timeout: Duration) {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = self.wake.wait_timeout(jobs, timeout);
+    }
+}
+
+/// Drops the oldest finished jobs beyond `KEEP_FINISHED` and the oldest
+/// failed ones beyond `KEEP_FAILED`, returning them.
+fn prune(jobs: &mut Vec<PrintJob>) -> Vec<PrintJob> {
+    let finished = |j: &PrintJob| matches!(j.state, JobState::Done | JobState::Cancelled);
+    let failed = |j: &PrintJob| j.state == JobState::Failed;
+    let count = |keep: &dyn Fn(&PrintJob) -> bool| jobs.iter().filter(|j| keep(j)).count();
+    let mut finished_excess = count(&finished).saturating_sub(KEEP_FINISHED);
+    let mut failed_excess = count(&failed).saturating_sub(KEEP_FAILED);
+    let mut dropped = Vec::new();
+    jobs.retain(|j| {
+        let excess = if finished(j) {
+            &mut finished_excess
+        } else if failed(j) {
+            &mut failed_excess
+        } else {
+            return true;
+        };
+        if *excess == 0 {
+            return true;
+        }
+        *excess -= 1;
+        dropped.push(j.clone());
+        false
+    });
+    dropped
+}
+
+/// Starts the background worker that retries queued jobs with `send`.
+pub fn spawn_worker<F>(spool: Arc<Spool>, send: F)
+where
+    F: Fn(&PrintJob, &CancelToken) -> Result<String, String> + Send + 'static,
+{
+    thread::spawn(move || loop {
+        match spool.claim_due(now_millis()) {
+            Some(job) => {
+                let result = send(&job, &spool.cancel_token(&job.id));
+                spool.finish(&job.id, &result);
+            }
+            None => spool.wait(Duration::from_secs(1)),
+        }
+    });
+}
+
+pub(crate) mod base64_bytes {
+    use base64::{engine::general_purpose, Engine as _};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&general_purpose::STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Reads the spool index. Only a missing index starts an empty spool; one
+/// that cannot be read or parsed is moved to `print-spool.json.corrupt` so
+/// the first persist cannot overwrite the jobs it still holds.
+fn load(path: &Path) -> Vec<PrintJob> {
+    let error = match fs::read(path) {
+        Ok(content) => match serde_json::from_slice(&content) {
+            Ok(jobs) => return jobs,
+            Err(e) => e.to_string(),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => e.to_string(),
+    };
+    let aside = path.with_extension("json.corrupt");
+    match fs::rename(path, &aside) {
+        Ok(()) => eprintln!(
+            "Print spool {} is unreadable ({}), moved it to {}",
+            path.display(),
+            error,
+            aside.display()
+        ),
+        Err(e) => eprintln!(
+            "Print spool {} is unreadable ({}) and could not be moved aside: {}",
+            path.display(),
+            error,
+            e
+        ),
+    }
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_spool(name: &str) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("chefcloud-spool-{}-{}", name, std::process::id()))
+            .join("print-spool.json");
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn moves_an_unreadable_index_aside() {
+        let path = temp_spool("corrupt");
+        let aside = path.with_extension("json.corrupt");
+        let _ = fs::remove_file(&aside);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "[{\"id\": \"18c2-00").unwrap();
+
+        let spool = Spool::open(path.clone());
+        assert!(spool.list().is_empty());
+        assert_eq!(fs::read_to_string(&aside).unwrap(), "[{\"id\": \"18c2-00");
+
+        spool.submit("receipt", JobKind::Raw, b"abc".to_vec());
+        assert_eq!(fs::read_to_string(&aside).unwrap(), "[{\"id\": \"18c2-00");
+    }
+
+    #[test]
+    fn failed_send_is_queued_with_backoff_and_survives_restart() {
+        let path = temp_spool("backoff");
+        let spool = Spool::open(path.clone());
+        let job = spool.submit("kitchen-1", JobKind::KitchenTicket, b"ticket".to_vec());
+        assert_eq!(job.state, JobState::Sending);
+
+        spool.finish(&job.id, &Err("Failed to connect".to_string()));
+        let queued = &spool.list()[0];
+        assert_eq!(queued.state, JobState::Queued);
+        assert_eq!(queued.attempts, 1);
+        assert!(queued.next_attempt_at >= queued.updated_at + BASE_BACKOFF_MS);
+        assert!(spool.claim_due(queued.updated_at).is_none());
+
+        let claimed = spool.claim_due(queued.next_attempt_at).unwrap();
+        assert_eq!(claimed.id, job.id);
+
+        // A crash mid-send leaves the job claimed; reopening queues it again.
+        let reopened = Spool::open(path.clone());
+        let job = &reopened.list()[0];
+        assert_eq!(job.state, JobState::Queued);
+        assert!(job.payload.is_empty());
+        assert_eq!(reopened.claim_due(u64::MAX).unwrap().payload, b"ticket");
+        // The index holds no job bytes.
+        assert!(!fs::read_to_string(&path).unwrap().contains("payload"));
+    }
+
+    #[test]
+    fn job_fails_after_max_attempts_and_can_be_retried() {
+        let spool = Spool::open(temp_spool("retry"));
+        let job = spool.submit("receipt", JobKind::Receipt, vec![0x1b, 0x40]);
+        for _ in 0..MAX_ATTEMPTS {
+            spool.finish(&job.id, &Err("Out of paper".to_string()));
+        }
+        assert_eq!(spool.list()[0].state, JobState::Failed);
+
+        let retried = spool.retry(&job.id).unwrap();
+        assert_eq!(retried.state, JobState::Queued);
+        assert_eq!(retried.attempts, 0);
+        assert!(spool.claim_due(0).is_some());
+    }
+
+    #[test]
+    fn keeps_only_the_newest_failed_jobs() {
+        let path = temp_spool("failed-cap");
+        let spool = Spool::open(path.clone());
+        let ids: Vec<String> = (0..KEEP_FAILED + 2)
+            .map(|_| {
+                let job = spool.submit("receipt", JobKind::Raw, b"abc".to_vec());
+                for _ in 0..MAX_ATTEMPTS {
+                    spool.finish(&job.id, &Err("Out of paper".to_string()));
+                }
+                job.id
+            })
+            .collect();
+        let kept: Vec<String> = spool.list().into_iter().map(|j| j.id).collect();
+        assert_eq!(kept, ids[2..]);
+        let payloads = PayloadStore::new(path.with_extension(""));
+        assert!(payloads.read(&ids[0]).is_err());
+        assert_eq!(payloads.read(&ids[2]).unwrap(), b"abc");
+
+        // A job whose bytes are gone fails instead of printing nothing.
+        payloads.remove(&ids[2]);
+        spool.retry(&ids[2]).unwrap();
+        assert!(spool.claim_due(u64::MAX).is_none());
+        assert_eq!(spool.list()[0].state, JobState::Failed);
+    }
+
+    #[test]
+    fn cancel_and_reprint() {
+        let spool = Spool::open(temp_spool("cancel"));
+        let job = spool.submit("receipt", JobKind::Raw, b"abc".to_vec());
+        spool.finish(&job.id, &Err("Timeout".to_string()));
+        assert_eq!(spool.cancel(&job.id).unwrap().state, JobState::Cancelled);
+        assert!(spool.claim_due(u64::MAX).is_none());
+
+        let copy = spool.reprint(&job.id).unwrap();
+        assert_ne!(copy.id, job.id);
+        assert_eq!(copy.payload, b"abc");
+        assert_eq!(spool.claim_due(u64::MAX).unwrap().id, copy.id);
+        assert!(spool.retry("missing").is_err());
+
+        spool.finish(&copy.id, &Ok("Printed".to_string()));
+        assert!(spool.cancel(&copy.id).is_err());
+    }
+
+    #[test]
+    fn cancelling_a_job_mid_send_trips_its_token() {
+        let spool = Spool::open(temp_spool("cancel-sending"));
+        let job = spool.submit("receipt", JobKind::Raw, b"abc".to_vec());
+        let token = spool.cancel_token(&job.id);
+        assert!(!token.is_cancelled());
+
+        assert_eq!(spool.cancel(&job.id).unwrap().state, JobState::Cancelled);
+        assert!(token.is_cancelled());
+
+        // The sender gives up; the job is not queued for a retry.
+        spool.finish(&job.id, &Err("Print job cancelled".to_string()));
+        let cancelled = &spool.list()[0];
+        assert_eq!(cancelled.state, JobState::Cancelled);
+        assert!(spool.claim_due(u64::MAX).is_none());
+        assert!(!spool.cancel_token(&job.id).is_cancelled());
+    }
+}
@@ -0,0 +1,409 @@
+//! Star Line Mode commands for Star receipt printers (TSP100, TSP650,
+//! TSP700II, mC-Print) set to Star emulation. StarPRNT printers accept the
+//! same commands for everything documents use.
+
+use super::barcode;
+use super::bitmap::Bitmap;
+use super::codepage::CodePage;
+use super::escpos::{Align, Cut, Hri, QrErrorCorrection, Symbology, Underline};
+
+// Star Line Mode control codes
+const BEL: u8 = 0x07;
+const LF: u8 = 0x0a;
+const SUB: u8 = 0x1a;
+const ESC: u8 = 0x1b;
+const GS: u8 = 0x1d;
+const RS: u8 = 0x1e;
+
+pub struct StarBuilder {
+    buf: Vec<u8>,
+    code_page: CodePage,
+    fallback: String,
+}
+
+impl Default for StarBuilder {
+    fn default() -> Self {
+        Self::with_code_page(CodePage::default(), "?")
+    }
+}
+
+impl StarBuilder {
+    /// Builder whose text is transcoded to `code_page`. Characters the code
+    /// page cannot represent are replaced with `fallback`.
+    pub fn with_code_page(code_page: CodePage, fallback: &str) -> Self {
+        StarBuilder {
+            buf: Vec::new(),
+            code_page,
+            fallback: fallback.to_string(),
+        }
+    }
+
+    pub fn init(&mut self) -> &mut Self {
+        // ESC @ - Initialize printer, ESC GS t n - Select code page
+        let table = self.code_page.star_table();
+        self.raw(&[ESC, 0x40]).raw(&[ESC, GS, 0x74, table])
+    }
+
+    pub fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn text(&mut self, content: &str) -> &mut Self {
+        let bytes = self.code_page.encode(content, &self.fallback);
+        self.raw(&bytes)
+    }
+
+    pub fn newline(&mut self) -> &mut Self {
+        self.raw(&[LF])
+    }
+
+    pub fn line(&mut self, content: &str) -> &mut Self {
+        self.text(content).newline()
+    }
+
+    pub fn bold(&mut self, enable: bool) -> &mut Self {
+        // ESC E - Emphasized on, ESC F - Emphasized off
+        self.raw(&[ESC, if enable { 0x45 } else { 0x46 }])
+    }
+
+    /// Star printers have a single underline; double prints as single.
+    pub fn underline(&mut self, underline: Underline) -> &mut Self {
+        // ESC - n - Underline
+        let n = match underline {
+            Underline::None => 0,
+            Underline::Single | Underline::Double => 1,
+        };
+        self.raw(&[ESC, 0x2d, n])
+    }
+
+    pub fn invert(&mut self, enable: bool) -> &mut Self {
+        // ESC 4 - White/black reverse on, ESC 5 - off
+        self.raw(&[ESC, if enable { 0x34 } else { 0x35 }])
+    }
+
+    pub fn align(&mut self, align: Align) -> &mut Self {
+        // ESC GS a n - Position alignment
+        let n = match align {
+            Align::Left => 0,
+            Align::Center => 1,
+            Align::Right => 2,
+        };
+        self.raw(&[ESC, GS, 0x61, n])
+    }
+
+    /// Character expansion, 1 to 6 in each direction.
+    pub fn size(&mut self, width: u8, height: u8) -> &mut Self {
+        // ESC i n1 n2 - Height (n1) and width (n2) expansion
+        let w = width.clamp(1, 6) - 1;
+        let h = height.clamp(1, 6) - 1;
+        self.raw(&[ESC, 0x69, h, w])
+    }
+
+    pub fn feed(&mut self, lines: u8) -> &mut Self {
+        if lines == 0 {
+            return self;
+        }
+        // ESC a n - Feed n lines
+        self.raw(&[ESC, 0x61, lines])
+    }
+
+    /// Feeds to the cutter position, then cuts.
+    pub fn cut(&mut self, cut: Cut) -> &mut Self {
+        // ESC d n - Feed to cutting position and cut
+        let n = match cut {
+            Cut::Full => 2,
+            Cut::Partial => 3,
+        };
+        self.raw(&[ESC, 0x64, n])
+    }
+
+    /// Pulses the cash drawer connector. `pin` is 2 or 5. Drive 1 (pin 2)
+    /// takes on and off times, rounded down to the printer's 10 ms units;
+    /// drive 2 (pin 5) uses the pulse stored in the printer's memory switch.
+    pub fn drawer_kick(&mut self, pin: u8, on_ms: u16, off_ms: u16) -> &mut Self {
+        if pin == 5 {
+            // SUB - Fire external device 2
+            return self.raw(&[SUB]);
+        }
+        // ESC BEL n1 n2 - Set drive pulse width, BEL - Fire external device 1
+        let n1 = (on_ms / 10).clamp(1, 127) as u8;
+        let n2 = (off_ms / 10).clamp(1, 127) as u8;
+        self.raw(&[ESC, BEL, n1, n2, BEL])
+    }
+
+    /// Native QR code (model 2). `module_size` is the dot size of one cell,
+    /// 1 to 8.
+    pub fn qr(&mut self, data: &str, module_size: u8, ec: QrErrorCorrection) -> &mut Self {
+        let ec = match ec {
+            QrErrorCorrection::L => 0,
+            QrErrorCorrection::M => 1,
+            QrErrorCorrection::Q => 2,
+            QrErrorCorrection::H => 3,
+        };
+        let len = data.len();
+        // ESC GS y S - Select model 2, error correction level and cell size
+        self.raw(&[ESC, GS, 0x79, 0x53, 0x30, 2])
+            .raw(&[ESC, GS, 0x79, 0x53, 0x31, ec])
+            .raw(&[ESC, GS, 0x79, 0x53, 0x32, module_size.clamp(1, 8)])
+            // ESC GS y D 1 m nL nH - Store data, then ESC GS y P - print it
+            .raw(&[
+                ESC,
+                GS,
+                0x79,
+                0x44,
+                0x31,
+                0,
+                (len & 0xff) as u8,
+                (len >> 8) as u8,
+            ])
+            .raw(data.as_bytes())
+            .raw(&[ESC, GS, 0x79, 0x50])
+    }
+
+    /// 1D barcode. `height` is in dots and `width` the narrowest module in
+    /// dots (2-4). Star prints the human-readable text below the bars only,
+    /// so any position other than [`Hri::None`] prints it there.
+    pub fn barcode(
+        &mut self,
+        symbology: Symbology,
+        data: &str,
+        height: u8,
+        width: u8,
+        hri: Hri,
+    ) -> &mut Self {
+        let text;
+        let (n1, data) = match symbology {
+            // ESC/POS code set selectors have no meaning here; Star picks
+            // the code set itself, so send the text they encode.
+            Symbology::Code128 if data.starts_with('{') => {
+                text = barcode::encode(symbology, data)
+                    .map(|bars| bars.text)
+                    .unwrap_or_default();
+                (b'6', text.as_str())
+            }
+            Symbology::Code128 => (b'6', data),
+            Symbology::Ean13 => (b'3', data),
+            Symbology::Code39 => (b'4', data),
+        };
+        // Characters under the bars, no line feed after.
+        let n2 = if hri == Hri::None { b'3' } else { b'4' };
+        let n3 = b'0' + width.clamp(2, 4) - 1;
+        // ESC b n1 n2 n3 n4 d1...dk RS - Print barcode
+        self.raw(&[ESC, 0x62, n1, n2, n3, height.max(1)])
+            .raw(&data.as_bytes()[..data.len().min(255)])
+            .raw(&[RS])
+    }
+
+    /// Prints a 1-bit raster image.
+    pub fn raster(&mut self, bitmap: &Bitmap) -> &mut Self {
+        let x = bitmap.bytes_per_row();
+        let y = bitmap.height;
+        // ESC GS S m xL xH yL yH n d1...dk - Print raster graphics
+        self.raw(&[
+            ESC,
+            GS,
+            0x53,
+            1,
+            (x & 0xff) as u8,
+            (x >> 8) as u8,
+            (y & 0xff) as u8,
+            (y >> 8) as u8,
+            0,
+        ])
+        .raw(&bitmap.data)
+    }
+
+    /// Star Line Mode has no equivalent of Epson NV graphics keys.
+    pub fn nv_graphic_print(&mut self, key: &str) -> Result<&mut Self, String> {
+        Err(format!(
+            "NV logo '{}' cannot be printed on a Star printer; use the raster logo mode",
+            key
+        ))
+    }
+
+    pub fn separator(&mut self, ch: char, length: usize) -> &mut Self {
+        self.line(&ch.to_string().repeat(length))
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        self.buf.clone()
+    }
+}
+
+/// Plain-text preview of Star Line Mode bytes, in the style of
+/// [`super::decode::preview`]: text is laid out on `columns`, and codes,
+/// images and cuts are shown as bracketed markers.
+pub fn preview(bytes: &[u8], columns: usize, code_page: CodePage) -> String {
+    let mut out = String::new();
+    let mut line: Vec<u8> = Vec::new();
+    let mut align = Align::Left;
+    let flush = |line: &mut Vec<u8>, align: Align, out: &mut String| {
+        let decoded = code_page.decode(line);
+        let text = decoded.trim_end();
+        let pad = columns.saturating_sub(decoded.chars().count());
+        let left = match align {
+            Align::Left => 0,
+            Align::Center => pad / 2,
+            Align::Right => pad,
+        };
+        if !text.is_empty() {
+            out.push_str(&" ".repeat(left));
+            out.push_str(text);
+        }
+        out.push('\n');
+        line.clear();
+    };
+    let mut qr_data = String::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        let arg = |n: usize| rest.get(n).copied().unwrap_or(0) as usize;
+        let (len, marker) = match rest {
+            [LF, ..] => {
+                flush(&mut line, align, &mut out);
+                (1, None)
+            }
+            [BEL, ..] => (1, Some("[DRAWER KICK pin 2]".to_string())),
+            [SUB, ..] => (1, Some("[DRAWER KICK pin 5]".to_string())),
+            [ESC, GS, 0x61, n, ..] => {
+                align = match n {
+                    1 => Align::Center,
+                    2 => Align::Right,
+                    _ => Align::Left,
+                };
+                (4, None)
+            }
+            [ESC, GS, 0x79, 0x44, ..] => {
+                let n = arg(6) | (arg(7) << 8);
+                let end = (8 + n).min(rest.len());
+                qr_data = String::from_utf8_lossy(&rest[8.min(end)..end]).into_owned();
+                (end, None)
+            }
+            [ESC, GS, 0x79, 0x50, ..] => (4, Some(format!("[QR {}]", qr_data))),
+            [ESC, GS, 0x79, 0x53, ..] => (6, None),
+            [ESC, GS, 0x74, ..] => (4, None),
+            [ESC, GS, 0x53, ..] => {
+                let (x, y) = (arg(4) | (arg(5) << 8), arg(6) | (arg(7) << 8));
+                (9 + x * y, Some(format!("[IMAGE {}x{}]", x * 8, y)))
+            }
+            [ESC, 0x62, n1, ..] => {
+                let end = rest.iter().position(|&b| b == RS).unwrap_or(rest.len());
+                let name = match n1 {
+                    b'6' => "CODE128",
+                    b'3' => "EAN13",
+                    b'4' => "CODE39",
+                    _ => "BARCODE",
+                };
+                let data = String::from_utf8_lossy(&rest[6.min(end)..end]);
+                (end + 1, Some(format!("[{} {}]", name, data)))
+            }
+            [ESC, 0x61, n, ..] => {
+                flush(&mut line, align, &mut out);
+                for _ in 0..*n {
+                    out.push('\n');
+                }
+                (3, None)
+            }
+            [ESC, 0x64, n, ..] => {
+                let label = if *n == 3 { " PARTIAL CUT " } else { " CUT " };
+                (3, Some(format!("{:-^width$}", label, width = columns)))
+            }
+            [ESC, BEL, ..] | [ESC, 0x69, ..] => (4, None),
+            [ESC, 0x2d, ..] => (3, None),
+            [ESC, ..] => (2, None),
+            [b, ..] => {
+                line.push(*b);
+                (1, None)
+            }
+            [] => break,
+        };
+        if let Some(marker) = marker {
+            if !line.is_empty() {
+                flush(&mut line, align, &mut out);
+            }
+            line.extend_from_slice(marker.as_bytes());
+            flush(&mut line, align, &mut out);
+        }
+        i += len;
+    }
+    if !line.is_empty() {
+        flush(&mut line, align, &mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_styles_to_star_line_mode() {
+        let bytes = StarBuilder::default()
+            .init()
+            .align(Align::Center)
+            .bold(true)
+            .size(2, 2)
+            .line("TOTAL")
+            .bold(false)
+            .cut(Cut::Partial)
+            .build();
+        assert_eq!(
+            bytes,
+            [
+                &[ESC, 0x40, ESC, GS, 0x74, 1][..],
+                &[ESC, GS, 0x61, 1],
+                &[ESC, 0x45],
+                &[ESC, 0x69, 1, 1],
+                b"TOTAL\n",
+                &[ESC, 0x46],
+                &[ESC, 0x64, 3],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn encodes_qr_codes_and_barcodes() {
+        let qr = StarBuilder::default()
+            .qr("https://chefcloud.app/r/42", 4, QrErrorCorrection::Q)
+            .build();
+        assert_eq!(
+            &qr[..18],
+            &[
+                ESC, GS, 0x79, 0x53, 0x30, 2, ESC, GS, 0x79, 0x53, 0x31, 2, ESC, GS, 0x79, 0x53,
+                0x32, 4
+            ]
+        );
+        assert_eq!(&qr[18..26], &[ESC, GS, 0x79, 0x44, 0x31, 0, 26, 0]);
+        assert!(qr.ends_with(b"https://chefcloud.app/r/42\x1b\x1dyP"));
+
+        let barcode = StarBuilder::default()
+            .barcode(Symbology::Code128, "{BA-1042", 80, 3, Hri::Below)
+            .build();
+        assert_eq!(barcode, b"\x1bb642\x50A-1042\x1e");
+        let barcode = StarBuilder::default()
+            .barcode(Symbology::Code128, "{C1234{B{{A", 80, 3, Hri::Below)
+            .build();
+        assert!(barcode.ends_with(b"1234{A\x1e"));
+    }
+
+    #[test]
+    fn previews_star_bytes_as_text() {
+        let bytes = StarBuilder::default()
+            .init()
+            .align(Align::Center)
+            .line("CHEFCLOUD")
+            .qr("A-1042", 4, QrErrorCorrection::M)
+            .newline()
+            .align(Align::Left)
+            .line("Total 12.00")
+            .cut(Cut::Full)
+            .build();
+        let preview = preview(&bytes, 20, CodePage::Cp437);
+        assert_eq!(
+            preview,
+            "     CHEFCLOUD\n    [QR A-1042]\n\nTotal 12.00\n------- CUT --------\n"
+        );
+    }
+}
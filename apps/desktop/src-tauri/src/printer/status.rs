@@ -0,0 +1,257 @@
+//! Real-time printer status over ESC/POS `DLE EOT n` queries and Automatic
+//! Status Back (ASB, `GS a n`).
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+const DLE: u8 = 0x10;
+const EOT: u8 = 0x04;
+
+/// GS a n - Enable ASB for drawer, online/offline, error and paper sensor
+/// changes.
+pub const ENABLE_ASB: [u8; 3] = [0x1d, 0x61, 0x0f];
+
+/// How the printer's status is read back while printing.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusMode {
+    /// No status reads after a job; `get_printer_status` still queries.
+    #[default]
+    None,
+    /// `DLE EOT` queries on the print connection after each job.
+    Query,
+    /// Enable ASB on the print connection and read the status it pushes.
+    Asb,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PrinterStatus {
+    pub online: bool,
+    pub cover_open: bool,
+    pub paper_near_end: bool,
+    pub paper_end: bool,
+    pub cutter_error: bool,
+    pub unrecoverable_error: bool,
+    pub auto_recoverable_error: bool,
+    /// Drawer kick-out connector pin 3 is high. Whether that means open or
+    /// closed depends on the drawer.
+    pub drawer_pin_high: bool,
+    pub feed_button: bool,
+}
+
+fn bit(byte: u8, n: u8) -> bool {
+    byte & (1 << n) != 0
+}
+
+impl PrinterStatus {
+    /// Status reported for simulated printers.
+    pub fn simulated() -> Self {
+        PrinterStatus {
+            online: true,
+            ..PrinterStatus::default()
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.online
+            && !self.cover_open
+            && !self.paper_end
+            && !self.cutter_error
+            && !self.unrecoverable_error
+            && !self.auto_recoverable_error
+    }
+
+    /// What keeps the printer from being ready, e.g. `["cover open"]`.
+    pub fn problems(&self) -> Vec<&'static str> {
+        [
+            (!self.online, "offline"),
+            (self.cover_open, "cover open"),
+            (self.paper_end, "paper end"),
+            (self.cutter_error, "cutter error"),
+            (self.unrecoverable_error, "unrecoverable error"),
+            (self.auto_recoverable_error, "auto-recoverable error"),
+        ]
+        .into_iter()
+        .filter_map(|(set, problem)| set.then_some(problem))
+        .collect()
+    }
+
+    /// Decodes the replies to `DLE EOT 1` (printer), `2` (offline cause),
+    /// `3` (error cause) and `4` (paper roll sensor).
+    pub fn from_dle_eot(printer: u8, offline: u8, error: u8, paper: u8) -> Self {
+        PrinterStatus {
+            online: !bit(printer, 3),
+            drawer_pin_high: bit(printer, 2),
+            cover_open: bit(offline, 2),
+            feed_button: bit(offline, 3),
+            paper_end: bit(offline, 5) || paper & 0x60 != 0,
+            cutter_error: bit(error, 3),
+            unrecoverable_error: bit(error, 5),
+            auto_recoverable_error: bit(error, 6),
+            paper_near_end: paper & 0x0c != 0,
+        }
+    }
+
+    /// Decodes a 4-byte ASB packet.
+    pub fn from_asb(packet: [u8; 4]) -> Self {
+        PrinterStatus {
+            drawer_pin_high: bit(packet[0], 2),
+            online: !bit(packet[0], 3),
+            cover_open: bit(packet[0], 5),
+            feed_button: bit(packet[0], 6),
+            cutter_error: bit(packet[1], 3),
+            unrecoverable_error: bit(packet[1], 5),
+            auto_recoverable_error: bit(packet[1], 6),
+            paper_near_end: packet[2] & 0x03 != 0,
+            paper_end: packet[2] & 0x0c != 0,
+        }
+    }
+}
+
+/// Real-time status bytes have bits 1 and 4 set and bits 0 and 7 clear.
+fn is_status_byte(byte: u8) -> bool {
+    byte & 0x93 == 0x12
+}
+
+/// The first byte of an ASB packet has bit 4 set and bits 0, 1 and 7 clear.
+fn is_asb_header(byte: u8) -> bool {
+    byte & 0x93 == 0x10
+}
+
+/// Sends `DLE EOT 1..=4` and decodes the four replies.
+pub fn query<S: Read + Write>(stream: &mut S) -> io::Result<PrinterStatus> {
+    let mut replies = [0u8; 4];
+    for (n, reply) in (1u8..=4).zip(replies.iter_mut()) {
+        stream.write_all(&[DLE, EOT, n])?;
+        stream.flush()?;
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte)?;
+        if !is_status_byte(byte[0]) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unexpected reply 0x{:02x} to DLE EOT {}", byte[0], n),
+            ));
+        }
+        *reply = byte[0];
+    }
+    Ok(PrinterStatus::from_dle_eot(
+        replies[0], replies[1], replies[2], replies[3],
+    ))
+}
+
+/// Reads the next ASB packet, skipping any bytes before its header.
+pub fn read_asb<S: Read>(stream: &mut S) -> io::Result<PrinterStatus> {
+    let mut byte = [0u8; 1];
+    loop {
+        stream.read_exact(&mut byte)?;
+        if is_asb_header(byte[0]) {
+            break;
+        }
+    }
+    let mut packet = [byte[0], 0, 0, 0];
+    stream.read_exact(&mut packet[1..])?;
+    Ok(PrinterStatus::from_asb(packet))
+}
+
+/// Last known status per printer id.
+#[derive(Default)]
+pub struct StatusBoard {
+    statuses: Mutex<HashMap<String, PrinterStatus>>,
+}
+
+impl StatusBoard {
+    /// Stores `status` and returns whether it differs from the last one.
+    pub fn record(&self, printer_id: &str, status: PrinterStatus) -> bool {
+        let mut statuses = self.statuses.lock().unwrap_or_else(|e| e.into_inner());
+        statuses.insert(printer_id.to_string(), status) != Some(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Answers each query from a scripted list of replies.
+    struct FakePrinter {
+        replies: Cursor<Vec<u8>>,
+        sent: Vec<u8>,
+    }
+
+    impl Read for FakePrinter {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for FakePrinter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn decodes_dle_eot_replies() {
+        let mut printer = FakePrinter {
+            // offline; cover open; autocutter error; paper near end
+            replies: Cursor::new(vec![0x1a, 0x16, 0x1a, 0x1e]),
+            sent: Vec::new(),
+        };
+        let status = query(&mut printer).unwrap();
+
+        assert_eq!(
+            printer.sent,
+            [0x10, 0x04, 0x01, 0x10, 0x04, 0x02, 0x10, 0x04, 0x03, 0x10, 0x04, 0x04]
+        );
+        assert!(!status.online);
+        assert!(status.cover_open);
+        assert!(status.cutter_error);
+        assert!(status.paper_near_end);
+        assert!(!status.paper_end);
+        assert!(!status.is_ready());
+    }
+
+    #[test]
+    fn ready_printer_and_invalid_reply() {
+        let mut printer = FakePrinter {
+            replies: Cursor::new(vec![0x12, 0x12, 0x12, 0x12]),
+            sent: Vec::new(),
+        };
+        assert!(query(&mut printer).unwrap().is_ready());
+
+        let mut printer = FakePrinter {
+            replies: Cursor::new(vec![0xff]),
+            sent: Vec::new(),
+        };
+        let err = query(&mut printer).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reads_asb_packet_after_noise() {
+        // XON, then cover open + paper end
+        let mut stream = Cursor::new(vec![0x11, 0x30, 0x00, 0x0c, 0x00]);
+        let status = read_asb(&mut stream).unwrap();
+        assert!(status.online);
+        assert!(status.cover_open);
+        assert!(status.paper_end);
+        assert!(!status.paper_near_end);
+    }
+
+    #[test]
+    fn board_reports_changes_only() {
+        let board = StatusBoard::default();
+        assert!(board.record("receipt", PrinterStatus::simulated()));
+        assert!(!board.record("receipt", PrinterStatus::simulated()));
+        assert!(board.record("receipt", PrinterStatus::default()));
+    }
+}
@@ -0,0 +1,563 @@
+//! Receipt, kitchen ticket and shift report layouts. These mirror the data
+//! shapes in `packages/printer` so the frontend can send the same objects.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::document::{Block, Document, TextStyle};
+use super::escpos::{Align, Cut, Hri, QrErrorCorrection, Symbology};
+use super::layout::{self, Cell};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptItem {
+    pub name: String,
+    pub quantity: u32,
+    pub price: f64,
+    pub subtotal: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptData {
+    pub restaurant_name: String,
+    pub branch_name: String,
+    pub order_number: String,
+    #[serde(default)]
+    pub table_number: Option<String>,
+    pub service_type: String,
+    pub items: Vec<ReceiptItem>,
+    pub subtotal: f64,
+    pub tax: f64,
+    pub discount: f64,
+    pub total: f64,
+    pub payment_method: String,
+    #[serde(default)]
+    pub footer: Option<String>,
+    /// EFRIS fiscal verification link, printed as a QR code.
+    #[serde(default)]
+    pub fiscal_url: Option<String>,
+    /// Feedback survey link, printed as a QR code.
+    #[serde(default)]
+    pub feedback_url: Option<String>,
+    pub timestamp: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct KitchenTicketItem {
+    pub name: String,
+    pub quantity: u32,
+    #[serde(default)]
+    pub modifiers: Vec<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct KitchenTicketData {
+    pub order_number: String,
+    #[serde(default)]
+    pub table_number: Option<String>,
+    pub station: String,
+    pub items: Vec<KitchenTicketItem>,
+    pub timestamp: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReportType {
+    XReport,
+    ZReport,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ShiftInfo {
+    pub opened_at: String,
+    #[serde(default)]
+    pub closed_at: Option<String>,
+    pub opened_by: String,
+    #[serde(default)]
+    pub closed_by: Option<String>,
+    pub opening_float: f64,
+    #[serde(default)]
+    pub declared_cash: Option<f64>,
+    #[serde(default)]
+    pub over_short: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportSummary {
+    pub order_count: u32,
+    pub total_sales: f64,
+    pub total_discount: f64,
+    #[serde(default)]
+    pub payments_by_method: Option<BTreeMap<String, f64>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportData {
+    #[serde(rename = "type")]
+    pub report_type: ReportType,
+    pub shift: ShiftInfo,
+    pub summary: ReportSummary,
+    pub generated_at: String,
+}
+
+/// Accumulates blocks for a template.
+struct Layout {
+    columns: usize,
+    blocks: Vec<Block>,
+}
+
+impl Layout {
+    fn new(columns: usize) -> Self {
+        Layout {
+            columns,
+            blocks: Vec::new(),
+        }
+    }
+
+    fn styled(&mut self, content: impl Into<String>, style: TextStyle) {
+        self.blocks.push(Block::Text {
+            content: content.into(),
+            style,
+        });
+    }
+
+    fn text(&mut self, content: impl Into<String>) {
+        self.styled(content, TextStyle::default());
+    }
+
+    fn bold(&mut self, content: impl Into<String>) {
+        self.styled(
+            content,
+            TextStyle {
+                bold: true,
+                ..TextStyle::default()
+            },
+        );
+    }
+
+    fn heading(&mut self, content: impl Into<String>) {
+        self.styled(
+            content,
+            TextStyle {
+                align: Align::Center,
+                bold: true,
+                width: 2,
+                height: 2,
+                ..TextStyle::default()
+            },
+        );
+    }
+
+    fn centered(&mut self, content: impl Into<String>) {
+        self.styled(
+            content,
+            TextStyle {
+                align: Align::Center,
+                ..TextStyle::default()
+            },
+        );
+    }
+
+    /// Cells across the line, in columns of the printer's width at the
+    /// style's character size.
+    fn row(&mut self, cells: &[Cell], style: TextStyle) {
+        let columns = self.columns / style.width.max(1) as usize;
+        for line in layout::row(cells, columns) {
+            self.styled(line, style);
+        }
+    }
+
+    /// `left` and `right` on one line, with `right` flush to the margin. A
+    /// long `left` wraps short of `right`.
+    fn pair(&mut self, left: &str, right: &str, style: TextStyle) {
+        let right = Cell::fixed(right, right.chars().count(), Align::Right);
+        self.row(&[Cell::new(left), right], style);
+    }
+
+    /// `marker` and `content` indented under an item, with `content`
+    /// wrapping under itself.
+    fn indented(&mut self, marker: &str, content: &str) {
+        self.row(
+            &[
+                Cell::fixed("", 1, Align::Left),
+                Cell::fixed(marker, marker.chars().count(), Align::Left),
+                Cell::new(content),
+            ],
+            TextStyle::default(),
+        );
+    }
+
+    fn amount(&mut self, label: &str, value: f64) {
+        self.pair(label, &format!("{:.2}", value), TextStyle::default());
+    }
+
+    fn qr(&mut self, data: &str) {
+        self.blocks.push(Block::Qr {
+            data: data.to_string(),
+            size: 5,
+            error_correction: QrErrorCorrection::M,
+            align: Align::Center,
+        });
+    }
+
+    fn separator(&mut self, ch: char) {
+        self.blocks.push(Block::Separator { ch });
+    }
+
+    fn finish(mut self) -> Document {
+        self.blocks.push(Block::Feed { lines: 3 });
+        self.blocks.push(Block::Cut { mode: Cut::Full });
+        Document {
+            blocks: self.blocks,
+        }
+    }
+}
+
+/// Tidies an ISO-8601 timestamp (`2024-05-01T18:30:00.000Z`) to
+/// `2024-05-01 18:30`. Other strings are printed as given.
+fn format_timestamp(timestamp: &str) -> String {
+    match timestamp.split_once('T') {
+        Some((date, time)) if time.len() >= 5 => format!("{} {}", date, &time[..5]),
+        _ => timestamp.to_string(),
+    }
+}
+
+pub fn receipt(data: &ReceiptData, columns: usize) -> Document {
+    let mut layout = Layout::new(columns);
+
+    // Header
+    layout.blocks.push(Block::Logo);
+    layout.heading(&data.restaurant_name);
+    layout.centered(&data.branch_name);
+    layout.separator('=');
+
+    // Order info
+    layout.text(format!("Order: {}", data.order_number));
+    layout.text(format!("Date: {}", format_timestamp(&data.timestamp)));
+    if let Some(table) = &data.table_number {
+        layout.text(format!("Table: {}", table));
+    }
+    layout.text(format!("Type: {}", data.service_type));
+    layout.separator('-');
+
+    // Items, with the amount column as wide as the widest amount on the
+    // receipt so wrapped names stay clear of it
+    let quantity = |item: &ReceiptItem| format!("{}x", item.quantity);
+    let quantity_width = data
+        .items
+        .iter()
+        .map(|item| quantity(item).len())
+        .max()
+        .unwrap_or(0);
+    let amount_width = data
+        .items
+        .iter()
+        .map(|item| item.subtotal)
+        .chain([data.subtotal, data.tax, -data.discount, data.total])
+        .map(|amount| format!("{:.2}", amount).len())
+        .max()
+        .unwrap_or(0);
+    for item in &data.items {
+        layout.row(
+            &[
+                Cell::fixed(quantity(item), quantity_width, Align::Left),
+                Cell::new(&item.name),
+                Cell::fixed(format!("{:.2}", item.subtotal), amount_width, Align::Right),
+            ],
+            TextStyle::default(),
+        );
+        if item.quantity > 1 {
+            layout.row(
+                &[
+                    Cell::fixed("", quantity_width, Align::Left),
+                    Cell::new(format!("{:.2} x {}", item.price, item.quantity)),
+                ],
+                TextStyle::default(),
+            );
+        }
+    }
+    layout.separator('-');
+
+    // Totals
+    layout.amount("Subtotal", data.subtotal);
+    layout.amount("Tax", data.tax);
+    if data.discount > 0.0 {
+        layout.amount("Discount", -data.discount);
+    }
+    layout.pair(
+        "TOTAL",
+        &format!("{:.2}", data.total),
+        TextStyle {
+            bold: true,
+            ..TextStyle::default()
+        },
+    );
+    layout.pair("Payment", &data.payment_method, TextStyle::default());
+    layout.separator('=');
+
+    // Footer
+    if let Some(fiscal_url) = &data.fiscal_url {
+        layout.centered("Verify this receipt (EFRIS)");
+        layout.qr(fiscal_url);
+    }
+    if let Some(feedback_url) = &data.feedback_url {
+        layout.centered("Tell us how we did");
+        layout.qr(feedback_url);
+    }
+    if let Some(footer) = &data.footer {
+        layout.centered(footer);
+    }
+    layout.centered("Thank you for your visit!");
+
+    layout.finish()
+}
+
+pub fn kitchen_ticket(data: &KitchenTicketData, columns: usize) -> Document {
+    let mut layout = Layout::new(columns);
+
+    // Header
+    layout.heading(&data.station);
+    layout.separator('=');
+
+    // Order info
+    layout.bold(format!("Order: {}", data.order_number));
+    layout.text(format!("Time: {}", format_timestamp(&data.timestamp)));
+    if let Some(table) = &data.table_number {
+        layout.bold(format!("Table: {}", table));
+    }
+    layout.separator('-');
+
+    // Items
+    for item in &data.items {
+        let quantity = format!("{}x", item.quantity);
+        layout.row(
+            &[
+                Cell::fixed(&quantity, quantity.len(), Align::Left),
+                Cell::new(&item.name),
+            ],
+            TextStyle {
+                bold: true,
+                width: 2,
+                height: 2,
+                ..TextStyle::default()
+            },
+        );
+        for modifier in &item.modifiers {
+            layout.indented("+", modifier);
+        }
+        if let Some(notes) = &item.notes {
+            layout.indented("NOTE:", notes);
+        }
+        layout.text("");
+    }
+    layout.separator('=');
+
+    // Order barcode for scanning at the pass
+    if Symbology::Code128.validate(&data.order_number).is_ok() {
+        layout.blocks.push(Block::Barcode {
+            data: data.order_number.clone(),
+            symbology: Symbology::Code128,
+            height: 60,
+            width: 2,
+            hri: Hri::Below,
+            align: Align::Center,
+        });
+    }
+
+    layout.finish()
+}
+
+pub fn shift_report(data: &ReportData, columns: usize) -> Document {
+    let mut layout = Layout::new(columns);
+
+    // Header
+    layout.heading(match data.report_type {
+        ReportType::XReport => "X REPORT",
+        ReportType::ZReport => "Z REPORT",
+    });
+    layout.centered(format!(
+        "Generated: {}",
+        format_timestamp(&data.generated_at)
+    ));
+    layout.separator('=');
+
+    // Shift info
+    let shift = &data.shift;
+    layout.text(format!("Opened: {}", format_timestamp(&shift.opened_at)));
+    layout.text(format!("By: {}", shift.opened_by));
+    if let Some(closed_at) = &shift.closed_at {
+        layout.text(format!("Closed: {}", format_timestamp(closed_at)));
+        layout.text(format!("By: {}", shift.closed_by.as_deref().unwrap_or("")));
+    }
+    layout.amount("Opening Float", shift.opening_float);
+    layout.separator('-');
+
+    // Summary
+    let summary = &data.summary;
+    layout.pair(
+        "Orders",
+        &summary.order_count.to_string(),
+        TextStyle::default(),
+    );
+    layout.amount("Sales", summary.total_sales);
+    layout.amount("Discount", summary.total_discount);
+
+    if let Some(payments) = &summary.payments_by_method {
+        layout.separator('-');
+        layout.text("Payments by Method:");
+        for (method, amount) in payments {
+            let amount = format!("{:.2}", amount);
+            layout.row(
+                &[
+                    Cell::fixed("", 1, Align::Left),
+                    Cell::new(method),
+                    Cell::fixed(&amount, amount.len(), Align::Right),
+                ],
+                TextStyle::default(),
+            );
+        }
+    }
+
+    if let Some(declared) = shift.declared_cash {
+        layout.separator('-');
+        layout.amount("Declared Cash", declared);
+        if let Some(over_short) = shift.over_short {
+            let status = if over_short >= 0.0 { "Over" } else { "Short" };
+            layout.pair(
+                "Over/Short",
+                &format!("{} {:.2}", status, over_short.abs()),
+                TextStyle::default(),
+            );
+        }
+    }
+    layout.separator('=');
+
+    layout.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lines(document: &Document) -> Vec<String> {
+        document
+            .blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Text { content, .. } => Some(content.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn receipt_right_aligns_amounts_to_paper_width() {
+        let data: ReceiptData = serde_json::from_value(json!({
+            "restaurantName": "ChefCloud",
+            "branchName": "Kampala Road",
+            "orderNumber": "A-102",
+            "serviceType": "DINE_IN",
+            "items": [
+                { "name": "Burger", "quantity": 2, "price": 12.5, "subtotal": 25.0 },
+                { "name": "Grilled tilapia with matoke and groundnut sauce", "quantity": 1,
+                  "price": 100.0, "subtotal": 100.0 }
+            ],
+            "subtotal": 125.0,
+            "tax": 4.5,
+            "discount": 0,
+            "total": 129.5,
+            "paymentMethod": "CASH",
+            "timestamp": "2024-05-01T18:30:12.000Z"
+        }))
+        .unwrap();
+
+        for columns in [32, 42, 48] {
+            let lines = lines(&receipt(&data, columns));
+            assert!(lines.contains(&"Date: 2024-05-01 18:30".to_string()));
+            let total = lines.iter().find(|l| l.starts_with("TOTAL")).unwrap();
+            assert_eq!(total.len(), columns);
+            assert!(total.ends_with("129.50"));
+            assert!(!lines.iter().any(|l| l.starts_with("Discount")));
+
+            let burger = lines
+                .iter()
+                .position(|l| l.starts_with("2x Burger"))
+                .unwrap();
+            assert!(lines[burger].ends_with(" 25.00") && lines[burger].len() == columns);
+            assert_eq!(lines[burger + 1], "   12.50 x 2");
+            // The long name wraps under itself, clear of the amount column.
+            let tilapia = lines
+                .iter()
+                .position(|l| l.starts_with("1x Grilled"))
+                .unwrap();
+            assert!(lines[tilapia].ends_with("100.00") && lines[tilapia].len() == columns);
+            assert!(
+                lines[tilapia + 1].starts_with("   ") && lines[tilapia + 1].len() < columns - 7
+            );
+        }
+    }
+
+    #[test]
+    fn kitchen_ticket_lists_modifiers_and_notes() {
+        let data: KitchenTicketData = serde_json::from_value(json!({
+            "orderNumber": "A-102",
+            "tableNumber": "7",
+            "station": "GRILL",
+            "items": [{ "name": "Burger", "quantity": 1, "modifiers": ["No onion"], "notes": "Well done" }],
+            "timestamp": "2024-05-01T18:30:12.000Z"
+        }))
+        .unwrap();
+
+        let document = kitchen_ticket(&data, 32);
+        let lines = lines(&document);
+        assert_eq!(lines[0], "GRILL");
+        assert!(lines.contains(&"Table: 7".to_string()));
+        assert!(lines.contains(&"  + No onion".to_string()));
+        assert!(lines.contains(&"  NOTE: Well done".to_string()));
+        assert!(document.blocks.iter().any(|b| matches!(
+            b,
+            Block::Barcode { data, symbology: Symbology::Code128, .. } if data == "A-102"
+        )));
+    }
+
+    #[test]
+    fn z_report_includes_over_short() {
+        let data: ReportData = serde_json::from_value(json!({
+            "type": "Z_REPORT",
+            "shift": {
+                "openedAt": "2024-05-01T08:00:00.000Z",
+                "closedAt": "2024-05-01T22:00:00.000Z",
+                "openedBy": "Amina",
+                "closedBy": "Brian",
+                "openingFloat": 100,
+                "declaredCash": 950,
+                "overShort": -12.5
+            },
+            "summary": {
+                "orderCount": 42,
+                "totalSales": 1234.5,
+                "totalDiscount": 20,
+                "paymentsByMethod": { "CASH": 862.5, "CARD": 372 }
+            },
+            "generatedAt": "2024-05-01T22:01:00.000Z"
+        }))
+        .unwrap();
+
+        let lines = lines(&shift_report(&data, 48));
+        assert_eq!(lines[0], "Z REPORT");
+        assert!(lines
+            .iter()
+            .any(|l| l.starts_with("  CARD") && l.ends_with("372.00")));
+        assert!(lines.iter().any(|l| l.ends_with("Short 12.50")));
+    }
+}
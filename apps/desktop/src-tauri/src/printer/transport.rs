@@ -0,0 +1,461 @@
+//! Connections to printers over TCP, LPD, USB printer class device files
+//! (`/dev/usb/lp0`) and serial ports (`/dev/ttyUSB0`, `COM3`).
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::config::Transport;
+use super::error::{PrintError, PrintErrorKind};
+use super::lpd::LpdTransport;
+use super::spool::CancelToken;
+
+/// Jobs are written in chunks of this size so a cancelled job stops between
+/// chunks rather than after the whole payload.
+const WRITE_CHUNK: usize = 4096;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    #[default]
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowControl {
+    #[default]
+    None,
+    /// XON/XOFF.
+    Software,
+    /// RTS/CTS.
+    Hardware,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SerialSettings {
+    pub baud_rate: u32,
+    /// 5 to 8.
+    pub data_bits: u8,
+    pub parity: Parity,
+    /// 1 or 2.
+    pub stop_bits: u8,
+    pub flow_control: FlowControl,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        SerialSettings {
+            baud_rate: 9600,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: FlowControl::None,
+        }
+    }
+}
+
+/// Per-printer limits on how long each stage of a send may block.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Timeouts {
+    /// Establishing a TCP connection.
+    pub connect_ms: u64,
+    /// A single write, e.g. while flow control holds the line because the
+    /// printer is out of paper.
+    pub write_ms: u64,
+    /// Waiting for a status reply.
+    pub read_ms: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            connect_ms: 3_000,
+            write_ms: 10_000,
+            read_ms: 1_000,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn connect(&self) -> Duration {
+        Duration::from_millis(self.connect_ms)
+    }
+
+    pub fn write(&self) -> Duration {
+        Duration::from_millis(self.write_ms)
+    }
+
+    pub fn read(&self) -> Duration {
+        Duration::from_millis(self.read_ms)
+    }
+}
+
+/// An open connection to a printer. Reads return status bytes sent back by
+/// the printer.
+pub trait PrinterTransport: Read + Write + Send {
+    /// Bounds how long a read may wait for the printer to answer.
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+    /// Bounds how long a write may wait for the printer to take the data.
+    fn set_write_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+}
+
+impl PrinterTransport for TcpStream {
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, Some(timeout))
+    }
+
+    fn set_write_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, Some(timeout))
+    }
+}
+
+/// USB printer class device. The kernel driver handles the USB side, so the
+/// device is read and written like a file.
+pub struct UsbTransport {
+    file: File,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+}
+
+impl UsbTransport {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(UsbTransport {
+            file,
+            read_timeout: None,
+            write_timeout: None,
+        })
+    }
+}
+
+impl Read for UsbTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(unix)]
+        if let Some(timeout) = self.read_timeout {
+            wait_ready(&self.file, libc::POLLIN, timeout)?;
+        }
+        self.file.read(buf)
+    }
+}
+
+impl Write for UsbTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        #[cfg(unix)]
+        if let Some(timeout) = self.write_timeout {
+            wait_ready(&self.file, libc::POLLOUT, timeout)?;
+        }
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl PrinterTransport for UsbTransport {
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.read_timeout = Some(timeout);
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.write_timeout = Some(timeout);
+        Ok(())
+    }
+}
+
+/// Waits until `file` is ready for `events` (`POLLIN` or `POLLOUT`).
+#[cfg(unix)]
+fn wait_ready(file: &File, events: libc::c_short, timeout: Duration) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut fd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events,
+        revents: 0,
+    };
+    let millis = timeout.as_millis().min(i32::MAX as u128) as libc::c_int;
+    match unsafe { libc::poll(&mut fd, 1, millis) } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "Timed out waiting for the printer",
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub struct SerialTransport {
+    port: Box<dyn serialport::SerialPort>,
+}
+
+impl SerialTransport {
+    pub fn open(
+        path: &str,
+        settings: &SerialSettings,
+        write_timeout: Duration,
+    ) -> io::Result<Self> {
+        let data_bits = match settings.data_bits {
+            5 => serialport::DataBits::Five,
+            6 => serialport::DataBits::Six,
+            7 => serialport::DataBits::Seven,
+            8 => serialport::DataBits::Eight,
+            n => return Err(invalid_setting(format!("Unsupported data bits: {}", n))),
+        };
+        let stop_bits = match settings.stop_bits {
+            1 => serialport::StopBits::One,
+            2 => serialport::StopBits::Two,
+            n => return Err(invalid_setting(format!("Unsupported stop bits: {}", n))),
+        };
+        let parity = match settings.parity {
+            Parity::None => serialport::Parity::None,
+            Parity::Odd => serialport::Parity::Odd,
+            Parity::Even => serialport::Parity::Even,
+        };
+        let flow_control = match settings.flow_control {
+            FlowControl::None => serialport::FlowControl::None,
+            FlowControl::Software => serialport::FlowControl::Software,
+            FlowControl::Hardware => serialport::FlowControl::Hardware,
+        };
+        let port = serialport::new(path, settings.baud_rate)
+            .data_bits(data_bits)
+            .stop_bits(stop_bits)
+            .parity(parity)
+            .flow_control(flow_control)
+            .timeout(write_timeout)
+            .open()?;
+        Ok(SerialTransport { port })
+    }
+}
+
+fn invalid_setting(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl Read for SerialTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.port.read(buf)
+    }
+}
+
+impl Write for SerialTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+impl PrinterTransport for SerialTransport {
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        // serialport has a single timeout for reads and writes, so callers
+        // set the one they need before each stage.
+        self.port.set_timeout(timeout).map_err(io::Error::from)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.port.set_timeout(timeout).map_err(io::Error::from)
+    }
+}
+
+/// Opens a connection for `transport`, bounded by `timeouts`.
+pub fn open(
+    transport: &Transport,
+    timeouts: &Timeouts,
+) -> Result<Box<dyn PrinterTransport>, PrintError> {
+    let result: io::Result<Box<dyn PrinterTransport>> = match transport {
+        Transport::Tcp { host, port } => connect_tcp(host, *port, timeouts)
+            .map(|stream| Box::new(stream) as Box<dyn PrinterTransport>),
+        Transport::Lpd { host, port, queue } => connect_tcp(host, *port, timeouts)
+            .map(|stream| Box::new(LpdTransport::new(stream, queue)) as Box<dyn PrinterTransport>),
+        Transport::Usb { path } => UsbTransport::open(path).map(|usb| {
+            Box::new(UsbTransport {
+                read_timeout: Some(timeouts.read()),
+                write_timeout: Some(timeouts.write()),
+                ..usb
+            }) as Box<dyn PrinterTransport>
+        }),
+        Transport::Serial { path, settings } => {
+            SerialTransport::open(path, settings, timeouts.write())
+                .map(|serial| Box::new(serial) as Box<dyn PrinterTransport>)
+        }
+        Transport::Ipp { uri } => {
+            return Err(PrintError::new(
+                PrintErrorKind::Invalid,
+                format!(
+                    "Printer at {} uses IPP and does not accept raw ESC/POS",
+                    uri
+                ),
+            ))
+        }
+    };
+    result.map_err(|e| PrintError::connect(transport, e))
+}
+
+/// Tries each address `host` resolves to in turn, giving each
+/// `timeouts.connect` to answer.
+fn connect_tcp(host: &str, port: u16, timeouts: &Timeouts) -> io::Result<TcpStream> {
+    let addrs = (host, port).to_socket_addrs().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Cannot resolve {}: {}", host, e),
+        )
+    })?;
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "No addresses found");
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeouts.connect()) {
+            Ok(stream) => {
+                stream.set_write_timeout(Some(timeouts.write()))?;
+                stream.set_read_timeout(Some(timeouts.read()))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Writes `bytes` in chunks, stopping early once `cancel` is set.
+pub fn write_all(
+    stream: &mut dyn PrinterTransport,
+    bytes: &[u8],
+    cancel: &CancelToken,
+) -> Result<(), PrintError> {
+    for chunk in bytes.chunks(WRITE_CHUNK) {
+        if cancel.is_cancelled() {
+            return Err(PrintError::cancelled());
+        }
+        stream
+            .write_all(chunk)
+            .map_err(|e| PrintError::io("Failed to send data to printer", e))?;
+    }
+    stream
+        .flush()
+        .map_err(|e| PrintError::io("Failed to send data to printer", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serialport::SerialPort;
+    use std::net::TcpListener;
+
+    #[test]
+    fn tcp_transport_sends_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let transport = Transport::Tcp {
+            host: "127.0.0.1".to_string(),
+            port,
+        };
+
+        let mut printer = open(&transport, &Timeouts::default()).unwrap();
+        write_all(&mut *printer, b"\x1b@hello", &CancelToken::default()).unwrap();
+        drop(printer);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"\x1b@hello");
+    }
+
+    #[test]
+    fn usb_transport_writes_to_device_file() {
+        let dir = std::env::temp_dir().join(format!("chefcloud-usb-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lp0");
+        std::fs::write(&path, b"").unwrap();
+        let transport = Transport::Usb {
+            path: path.to_string_lossy().into_owned(),
+        };
+
+        let mut printer = open(&transport, &Timeouts::default()).unwrap();
+        printer.set_read_timeout(Duration::from_millis(50)).unwrap();
+        printer.write_all(b"\x1b@hello").unwrap();
+        drop(printer);
+        assert_eq!(std::fs::read(&path).unwrap(), b"\x1b@hello");
+
+        let missing = Transport::Usb {
+            path: dir.join("lp9").to_string_lossy().into_owned(),
+        };
+        let err = open(&missing, &Timeouts::default()).err().unwrap();
+        assert!(err.message.contains("lp9"), "{}", err);
+        assert_eq!(err.kind, PrintErrorKind::Unreachable);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serial_transport_talks_over_a_pty() {
+        let (mut master, slave) = serialport::TTYPort::pair().unwrap();
+        let path = slave.name().unwrap();
+        let transport = Transport::Serial {
+            path,
+            settings: SerialSettings {
+                baud_rate: 19200,
+                ..SerialSettings::default()
+            },
+        };
+
+        let mut printer = open(&transport, &Timeouts::default()).unwrap();
+        printer.write_all(b"\x1b@hello").unwrap();
+        printer.flush().unwrap();
+        let mut received = [0u8; 7];
+        master.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"\x1b@hello");
+
+        // Status replies travel back the other way.
+        master.write_all(&[0x12]).unwrap();
+        printer.set_read_timeout(Duration::from_secs(1)).unwrap();
+        let mut reply = [0u8; 1];
+        printer.read_exact(&mut reply).unwrap();
+        assert_eq!(reply, [0x12]);
+    }
+
+    #[test]
+    fn rejects_unsupported_serial_settings() {
+        let settings = SerialSettings {
+            stop_bits: 3,
+            ..SerialSettings::default()
+        };
+        let err = SerialTransport::open("/dev/null", &settings, Duration::from_secs(1))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn classifies_refused_connections_and_stops_cancelled_writes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let transport = Transport::Tcp {
+            host: "127.0.0.1".to_string(),
+            port,
+        };
+        let mut printer = open(&transport, &Timeouts::default()).unwrap();
+        let cancel = CancelToken::default();
+        cancel.cancel();
+        let err = write_all(&mut *printer, b"\x1b@hello", &cancel).unwrap_err();
+        assert_eq!(err.kind, PrintErrorKind::Cancelled);
+        drop(printer);
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).unwrap();
+        assert!(received.is_empty());
+
+        // Nothing listens on the port once the listener is gone.
+        drop(listener);
+        let err = open(&transport, &Timeouts::default()).err().unwrap();
+        assert_eq!(err.kind, PrintErrorKind::Refused, "{}", err);
+    }
+}
@@ -1,8 +1,370 @@
 import { invoke } from '@tauri-apps/api/tauri';
 
-export async function printReceipt(data: Buffer): Promise<string> {
+export type PrinterRole = 'receipt' | 'kitchen' | 'bar' | 'label' | 'office';
+
+export interface SerialSettings {
+  baudRate?: number;
+  dataBits?: 5 | 6 | 7 | 8;
+  parity?: 'none' | 'odd' | 'even';
+  stopBits?: 1 | 2;
+  flowControl?: 'none' | 'software' | 'hardware';
+}
+
+export type PrinterTransport =
+  | { type: 'tcp'; host: string; port: number }
+  | { type: 'lpd'; host: string; port?: number; queue?: string }
+  | { type: 'usb'; path: string }
+  | ({ type: 'serial'; path: string } & SerialSettings)
+  | { type: 'ipp'; uri: string };
+
+export type CodePage = 'cp437' | 'cp850' | 'cp852' | 'cp858' | 'cp866' | 'cp1252';
+
+export type PrinterLanguage = 'escpos' | 'zpl' | 'tspl' | 'star';
+
+export interface PrinterDefinition {
+  id: string;
+  role: PrinterRole;
+  transport: PrinterTransport;
+  /** Defaults to `escpos`; label printers take `zpl` (Zebra) or `tspl` (TSC). */
+  language?: PrinterLanguage;
+  /** Print head resolution used to lay out labels. Defaults to 203. */
+  dpi?: number;
+  paperWidth: number;
+  /** Characters per line; defaults to 32 on 58mm paper and 48 on 80mm. */
+  columns?: number;
+  codePage: CodePage;
+  codePageFallback: string;
+  /** Defaults to 3000 ms to connect, 10000 ms per write and 1000 ms per status read. */
+  timeouts?: { connectMs?: number; writeMs?: number; readMs?: number };
+}
+
+export async function listPrinters(): Promise<PrinterDefinition[]> {
+  return invoke<PrinterDefinition[]>('list_printers');
+}
+
+/** Printers that stand in for each other, primary first. */
+export interface PrinterGroup {
+  id: string;
+  members: string[];
+}
+
+export interface PrinterConfig {
+  simulate: boolean;
+  /** A printer or group id. */
+  defaultPrinter?: string | null;
+  /** What `print_receipt` does with bytes that fail inspection. Defaults to `warn`. */
+  preflight?: 'off' | 'warn' | 'reject';
+  printers: PrinterDefinition[];
+  groups?: PrinterGroup[];
+}
+
+/** Rejection from the config commands; `fields` name JSON paths such as `printers[0].transport.host`. */
+export interface ConfigError {
+  message: string;
+  fields: { field: string; message: string }[];
+}
+
+export async function getPrinterConfig(): Promise<PrinterConfig> {
+  return invoke<PrinterConfig>('get_printer_config');
+}
+
+/** Validates and saves `~/.chefcloud/printer.json`. Rejects with a `ConfigError`. */
+export async function savePrinterConfig(config: PrinterConfig): Promise<PrinterConfig> {
+  return invoke<PrinterConfig>('save_printer_config', { config });
+}
+
+export async function testPrinterConnection(printer: PrinterDefinition): Promise<string> {
+  return invoke<string>('test_printer_connection', { printer });
+}
+
+export interface DiscoveredPrinter {
+  host: string;
+  openPorts: number[];
+  escpos: boolean;
+  ipp: boolean;
+  model?: string;
+  name?: string;
+  /** Ready to save into the printer registry. */
+  printer?: PrinterDefinition;
+}
+
+/** Scans the local subnet (or `subnet`, e.g. `192.168.1.0/24`) and mDNS for printers. */
+export async function discoverPrinters(subnet?: string): Promise<DiscoveredPrinter[]> {
+  return invoke<DiscoveredPrinter[]>('discover_printers', { subnet });
+}
+
+/** Rejection from the print commands. */
+export interface PrintError {
+  kind: 'unreachable' | 'timeout' | 'refused' | 'io' | 'notReady' | 'cancelled' | 'invalid';
+  message: string;
+}
+
+/** Prints raw ESC/POS and keeps it in the print history. Rejects with a `PrintError`. */
+export async function printReceipt(
+  data: Buffer,
+  printerId?: string,
+  orderId?: string,
+): Promise<string> {
+  const base64Data = data.toString('base64');
+  return invoke<string>('print_receipt', { base64Data, printerId, orderId });
+}
+
+export interface PrintHistoryEntry {
+  jobId: string;
+  kind: 'raw' | 'receipt';
+  orderId: string | null;
+  printerId: string;
+  createdAt: number;
+  updatedAt: number;
+  outcome: 'pending' | 'printed' | 'failed' | 'cancelled';
+  error: string | null;
+  /** Set on reprints: the job id of the original receipt. */
+  reprintOf: string | null;
+  operator: string | null;
+  reason: string | null;
+  /** Times the receipt has been reprinted. */
+  reprints: number;
+}
+
+/** Receipts printed on this terminal, newest first. */
+export async function listPrintHistory(orderId?: string, limit?: number): Promise<PrintHistoryEntry[]> {
+  return invoke<PrintHistoryEntry[]>('list_print_history', { orderId, limit });
+}
+
+/** Prints a receipt from the history again with a "COPY - REPRINT" banner. */
+export async function reprintJob(jobId: string, operator: string, reason?: string): Promise<string> {
+  return invoke<string>('reprint_job', { jobId, operator, reason });
+}
+
+export interface PrintIssue {
+  offset: number;
+  message: string;
+}
+
+export interface PrintInspection {
+  /** Decoded commands, each with its byte `offset` and an `op` name. */
+  commands: ({ offset: number; op: string } & Record<string, unknown>)[];
+  issues: PrintIssue[];
+  preview: string;
+}
+
+/** Decodes raw ESC/POS without printing it and lists what the pre-flight check would flag. */
+export async function inspectPrintBytes(data: Buffer, printerId?: string): Promise<PrintInspection> {
   const base64Data = data.toString('base64');
-  return invoke<string>('print_receipt', { base64Data });
+  return invoke<PrintInspection>('inspect_print_bytes', { base64Data, printerId });
+}
+
+/** Payload of the `printer-failover` event. */
+export interface FailoverEvent {
+  at: number;
+  jobId: string;
+  from: string;
+  to: string;
+  reason: string;
+}
+
+export async function listFailoverEvents(since?: number): Promise<FailoverEvent[]> {
+  return invoke<FailoverEvent[]>('list_failover_events', { since });
+}
+
+/** Positions and sizes are in millimetres from the label's top-left corner. */
+export type LabelElement =
+  | { type: 'text'; x: number; y: number; content: string; height?: number }
+  | {
+      type: 'barcode';
+      x: number;
+      y: number;
+      data: string;
+      symbology?: 'code128' | 'ean13' | 'code39';
+      height?: number;
+      /** Narrow bar width in dots. */
+      module?: number;
+      hri?: boolean;
+    }
+  | { type: 'qr'; x: number; y: number; data: string; cell?: number }
+  | { type: 'box'; x: number; y: number; width: number; height: number; thickness?: number };
+
+export interface Label {
+  width: number;
+  height: number;
+  /** Gap between labels on the roll, in millimetres. Defaults to 3. */
+  gap?: number;
+  copies?: number;
+  elements: LabelElement[];
+}
+
+/** Prints a label on a ZPL or TSPL printer, by default the first `label` printer. */
+export async function printLabel(label: Label, printerId?: string): Promise<string> {
+  return invoke<string>('print_label', { label, printerId });
+}
+
+/** Cached from the server; see `syncStationRoutes`. */
+export interface RoutingTable {
+  /** Menu item id → station. Wins over the item's category. */
+  items: Record<string, string>;
+  /** Menu category id → station. */
+  categories: Record<string, string>;
+  /** Station → printer or printer group id. Unmapped stations, and stations whose printer is not configured, use the first kitchen printer. */
+  stations: Record<string, string>;
+  defaultStation?: string;
+  syncedAt?: number;
+}
+
+export interface KitchenOrderItem {
+  itemId?: string;
+  categoryId?: string;
+  name: string;
+  quantity: number;
+  modifiers?: string[];
+  notes?: string;
+}
+
+export interface KitchenOrder {
+  orderNumber: string;
+  tableNumber?: string;
+  items: KitchenOrderItem[];
+  timestamp: string;
+}
+
+export interface StationPrint {
+  station: string;
+  printerId: string | null;
+  /** The station's printer when it is not configured; the ticket went to the first kitchen printer. */
+  missingPrinter: string | null;
+  message: string | null;
+  /** Set when the ticket could not be printed; the job stays queued for retry. */
+  error: string | null;
+}
+
+/** Stores the routing table fetched from the server so tickets route correctly offline. */
+export async function syncStationRoutes(table: RoutingTable): Promise<RoutingTable> {
+  return invoke<RoutingTable>('sync_station_routes', { table });
+}
+
+export async function getStationRoutes(): Promise<RoutingTable> {
+  return invoke<RoutingTable>('get_station_routes');
+}
+
+/** Prints one kitchen ticket per station for `order`. */
+export async function printKitchenOrder(order: KitchenOrder): Promise<StationPrint[]> {
+  return invoke<StationPrint[]>('print_kitchen_order', { order });
+}
+
+export interface TextStyle {
+  align?: 'left' | 'center' | 'right';
+  bold?: boolean;
+  underline?: 'none' | 'single' | 'double';
+  invert?: boolean;
+  width?: number;
+  height?: number;
+}
+
+/** A column of a `row`. Cells without a `width` share the rest of the line. */
+export interface RowCell {
+  content: string;
+  width?: number;
+  align?: 'left' | 'center' | 'right';
+  /** `wrap` (default) continues on the next lines; `ellipsis` cuts to one line. */
+  overflow?: 'wrap' | 'ellipsis';
+}
+
+export type DocumentBlock =
+  | ({ type: 'text'; content: string } & TextStyle)
+  /** Cells across the printer's line, e.g. an item name and a right-aligned amount. */
+  | ({ type: 'row'; cells: RowCell[] } & Omit<TextStyle, 'align'>)
+  | { type: 'separator'; ch?: string }
+  | { type: 'feed'; lines?: number }
+  | { type: 'cut'; mode?: 'full' | 'partial' }
+  | {
+      type: 'qr';
+      data: string;
+      size?: number;
+      errorCorrection?: 'L' | 'M' | 'Q' | 'H';
+      align?: 'left' | 'center' | 'right';
+    }
+  | {
+      type: 'barcode';
+      /**
+       * Code 128 data is plain text unless it starts with `{`, in which case it is
+       * ESC/POS code set syntax: `{A`, `{B`, `{C`, and `{{` for a literal brace.
+       */
+      data: string;
+      symbology?: 'code128' | 'ean13' | 'code39';
+      height?: number;
+      width?: number;
+      hri?: 'none' | 'above' | 'below' | 'both';
+      align?: 'left' | 'center' | 'right';
+    };
+
+export interface PrintDocument {
+  blocks: DocumentBlock[];
+}
+
+export async function printDocument(document: PrintDocument, printerId?: string): Promise<string> {
+  return invoke<string>('print_document', { document, printerId });
+}
+
+export type PreviewSource =
+  | { type: 'document'; document: PrintDocument }
+  /** Receipt template fields, as taken by `print_receipt_data`. */
+  | { type: 'receipt'; data: Record<string, unknown> }
+  /** Raw ESC/POS, base64-encoded. */
+  | { type: 'escPos'; data: string };
+
+export interface ReceiptImage {
+  /** Printer dots: 384 on 58mm paper, 576 on 80mm. */
+  width: number;
+  height: number;
+  /** Base64-encoded PNG. */
+  png: string;
+}
+
+/** Renders a receipt as a PNG, by default for the first `receipt` printer. */
+export async function previewReceipt(source: PreviewSource, printerId?: string): Promise<ReceiptImage> {
+  return invoke<ReceiptImage>('preview_receipt', { source, printerId });
+}
+
+export interface IppJob {
+  id: number;
+  state:
+    | 'pending'
+    | 'pendingHeld'
+    | 'processing'
+    | 'processingStopped'
+    | 'canceled'
+    | 'aborted'
+    | 'completed'
+    | 'unknown';
+  stateReasons: string[];
+}
+
+/** Prints a PDF or plain-text document on an IPP office printer. */
+export async function printOfficeDocument(
+  data: Buffer,
+  printerId?: string,
+  jobName?: string,
+): Promise<IppJob> {
+  const base64Data = data.toString('base64');
+  return invoke<IppJob>('print_office_document', { base64Data, printerId, jobName });
+}
+
+export async function getOfficeJob(jobId: number, printerId?: string): Promise<IppJob> {
+  return invoke<IppJob>('get_office_job', { printerId, jobId });
+}
+
+export interface SimulatedPrint {
+  id: string;
+  printerId: string;
+  createdAt: number;
+  size: number;
+  binPath: string;
+  txtPath: string;
+  preview: string;
+}
+
+/** Jobs captured in simulate mode, newest first. */
+export async function listSimulatedPrints(limit?: number): Promise<SimulatedPrint[]> {
+  return invoke<SimulatedPrint[]>('list_simulated_prints', { limit });
 }
 
 export async function testPrint(): Promise<void> {
@@ -67,6 +429,6 @@ export async function testPrint(): Promise<void> {
     alert(`Print successful: ${result}`);
   } catch (error) {
     console.error('Print failed:', error);
-    alert(`Print failed: ${error}`);
+    alert(`Print failed: ${(error as PrintError).message ?? error}`);
   }
 }
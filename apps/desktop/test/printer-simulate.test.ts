@@ -5,9 +5,9 @@ describe('Printer simulate mode', () => {
     process.env.PRINTER_SIMULATE = 'true';
   });
 
-  it('should log byte length in simulate mode', () => {
+  it('should report byte length in simulate mode', () => {
     // This test verifies the expected behavior when simulate=true
-    // The Rust command should log "PRINT BYTES n" where n is the byte count
+    // The Rust command should save the bytes under print-sim/ and report the byte count
 
     // Create test data
     const testData = Buffer.from('Test receipt data');
@@ -15,7 +15,7 @@ describe('Printer simulate mode', () => {
 
     // In simulate mode, the command should:
     // 1. Decode the base64 data
-    // 2. Log "PRINT BYTES <count>" to stdout
+    // 2. Save the bytes and a text preview under {appDataDir}/print-sim/
     // 3. Return success message with byte count
 
     expect(expectedByteCount).toBeGreaterThan(0);